@@ -26,6 +26,7 @@ pub type Result<T, E = Error> = ::std::result::Result<T, E>;
 
 impl Metadata {
     /// Attempt to deserialize an instance from a string.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self> {
         Ok(quick_xml::de::from_str(s)?)
     }
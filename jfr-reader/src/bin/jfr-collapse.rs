@@ -0,0 +1,60 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use jfr_reader::{
+    collapsed::{CollapsedStacks, Weight},
+    error::Result,
+    recording::Recording,
+};
+use std::{io::BufWriter, path::PathBuf};
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: {} [--event NAME]... [--weight count|duration|FIELD] [--lines] INPUT",
+        std::env::current_exe().unwrap().display()
+    );
+    std::process::exit(1);
+}
+
+fn main() -> Result<()> {
+    let mut event_names = vec![];
+    let mut weight = Weight::Count;
+    let mut line_numbers = false;
+    let mut input = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--event" => event_names.push(args.next().unwrap_or_else(|| usage())),
+            "--weight" => {
+                weight = match args.next().unwrap_or_else(|| usage()).as_str() {
+                    "count" => Weight::Count,
+                    "duration" => Weight::Duration,
+                    field => Weight::Field(field.to_string()),
+                }
+            }
+            "--lines" => line_numbers = true,
+            "-h" | "--help" => usage(),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => usage(),
+        }
+    }
+
+    let input = input.unwrap_or_else(|| usage());
+
+    let mut stacks = CollapsedStacks::new(weight);
+    stacks.set_line_numbers(line_numbers);
+    for name in event_names {
+        stacks.add_event_name(name);
+    }
+
+    stacks.add_recording(&Recording::from_path(input)?)?;
+    stacks.write(&mut BufWriter::new(std::io::stdout().lock()))?;
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use chrono::{DateTime, Utc};
+use jfr_reader::{filter::RecordingFilter, recording::Recording};
+use std::{io::BufWriter, path::PathBuf};
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: {} [--event NAME]... [--start RFC3339] [--end RFC3339] INPUT OUTPUT",
+        std::env::current_exe().unwrap().display()
+    );
+    std::process::exit(1);
+}
+
+fn parse_time(value: Option<String>) -> DateTime<Utc> {
+    let value = value.unwrap_or_else(|| usage());
+
+    match DateTime::parse_from_rfc3339(&value) {
+        Ok(t) => t.with_timezone(&Utc),
+        Err(e) => {
+            eprintln!("invalid time {}: {}", value, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut filter = RecordingFilter::new();
+    let mut paths = vec![];
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--event" => filter.add_event_name(args.next().unwrap_or_else(|| usage())),
+            "--start" => filter.set_start(Some(parse_time(args.next()))),
+            "--end" => filter.set_end(Some(parse_time(args.next()))),
+            "-h" | "--help" => usage(),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [input, output] = <[PathBuf; 2]>::try_from(paths).unwrap_or_else(|_| usage());
+
+    let recording = Recording::from_path(input)?;
+    let mut dest = BufWriter::new(std::fs::File::create(output)?);
+
+    let stats = filter.filter_recording(&recording, &mut dest)?;
+
+    eprintln!(
+        "wrote {} of {} events in {} of {} chunks",
+        stats.events_written, stats.events_read, stats.chunks_written, stats.chunks_read
+    );
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use jfr_reader::{error::Result, pprof::ProfileBuilder, recording::Recording};
+use std::io::BufWriter;
+
+fn main() -> Result<()> {
+    let args = std::env::args_os().collect::<Vec<_>>();
+
+    if args.len() != 3 {
+        eprintln!(
+            "Usage: {} path/to/recording.jfr path/to/profile.pb.gz",
+            std::env::current_exe().unwrap().display()
+        );
+        std::process::exit(1);
+    }
+
+    let recording = Recording::from_path(&args[1])?;
+
+    let mut builder = ProfileBuilder::new();
+    builder.add_recording(&recording)?;
+
+    let dest = BufWriter::new(std::fs::File::create(&args[2])?);
+    builder.write_gzip(dest)?;
+
+    Ok(())
+}
@@ -0,0 +1,219 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A replacement for the JDK's `jfr` tool.
+
+#[cfg(feature = "json")]
+use jfr_reader::json::{EventSerializer, JsonWriter};
+use jfr_reader::{
+    chunk_event::ChunkEvent,
+    error::Result,
+    print::{write_chunks, write_class, write_summary, EventPrinter},
+    recording::{Recording, RecordingChunk, RecordingEvent},
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+fn usage() -> ! {
+    let exe = std::env::current_exe().unwrap();
+    let exe = exe.display();
+
+    eprintln!("Usage: {} summary [--per-chunk] FILE", exe);
+    #[cfg(feature = "json")]
+    eprintln!(
+        "       {} print [--json] [--events NAME[,NAME]...] [--stack-depth N] FILE",
+        exe
+    );
+    #[cfg(not(feature = "json"))]
+    eprintln!(
+        "       {} print [--events NAME[,NAME]...] [--stack-depth N] FILE",
+        exe
+    );
+    eprintln!("       {} metadata FILE", exe);
+    eprintln!("       {} chunks FILE", exe);
+    std::process::exit(1);
+}
+
+/// Obtain the events in a chunk to print, sorted by start time.
+fn chunk_events<'a>(
+    chunk: &RecordingChunk<'a>,
+    event_names: &[String],
+) -> Result<Vec<RecordingEvent<'a>>> {
+    let resolver = chunk.resolver();
+
+    // Names match either the full event name or its name without the
+    // package, like the JDK.
+    let event_ids = if event_names.is_empty() {
+        None
+    } else {
+        Some(
+            resolver
+                .class_ids_and_names()
+                .filter(|(_, name)| {
+                    let simple = name.rsplit('.').next().unwrap_or_default();
+                    event_names.iter().any(|n| n == name || n == simple)
+                })
+                .map(|(id, _)| id)
+                .collect::<FxHashSet<_>>(),
+        )
+    };
+
+    if matches!(&event_ids, Some(ids) if ids.is_empty()) {
+        return Ok(vec![]);
+    }
+
+    // Events are buffered by the JVM and aren't written in order. Sort
+    // them by start time, like the JDK.
+    let mut events = chunk
+        .iter_events()
+        .filter(|event| match (event, &event_ids) {
+            (Ok(event), Some(ids)) => ids.contains(&event.record().header.event_type),
+            _ => true,
+        })
+        .collect::<Result<Vec<_>>>()?;
+    events.sort_by_cached_key(|event| event.start_ticks().unwrap_or_default());
+
+    Ok(events)
+}
+
+fn command_print(
+    recording: &Recording,
+    dest: &mut impl Write,
+    event_names: &[String],
+    stack_depth: usize,
+) -> Result<()> {
+    for chunk in recording.iter_chunks() {
+        let chunk = chunk?;
+        let events = chunk_events(&chunk, event_names)?;
+
+        if events.is_empty() {
+            continue;
+        }
+
+        let mut printer = EventPrinter::new(chunk.resolver())?;
+        printer.set_stack_depth(stack_depth);
+
+        for event in events {
+            printer.write_event(dest, &event)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+fn command_print_json(
+    recording: &Recording,
+    dest: &mut impl Write,
+    event_names: &[String],
+    stack_depth: usize,
+) -> Result<()> {
+    let mut writer = JsonWriter::new(dest)?;
+
+    for chunk in recording.iter_chunks() {
+        let chunk = chunk?;
+        let events = chunk_events(&chunk, event_names)?;
+
+        if events.is_empty() {
+            continue;
+        }
+
+        let mut serializer = EventSerializer::new(chunk.resolver())?;
+        serializer.set_stack_depth(Some(stack_depth));
+
+        for event in events {
+            writer.write_event(&serializer.event(&event)?)?;
+        }
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+fn command_metadata(recording: &Recording, dest: &mut impl Write) -> Result<()> {
+    // Chunks usually share metadata. Only print each class once.
+    let mut seen = FxHashMap::default();
+
+    for chunk in recording.iter_chunks() {
+        let chunk = chunk?;
+        let resolver = chunk.resolver();
+
+        let mut classes = resolver
+            .class_ids_and_names()
+            .filter(|(_, name)| !seen.contains_key(*name))
+            .collect::<Vec<_>>();
+        classes.sort_by(|a, b| a.1.cmp(b.1));
+
+        for (id, name) in classes {
+            if let Some(class) = resolver.get_class(id) {
+                write_class(dest, resolver, class)?;
+            }
+            seen.insert(name.to_string(), id);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_else(|| usage());
+
+    let mut per_chunk = false;
+    #[cfg(feature = "json")]
+    let mut json = false;
+    let mut event_names = vec![];
+    let mut stack_depth = EventPrinter::DEFAULT_STACK_DEPTH;
+    let mut input = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--per-chunk" if command == "summary" => per_chunk = true,
+            "--events" if command == "print" => event_names.extend(
+                args.next()
+                    .unwrap_or_else(|| usage())
+                    .split(',')
+                    .map(|s| s.to_string()),
+            ),
+            #[cfg(feature = "json")]
+            "--json" if command == "print" => json = true,
+            "--stack-depth" if command == "print" => {
+                stack_depth = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "-h" | "--help" => usage(),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => usage(),
+        }
+    }
+
+    let input = input.unwrap_or_else(|| usage());
+    let recording = Recording::from_path(input)?;
+
+    let mut dest = BufWriter::new(std::io::stdout().lock());
+
+    match command.as_str() {
+        "summary" => write_summary(&mut dest, &recording, per_chunk)?,
+        #[cfg(feature = "json")]
+        "print" if json => command_print_json(&recording, &mut dest, &event_names, stack_depth)?,
+        "print" => command_print(&recording, &mut dest, &event_names, stack_depth)?,
+        "metadata" => command_metadata(&recording, &mut dest)?,
+        "chunks" => write_chunks(&mut dest, &recording)?,
+        _ => usage(),
+    }
+
+    dest.flush()?;
+
+    Ok(())
+}
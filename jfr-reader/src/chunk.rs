@@ -84,10 +84,22 @@ impl ChunkHeader {
     /// Data after this offset will be events data.
     pub const HEADER_SIZE: u64 = 68;
 
+    /// Offset within the header of the byte holding the file state.
+    pub const FILE_STATE_OFFSET: u64 = 64;
+
+    /// File state value indicating the header is being updated.
+    pub const FILE_STATE_UPDATING: u8 = 255;
+
+    /// Flag indicating integers are LEB-128 compressed.
+    pub const FLAG_COMPRESSED_INTEGERS: u8 = 1;
+
+    /// Flag indicating this is the final chunk in a recording.
+    pub const FLAG_FINAL_CHUNK: u8 = 1 << 1;
+
     /// Parse a chunk header from an input slice.
     ///
     /// Input must be at least [Self::HEADER_SIZE] in length.
-    pub fn parse(s: &[u8]) -> ParseResult<Self> {
+    pub fn parse(s: &[u8]) -> ParseResult<'_, Self> {
         let (s, _) = tag(MAGIC)(s)?;
         let (s, major) = be_u16(s)?;
         let (s, minor) = be_u16(s)?;
@@ -116,6 +128,54 @@ impl ChunkHeader {
             },
         ))
     }
+
+    /// Serialize this header to bytes.
+    ///
+    /// This is the inverse of [Self::parse].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(Self::HEADER_SIZE as usize);
+
+        res.extend_from_slice(&MAGIC);
+        res.extend_from_slice(&self.major.to_be_bytes());
+        res.extend_from_slice(&self.minor.to_be_bytes());
+        res.extend_from_slice(&self.chunk_size.to_be_bytes());
+        res.extend_from_slice(&self.constant_pool_position.to_be_bytes());
+        res.extend_from_slice(&self.metadata_position.to_be_bytes());
+        res.extend_from_slice(&self.nanoseconds_since_epoch.to_be_bytes());
+        res.extend_from_slice(&self.duration_nanoseconds.to_be_bytes());
+        res.extend_from_slice(&self.start_ticks.to_be_bytes());
+        res.extend_from_slice(&self.ticks_per_second.to_be_bytes());
+        res.extend_from_slice(&self.state_and_flags.to_be_bytes());
+
+        res
+    }
+
+    /// The file state byte from [Self::state_and_flags].
+    pub fn file_state(&self) -> u8 {
+        (self.state_and_flags >> 24) as u8
+    }
+
+    /// The flags byte from [Self::state_and_flags].
+    pub fn flags(&self) -> u8 {
+        self.state_and_flags as u8
+    }
+
+    /// Whether the chunk is finished and won't be written to any more.
+    pub fn is_finished(&self) -> bool {
+        self.file_state() == 0
+    }
+
+    /// Whether the header is in the process of being updated.
+    ///
+    /// Other header fields may not be consistent when this is true.
+    pub fn is_updating(&self) -> bool {
+        self.file_state() == Self::FILE_STATE_UPDATING
+    }
+
+    /// Whether this is the final chunk in a recording.
+    pub fn is_final_chunk(&self) -> bool {
+        self.flags() & Self::FLAG_FINAL_CHUNK != 0
+    }
 }
 
 /// Describes common properties of entities that can read JFR chunks.
@@ -261,10 +321,118 @@ impl<'a> SliceReader<'a> {
         self.data.len()
     }
 
+    /// The raw data for this chunk, inclusive of the header.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Iterate event records in this chunk.
+    ///
+    /// This is like [ChunkReader::iter_event_records] except the returned
+    /// iterator only borrows the chunk data and not this instance.
+    ///
+    /// Iteration stops after the first error.
+    pub fn event_records(&self) -> impl Iterator<Item = Result<EventRecord<'a>>> + 'a {
+        self.event_records_from(ChunkHeader::HEADER_SIZE as _)
+    }
+
+    /// Iterate event records in this chunk starting at a chunk offset.
+    ///
+    /// The offset must be the start of an event record. Offsets beyond the
+    /// end of the chunk yield no records.
+    ///
+    /// Iteration stops after the first error.
+    pub fn event_records_from(
+        &self,
+        offset: usize,
+    ) -> impl Iterator<Item = Result<EventRecord<'a>>> + 'a {
+        let mut events_data = self.data.get(offset..).unwrap_or_default();
+
+        std::iter::repeat(()).map_while(move |_| {
+            if events_data.is_empty() {
+                None
+            } else {
+                match EventRecord::parse(events_data) {
+                    Ok((remaining, record)) => {
+                        events_data = remaining;
+
+                        Some(Ok(record))
+                    }
+                    Err(err) => {
+                        events_data = &[];
+
+                        Some(Err(err.into()))
+                    }
+                }
+            }
+        })
+    }
+
+    /// Iterate constant pool events in this chunk.
+    ///
+    /// This is like [ChunkReader::iter_constant_pool_events] except the returned
+    /// iterator only borrows the chunk data and not this instance.
+    ///
+    /// Iteration stops after the first error.
+    pub fn constant_pool_events(&self) -> impl Iterator<Item = Result<ConstantPoolEvent<'a>>> + 'a {
+        let data = self.data;
+        let mut offset = 0;
+        let mut delta = self.header.constant_pool_position as i64;
+
+        std::iter::repeat(()).map_while(move |_| {
+            if delta == 0 {
+                None
+            } else {
+                offset += delta;
+
+                match Self::parse_constant_pool_event(data, offset as _) {
+                    Ok((_, cp)) => {
+                        delta = cp.header.delta;
+
+                        Some(Ok(cp))
+                    }
+                    Err(err) => {
+                        delta = 0;
+
+                        Some(Err(err.into()))
+                    }
+                }
+            }
+        })
+    }
+
+    /// Obtain an [EventResolver] whose lifetime is only bound to the chunk data.
+    ///
+    /// [ChunkReader::resolver] borrows this instance for the lifetime of the
+    /// returned resolver. This variant doesn't, which allows the resolver to
+    /// outlive this instance or be stored alongside it.
+    pub fn data_resolver(&self) -> Result<EventResolver<'a>> {
+        let constant_pools = self.constant_pool_events().collect::<Result<Vec<_>>>()?;
+
+        self.data_resolver_with_constant_pools(constant_pools.into_iter())
+    }
+
+    /// Obtain an [EventResolver] using constant pool events located by the caller.
+    ///
+    /// This is like [Self::data_resolver] except the chain of constant pool
+    /// events isn't walked. Callers following a chunk that is still being
+    /// written can use this to avoid parsing the same constant pool events
+    /// after every flush.
+    pub fn data_resolver_with_constant_pools(
+        &self,
+        constant_pools: impl Iterator<Item = ConstantPoolEvent<'a>>,
+    ) -> Result<EventResolver<'a>> {
+        let (_, metadata) = Metadata::parse(self.metadata_event_data)?;
+
+        EventResolver::new(&self.header, metadata, constant_pools)
+    }
+
     /// Attempt to parse a constant pool event at a given chunk offset.
-    fn parse_constant_pool_event(&self, offset: usize) -> ParseResult<ConstantPoolEvent<'a>> {
-        let (event_data, _) =
-            context("resolving content pool event data", take(offset))(self.data)?;
+    pub(crate) fn parse_constant_pool_event(
+        data: &'a [u8],
+        offset: usize,
+    ) -> ParseResult<'a, ConstantPoolEvent<'a>> {
+        let (event_data, _) = context("resolving content pool event data", take(offset))(data)?;
 
         let (remaining, event) =
             context("resolving constant pool event", ConstantPoolEvent::parse)(event_data)?;
@@ -274,11 +442,11 @@ impl<'a> SliceReader<'a> {
 }
 
 impl<'a, 'reader: 'a> ChunkReader<'a, 'reader> for SliceReader<'a> {
-    fn header(&'reader self) -> &ChunkHeader {
+    fn header(&'reader self) -> &'reader ChunkHeader {
         &self.header
     }
 
-    fn metadata_header(&'reader self) -> &MetadataHeader {
+    fn metadata_header(&'reader self) -> &'reader MetadataHeader {
         &self.metadata_header
     }
 
@@ -296,23 +464,7 @@ impl<'a, 'reader: 'a> ChunkReader<'a, 'reader> for SliceReader<'a> {
     fn iter_event_records(
         &'reader self,
     ) -> Box<dyn Iterator<Item = Result<EventRecord<'a>>> + 'reader> {
-        // Taking slice directly should be safe since we did parse it.
-        let mut events_data = &self.data[ChunkHeader::HEADER_SIZE as _..];
-
-        Box::new(std::iter::repeat(()).map_while(move |_| {
-            if events_data.is_empty() {
-                None
-            } else {
-                match EventRecord::parse(events_data) {
-                    Ok((remaining, record)) => {
-                        events_data = remaining;
-
-                        Some(Ok(record))
-                    }
-                    Err(err) => Some(Err(err.into())),
-                }
-            }
-        }))
+        Box::new(self.event_records())
     }
 
     /// Iterate constant pool events in this chunk.
@@ -322,25 +474,7 @@ impl<'a, 'reader: 'a> ChunkReader<'a, 'reader> for SliceReader<'a> {
     fn iter_constant_pool_events(
         &'reader self,
     ) -> Box<dyn Iterator<Item = Result<ConstantPoolEvent<'a>>> + 'reader> {
-        let mut offset = 0;
-        let mut delta = self.header.constant_pool_position as i64;
-
-        Box::new(std::iter::repeat(()).map_while(move |_| {
-            if delta == 0 {
-                None
-            } else {
-                offset += delta;
-
-                match self.parse_constant_pool_event(offset as _) {
-                    Ok((_, cp)) => {
-                        delta = cp.header.delta;
-
-                        Some(Ok(cp))
-                    }
-                    Err(err) => Some(Err(err.into())),
-                }
-            }
-        }))
+        Box::new(self.constant_pool_events())
     }
 }
 
@@ -385,4 +519,17 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn header_roundtrip() {
+        let header = HEADER_HEX
+            .bytes()
+            .filter(|x| !x.is_ascii_whitespace())
+            .collect::<Vec<_>>();
+
+        let raw = hex::decode(header).unwrap();
+        let (_, header) = ChunkHeader::parse(&raw).unwrap();
+
+        assert_eq!(header.to_bytes(), raw);
+    }
 }
@@ -33,7 +33,7 @@ pub struct EventHeader {
 }
 
 impl EventHeader {
-    pub fn parse(s: &[u8]) -> ParseResult<Self> {
+    pub fn parse(s: &[u8]) -> ParseResult<'_, Self> {
         let (s, size) = leb128_i32(s)?;
         let (s, event_type) = leb128_i64(s)?;
 
@@ -107,7 +107,7 @@ impl<'a> EventRecord<'a> {
     ///
     /// Will ensure the declared space for the event is available. But does not
     /// parse event fields data.
-    pub fn parse(s: &'a [u8]) -> ParseResult<Self> {
+    pub fn parse(s: &'a [u8]) -> ParseResult<'a, Self> {
         let (after_header, header) = context("parsing event header", EventHeader::parse)(s)?;
 
         let header_size = s.len() - after_header.len();
@@ -169,7 +169,7 @@ impl<'a> ChunkEvent<'a> for EventRecord<'a> {
     }
 
     fn event_data(&self) -> Result<&'a [u8]> {
-        Ok(&self.event_data)
+        Ok(self.event_data)
     }
 
     fn fields_data(&self) -> Result<&'a [u8]> {
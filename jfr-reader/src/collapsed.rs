@@ -0,0 +1,368 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Collapsed stack output for flame graphs.
+//!
+//! [CollapsedStacks] aggregates the stack traces of events having a
+//! `stackTrace` field and emits them in the *collapsed* text format consumed
+//! by Brendan Gregg's `flamegraph.pl` and compatible tools. Each line holds
+//! the `;` delimited frames of a stack, root first, followed by a space and
+//! the stack's weight.
+//!
+//! Frames are named `package.Class.method`, optionally suffixed with
+//! `:<line>`.
+
+use crate::{
+    error::Result,
+    recording::{Recording, RecordingChunk},
+    stack::{frame_line_number, resolve_method, stack_frames},
+    value::Value,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::{collections::BTreeMap, io::Write};
+
+/// How stacks are weighted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Weight {
+    /// Each event counts as 1.
+    Count,
+    /// The `duration` field of the event, in nanoseconds.
+    Duration,
+    /// The value of a named integer field of the event, e.g. `weight` or
+    /// `allocationSize`.
+    Field(String),
+}
+
+/// Aggregates event stack traces into collapsed stacks.
+pub struct CollapsedStacks {
+    event_names: Option<FxHashSet<String>>,
+    weight: Weight,
+    line_numbers: bool,
+    stacks: BTreeMap<String, u64>,
+}
+
+impl CollapsedStacks {
+    /// Construct an instance using the given weighting.
+    ///
+    /// By default all events having a `stackTrace` field are considered.
+    pub fn new(weight: Weight) -> Self {
+        Self {
+            event_names: None,
+            weight,
+            line_numbers: false,
+            stacks: BTreeMap::new(),
+        }
+    }
+
+    /// Only consider events with the given class name, e.g. `jdk.ExecutionSample`.
+    pub fn add_event_name(&mut self, name: impl ToString) {
+        self.event_names
+            .get_or_insert_with(Default::default)
+            .insert(name.to_string());
+    }
+
+    /// Set whether frame names include line numbers.
+    pub fn set_line_numbers(&mut self, v: bool) {
+        self.line_numbers = v;
+    }
+
+    /// Render a `jdk.types.StackTrace` as collapsed frames, root first.
+    fn collapse(
+        &self,
+        stack: &Value,
+        constants: &crate::resolver::ConstantPoolValues,
+        methods: &mut FxHashMap<i64, String>,
+    ) -> String {
+        let mut frames = vec![];
+
+        for frame in stack_frames(stack, constants) {
+            let method = frame.field("method");
+
+            let cached = match method {
+                Some(Value::ConstantPool { constant_index, .. }) => methods.get(constant_index),
+                _ => None,
+            };
+
+            let mut name = if let Some(name) = cached {
+                name.clone()
+            } else {
+                let name = method
+                    .and_then(|m| resolve_method(m, constants))
+                    .map(|m| m.qualified_name())
+                    .unwrap_or_else(|| "<unknown>".to_string());
+
+                if let Some(Value::ConstantPool { constant_index, .. }) = method {
+                    methods.insert(*constant_index, name.clone());
+                }
+
+                name
+            };
+
+            if self.line_numbers {
+                if let Some(line) = frame_line_number(frame) {
+                    name.push_str(&format!(":{}", line));
+                }
+            }
+
+            frames.push(name);
+        }
+
+        frames.reverse();
+        frames.join(";")
+    }
+
+    /// Add events from a chunk.
+    pub fn add_chunk(&mut self, chunk: &RecordingChunk) -> Result<()> {
+        let resolver = chunk.resolver();
+        let time_resolver = chunk.time_resolver();
+
+        let event_ids = self.event_names.as_ref().map(|names| {
+            names
+                .iter()
+                .filter_map(|name| resolver.class_id(name))
+                .collect::<FxHashSet<_>>()
+        });
+
+        if matches!(&event_ids, Some(ids) if ids.is_empty()) {
+            return Ok(());
+        }
+
+        let constants = resolver.constant_pool_values()?;
+
+        // Constant pool indices are only meaningful within a chunk.
+        let mut stacks = FxHashMap::<i64, String>::default();
+        let mut methods = FxHashMap::<i64, String>::default();
+
+        for event in chunk.iter_events() {
+            let event = event?;
+
+            if let Some(ids) = &event_ids {
+                if !ids.contains(&event.record().header.event_type) {
+                    continue;
+                }
+            }
+
+            let object = event.resolve_object()?;
+
+            let stack = if let Some(stack) = object.field("stackTrace") {
+                stack
+            } else {
+                continue;
+            };
+
+            let weight = match &self.weight {
+                Weight::Count => 1,
+                Weight::Duration => object
+                    .field("duration")
+                    .and_then(|v| v.as_primitive())
+                    .and_then(|p| p.as_i64())
+                    .map(|ticks| time_resolver.delta_nanoseconds(0, ticks))
+                    .unwrap_or_default(),
+                Weight::Field(name) => object
+                    .field(name)
+                    .and_then(|v| v.as_primitive())
+                    .and_then(|p| p.as_i64())
+                    .unwrap_or_default(),
+            };
+
+            if weight <= 0 {
+                continue;
+            }
+
+            let collapsed = match stack {
+                Value::ConstantPool { constant_index, .. } => {
+                    if let Some(collapsed) = stacks.get(constant_index) {
+                        collapsed.clone()
+                    } else {
+                        let collapsed = self.collapse(stack, &constants, &mut methods);
+                        stacks.insert(*constant_index, collapsed.clone());
+
+                        collapsed
+                    }
+                }
+                Value::ConstantPoolNull => continue,
+                _ => self.collapse(stack, &constants, &mut methods),
+            };
+
+            if collapsed.is_empty() {
+                continue;
+            }
+
+            *self.stacks.entry(collapsed).or_default() += weight as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Add events from all chunks in a recording.
+    pub fn add_recording<D: AsRef<[u8]>>(&mut self, recording: &Recording<D>) -> Result<()> {
+        for chunk in recording.iter_chunks() {
+            self.add_chunk(&chunk?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Iterate over collapsed stacks and their weights.
+    ///
+    /// Stacks are emitted in lexicographic order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> + '_ {
+        self.stacks.iter().map(|(k, v)| (k.as_str(), *v))
+    }
+
+    /// Write collapsed stacks to a writer, one per line.
+    pub fn write(&self, dest: &mut impl Write) -> Result<()> {
+        for (stack, weight) in self.iter() {
+            writeln!(dest, "{} {}", stack, weight)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        test_util::{
+            add_method, add_stack_classes, add_stack_trace, constant, field, long, writer, LONG_ID,
+            STACK_TRACE_ID,
+        },
+        writer::primitive_class,
+    };
+
+    const SAMPLE_ID: i64 = 101;
+    const OTHER_ID: i64 = 102;
+
+    fn recording() -> Result<Recording<Vec<u8>>> {
+        let mut w = writer();
+        add_stack_classes(&mut w);
+
+        for (id, name) in [(SAMPLE_ID, "test.Sample"), (OTHER_ID, "test.Other")] {
+            let mut event = primitive_class(id, name);
+            event.fields = vec![
+                field("startTime", LONG_ID, false),
+                field("duration", LONG_ID, false),
+                field("stackTrace", STACK_TRACE_ID, true),
+                field("weight", LONG_ID, false),
+            ];
+            w.add_class(event);
+        }
+
+        add_method(
+            &mut w,
+            1,
+            "com/example/App",
+            "main",
+            "([Ljava/lang/String;)V",
+        )?;
+        add_method(&mut w, 2, "com/example/App", "work", "(I)J")?;
+        add_method(&mut w, 3, "com/example/Util", "hash", "()I")?;
+        add_stack_trace(&mut w, 1, &[(2, 20), (1, 10)])?;
+        // Frames without line numbers don't get a suffix.
+        add_stack_trace(&mut w, 2, &[(3, 0), (1, 11)])?;
+
+        for (event, stack, duration, weight) in [
+            (SAMPLE_ID, 1, 100, 5),
+            (SAMPLE_ID, 1, 50, 0),
+            (SAMPLE_ID, 2, 10, 7),
+            (OTHER_ID, 2, 1, 1),
+        ] {
+            w.write_event(
+                event,
+                &[
+                    long(0),
+                    long(duration),
+                    constant(STACK_TRACE_ID, stack),
+                    long(weight),
+                ],
+            )?;
+        }
+
+        Ok(Recording::from_data(w.finish()?))
+    }
+
+    fn collapse(stacks: &mut CollapsedStacks) -> Result<String> {
+        stacks.add_recording(&recording()?)?;
+
+        let mut output = vec![];
+        stacks.write(&mut output)?;
+
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn count() -> Result<()> {
+        let mut stacks = CollapsedStacks::new(Weight::Count);
+
+        assert_eq!(
+            collapse(&mut stacks)?,
+            "com.example.App.main;com.example.App.work 2\n\
+             com.example.App.main;com.example.Util.hash 2\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn line_numbers() -> Result<()> {
+        let mut stacks = CollapsedStacks::new(Weight::Count);
+        stacks.add_event_name("test.Sample");
+        stacks.set_line_numbers(true);
+
+        assert_eq!(
+            collapse(&mut stacks)?,
+            "com.example.App.main:10;com.example.App.work:20 2\n\
+             com.example.App.main:11;com.example.Util.hash 1\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn duration() -> Result<()> {
+        let mut stacks = CollapsedStacks::new(Weight::Duration);
+        collapse(&mut stacks)?;
+
+        assert_eq!(
+            stacks.iter().collect::<Vec<_>>(),
+            vec![
+                ("com.example.App.main;com.example.App.work", 150),
+                ("com.example.App.main;com.example.Util.hash", 11),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn field_weight() -> Result<()> {
+        let mut stacks = CollapsedStacks::new(Weight::Field("weight".to_string()));
+        collapse(&mut stacks)?;
+
+        assert_eq!(
+            stacks.iter().collect::<Vec<_>>(),
+            vec![
+                ("com.example.App.main;com.example.App.work", 5),
+                ("com.example.App.main;com.example.Util.hash", 8),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_event_name() -> Result<()> {
+        let mut stacks = CollapsedStacks::new(Weight::Count);
+        stacks.add_event_name("jdk.ExecutionSample");
+
+        assert_eq!(collapse(&mut stacks)?, "");
+
+        Ok(())
+    }
+}
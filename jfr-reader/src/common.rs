@@ -12,7 +12,7 @@ use crate::error::ParseResult;
 use nom::number::streaming::be_i8;
 
 /// Read an LEB-128 encoded integer.
-pub fn leb128_i64(mut s: &[u8]) -> ParseResult<i64> {
+pub fn leb128_i64(mut s: &[u8]) -> ParseResult<'_, i64> {
     let mut res = 0;
 
     let mut x: i8;
@@ -32,14 +32,48 @@ pub fn leb128_i64(mut s: &[u8]) -> ParseResult<i64> {
     Ok((s, res))
 }
 
-pub fn leb128_i16(s: &[u8]) -> ParseResult<i16> {
+pub fn leb128_i16(s: &[u8]) -> ParseResult<'_, i16> {
     let (s, x) = leb128_i64(s)?;
 
     Ok((s, x as i16))
 }
 
-pub fn leb128_i32(s: &[u8]) -> ParseResult<i32> {
+pub fn leb128_i32(s: &[u8]) -> ParseResult<'_, i32> {
     let (s, x) = leb128_i64(s)?;
 
     Ok((s, x as i32))
 }
+
+/// Write an LEB-128 encoded integer.
+///
+/// This is the inverse of [leb128_i64]. Values are encoded in up to 9 bytes:
+/// 8 bytes holding 7 bits each followed by a final byte holding 8 bits.
+pub fn write_leb128_i64(buf: &mut Vec<u8>, v: i64) {
+    let mut v = v as u64;
+
+    for _ in 0..8 {
+        if v < 0x80 {
+            buf.push(v as u8);
+            return;
+        }
+
+        buf.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+
+    buf.push(v as u8);
+}
+
+/// Write an LEB-128 encoded 16-bit integer.
+///
+/// Negative values are encoded as their unsigned bit pattern, like the JVM does.
+pub fn write_leb128_i16(buf: &mut Vec<u8>, v: i16) {
+    write_leb128_i64(buf, v as u16 as i64)
+}
+
+/// Write an LEB-128 encoded 32-bit integer.
+///
+/// Negative values are encoded as their unsigned bit pattern, like the JVM does.
+pub fn write_leb128_i32(buf: &mut Vec<u8>, v: i32) {
+    write_leb128_i64(buf, v as u32 as i64)
+}
@@ -33,7 +33,7 @@ pub struct ConstantPoolHeader {
 }
 
 impl ConstantPoolHeader {
-    pub fn parse(s: &[u8]) -> ParseResult<Self> {
+    pub fn parse(s: &[u8]) -> ParseResult<'_, Self> {
         let (s, size) = leb128_i32(s)?;
         // Should be constant pool type id.
         let (s, type_id) = leb128_i64(s)?;
@@ -59,6 +59,9 @@ impl ConstantPoolHeader {
     }
 }
 
+/// Constants belonging to a single class. Pairs of constant pool index and value.
+pub type ClassConstants<'r> = Vec<(i64, Value<'r>)>;
+
 fn parse_constant_pool_value<'a, 'r>(
     s: &'a [u8],
     resolver: &'r EventResolver<'a>,
@@ -76,7 +79,7 @@ fn parse_constant_pool_value<'a, 'r>(
 fn parse_constant_pool_class<'a, 'r>(
     s: &'a [u8],
     resolver: &'r EventResolver<'a>,
-) -> Result<(&'a [u8], i64, Vec<(i64, Value<'r>)>)> {
+) -> Result<(&'a [u8], i64, ClassConstants<'r>)> {
     let (mut s, (class_id, constant_count)) = context(
         "parsing constant pool class entry",
         pair(leb128_i64, leb128_i32),
@@ -104,7 +107,7 @@ pub struct ConstantPoolEvent<'a> {
 }
 
 impl<'a> ConstantPoolEvent<'a> {
-    pub fn parse(s: &'a [u8]) -> ParseResult<Self> {
+    pub fn parse(s: &'a [u8]) -> ParseResult<'a, Self> {
         let (pool_data, header) =
             context("parsing constant pool header", ConstantPoolHeader::parse)(s)?;
 
@@ -128,7 +131,7 @@ impl<'a> ConstantPoolEvent<'a> {
     pub fn resolve_constants<'r>(
         &self,
         resolver: &'r EventResolver<'a>,
-    ) -> Result<Vec<(i64, Vec<(i64, Value<'r>)>)>> {
+    ) -> Result<Vec<(i64, ClassConstants<'r>)>> {
         let mut s = self.pool_data;
 
         let mut res = Vec::new();
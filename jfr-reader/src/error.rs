@@ -113,6 +113,9 @@ pub enum Error {
 
     #[error("deserialization error: {0}")]
     Deserialize(String),
+
+    #[error("writing: {0}")]
+    Write(String),
 }
 
 impl From<nom::Err<NomParseError>> for Error {
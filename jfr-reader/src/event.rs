@@ -154,8 +154,13 @@ where
         Self { object, constants }
     }
 
+    /// Obtain the [ConstantResolver] used to resolve constants in this event.
+    pub fn constants(&self) -> &'cr CR {
+        self.constants
+    }
+
     /// Obtain the [ClassElement] being defined.
-    pub fn class(&self) -> &ClassElement {
+    pub fn class(&self) -> &ClassElement<'_> {
         self.object.class()
     }
 
@@ -179,26 +184,26 @@ where
     }
 
     /// Obtain the [FieldElement] at specified index.
-    pub fn field_element_at(&self, index: usize) -> Option<&FieldElement> {
+    pub fn field_element_at(&self, index: usize) -> Option<&FieldElement<'_>> {
         self.object.class().fields.get(index)
     }
 
     /// Obtain the [Value] for a field at the specified index.
-    pub fn field_value_at(&self, index: usize) -> Option<&Value> {
+    pub fn field_value_at(&self, index: usize) -> Option<&Value<'_>> {
         self.object.field_at(index)
     }
 
     /// Resolve the [FieldElement] and [Value] for the specified field index.
-    pub fn field_at(&self, index: usize) -> Option<(&FieldElement, &Value)> {
-        self.object.class().fields.get(index).and_then(|field| {
-            self.object
-                .field_at(index)
-                .and_then(|value| Some((field, value)))
-        })
+    pub fn field_at(&self, index: usize) -> Option<(&FieldElement<'_>, &Value<'_>)> {
+        self.object
+            .class()
+            .fields
+            .get(index)
+            .and_then(|field| self.object.field_at(index).map(|value| (field, value)))
     }
 
     /// Iterate over [FieldElement] and [Value] pairs.
-    pub fn iter_fields(&self) -> impl Iterator<Item = (&FieldElement, &Value)> + '_ {
+    pub fn iter_fields(&self) -> impl Iterator<Item = (&FieldElement<'_>, &Value<'_>)> + '_ {
         self.object
             .class()
             .fields
@@ -207,7 +212,7 @@ where
     }
 
     /// Find the field with a given name and return its [FieldElement] and [Value].
-    pub fn find_field_named(&self, name: &str) -> Option<(&FieldElement, &Value)> {
+    pub fn find_field_named(&self, name: &str) -> Option<(&FieldElement<'_>, &Value<'_>)> {
         self.iter_fields().find(|(field, _)| field.name == name)
     }
 
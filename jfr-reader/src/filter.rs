@@ -0,0 +1,353 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Filtering recordings.
+//!
+//! [RecordingFilter] rewrites a recording so it only contains events of
+//! selected types and/or events starting within a wall clock time window.
+//!
+//! Event records are copied verbatim. Constant pool entries not (transitively)
+//! referenced by a retained event are dropped. Chunks without any retained
+//! events are dropped.
+
+use crate::{
+    chunk::ChunkReader,
+    chunk_event::ChunkEvent,
+    error::Result,
+    primitive::Primitive,
+    recording::{Recording, RecordingChunk},
+    resolver::{ConstantPoolValues, ConstantResolver},
+    value::{ConstantValue, Value},
+    writer::ChunkWriter,
+};
+use chrono::{DateTime, Utc};
+use rustc_hash::FxHashSet;
+use std::io::Write;
+
+/// Statistics about a filter operation.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FilterStats {
+    /// Number of chunks in the input.
+    pub chunks_read: usize,
+    /// Number of chunks in the output.
+    pub chunks_written: usize,
+    /// Number of non-special events in the input.
+    pub events_read: usize,
+    /// Number of events in the output.
+    pub events_written: usize,
+    /// Number of constants in the output.
+    pub constants_written: usize,
+}
+
+/// Rewrites recordings to retain a subset of events.
+///
+/// By default all events are retained.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingFilter {
+    event_names: Option<FxHashSet<String>>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+impl RecordingFilter {
+    /// Construct an instance that retains all events.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retain events having the given class name, e.g. `jdk.ExecutionSample`.
+    ///
+    /// Once called, events of types not added are dropped.
+    pub fn add_event_name(&mut self, name: impl ToString) {
+        self.event_names
+            .get_or_insert_with(Default::default)
+            .insert(name.to_string());
+    }
+
+    /// Only retain events starting at or after the given time.
+    pub fn set_start(&mut self, start: Option<DateTime<Utc>>) {
+        self.start = start;
+    }
+
+    /// Only retain events starting before the given time.
+    pub fn set_end(&mut self, end: Option<DateTime<Utc>>) {
+        self.end = end;
+    }
+
+    /// Filter a single chunk.
+    ///
+    /// Returns a [ChunkWriter] populated with the retained events and their
+    /// constants, or [None] if no events were retained.
+    pub fn filter_chunk<'a>(
+        &self,
+        chunk: &RecordingChunk<'a>,
+        stats: &mut FilterStats,
+    ) -> Result<Option<ChunkWriter<'a>>> {
+        let resolver = chunk.resolver();
+        let time_resolver = chunk.time_resolver();
+
+        let event_ids = self.event_names.as_ref().map(|names| {
+            names
+                .iter()
+                .filter_map(|name| resolver.class_id(name))
+                .collect::<FxHashSet<_>>()
+        });
+
+        let mut records = vec![];
+
+        for event in chunk.iter_events() {
+            let event = event?;
+            stats.events_read += 1;
+
+            if let Some(ids) = &event_ids {
+                if !ids.contains(&event.record().header.event_type) {
+                    continue;
+                }
+            }
+
+            if self.start.is_some() || self.end.is_some() {
+                let start = time_resolver.date_time_utc(event.start_ticks()?);
+
+                if matches!(self.start, Some(t) if start < t)
+                    || matches!(self.end, Some(t) if start >= t)
+                {
+                    continue;
+                }
+            }
+
+            records.push(event.record().clone());
+        }
+
+        if records.is_empty() {
+            return Ok(None);
+        }
+
+        let constants = resolver.constant_pool_values()?;
+        let string_class_id = resolver.class_id("java.lang.String");
+
+        // Find all constants referenced by retained events, following
+        // references between constants.
+        let mut pending = vec![];
+        for record in &records {
+            collect_references(
+                &Value::Object(record.resolve_object(resolver)?),
+                string_class_id,
+                &mut pending,
+            );
+        }
+
+        let mut referenced = FxHashSet::default();
+        while let Some(key) = pending.pop() {
+            if !referenced.insert(key) {
+                continue;
+            }
+
+            if let ConstantValue::Value(v) = constants.get(key.0, key.1) {
+                collect_references(v, string_class_id, &mut pending);
+            }
+        }
+
+        let header = chunk.header();
+        let metadata = chunk.reader().metadata()?;
+
+        let mut writer = ChunkWriter::new();
+        writer.set_nanoseconds_since_epoch(header.nanoseconds_since_epoch);
+        writer.set_duration_nanoseconds(header.duration_nanoseconds);
+        writer.set_ticks(header.start_ticks, header.ticks_per_second)?;
+        writer.set_region(
+            &metadata.root.region.locale,
+            metadata.root.region.gmt_offset,
+        );
+
+        let mut class_ids = resolver
+            .class_ids_and_names()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        class_ids.sort_unstable();
+        writer.add_classes(
+            class_ids
+                .into_iter()
+                .filter_map(|id| resolver.get_class(id).cloned()),
+        );
+
+        stats.constants_written += add_constants(&mut writer, &constants, referenced)?;
+
+        for record in &records {
+            writer.write_raw_event(record.event_data()?)?;
+        }
+        stats.events_written += records.len();
+
+        Ok(Some(writer))
+    }
+
+    /// Filter a recording, writing the result to a writer.
+    pub fn filter_recording<D: AsRef<[u8]>>(
+        &self,
+        recording: &Recording<D>,
+        dest: &mut impl Write,
+    ) -> Result<FilterStats> {
+        let mut stats = FilterStats::default();
+
+        // A chunk is only written once we know whether it is the final one.
+        let mut previous: Option<ChunkWriter> = None;
+
+        for chunk in recording.iter_chunks() {
+            let chunk = chunk?;
+            stats.chunks_read += 1;
+
+            if let Some(writer) = self.filter_chunk(&chunk, &mut stats)? {
+                if let Some(mut previous) = previous.replace(writer) {
+                    previous.set_final_chunk(false);
+                    dest.write_all(&previous.finish()?)?;
+                    stats.chunks_written += 1;
+                }
+            }
+        }
+
+        if let Some(previous) = previous {
+            dest.write_all(&previous.finish()?)?;
+            stats.chunks_written += 1;
+        }
+
+        Ok(stats)
+    }
+}
+
+/// Collect the (class ID, constant index) references within a value.
+fn collect_references(value: &Value, string_class_id: Option<i64>, dest: &mut Vec<(i64, i64)>) {
+    match value {
+        Value::Primitive(Primitive::StringConstantPool(index)) => {
+            if let Some(class_id) = string_class_id {
+                dest.push((class_id, *index));
+            }
+        }
+        Value::Primitive(_) | Value::ConstantPoolNull => {}
+        Value::Object(o) => {
+            for v in o.iter_fields() {
+                collect_references(v, string_class_id, dest);
+            }
+        }
+        Value::ConstantPool {
+            class_id,
+            constant_index,
+        } => {
+            // Index 0 is null and has no pool entry.
+            if *constant_index != 0 {
+                dest.push((*class_id, *constant_index));
+            }
+        }
+        Value::Array(a) => {
+            for v in a {
+                collect_references(v, string_class_id, dest);
+            }
+        }
+    }
+}
+
+/// Add referenced constants to a writer in a deterministic order.
+///
+/// Returns the number of constants added.
+fn add_constants(
+    writer: &mut ChunkWriter,
+    constants: &ConstantPoolValues,
+    referenced: FxHashSet<(i64, i64)>,
+) -> Result<usize> {
+    let mut referenced = referenced.into_iter().collect::<Vec<_>>();
+    referenced.sort_unstable();
+
+    let mut count = 0;
+
+    for (class_id, index) in referenced {
+        if let ConstantValue::Value(v) = constants.get(class_id, index) {
+            writer.add_constant(class_id, index, v)?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        test_util::{add_thread, constant, field, long, writer, LONG_ID, THREAD_ID},
+        writer::primitive_class,
+    };
+    use chrono::TimeZone;
+
+    const SAMPLE_ID: i64 = 101;
+    const OTHER_ID: i64 = 102;
+
+    fn recording() -> Result<Vec<u8>> {
+        let mut w = writer();
+
+        for (id, name) in [(SAMPLE_ID, "test.Sample"), (OTHER_ID, "test.Other")] {
+            let mut event = primitive_class(id, name);
+            event.fields = vec![
+                field("startTime", LONG_ID, false),
+                field("eventThread", THREAD_ID, true),
+            ];
+            w.add_class(event);
+        }
+
+        for index in 1..=3 {
+            add_thread(&mut w, index, &format!("thread-{}", index))?;
+        }
+
+        for (i, id) in [SAMPLE_ID, OTHER_ID, SAMPLE_ID].into_iter().enumerate() {
+            w.write_event(
+                id,
+                &[
+                    long(i as i64 * 1_000_000_000),
+                    constant(THREAD_ID, i as i64 + 1),
+                ],
+            )?;
+        }
+
+        w.finish()
+    }
+
+    #[test]
+    fn filter_events() -> Result<()> {
+        let recording = Recording::from_data(recording()?);
+
+        let mut filter = RecordingFilter::new();
+        filter.add_event_name("test.Sample");
+        filter.set_end(Some(Utc.timestamp_opt(1, 0).unwrap()));
+
+        let mut out = vec![];
+        let stats = filter.filter_recording(&recording, &mut out)?;
+
+        assert_eq!(
+            stats,
+            FilterStats {
+                chunks_read: 1,
+                chunks_written: 1,
+                events_read: 3,
+                events_written: 1,
+                constants_written: 1,
+            }
+        );
+
+        let filtered = Recording::from_data(out);
+        let events = filtered.iter_events().collect::<Result<Vec<_>>>()?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].class_name(), Some("test.Sample"));
+
+        let constants = events[0].resolver().constant_pool_values()?;
+        let object = events[0].resolve_object()?.resolve_constants(&constants)?;
+        let thread = object.field_at(1).unwrap().as_object().unwrap();
+        assert!(matches!(
+            thread.field_at(0),
+            Some(Value::Primitive(Primitive::String(s))) if s == "thread-1"
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,541 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! JSON export of events.
+//!
+//! [EventSerializer] provides [serde::Serialize] implementations for resolved
+//! events and values. Constant pool references are followed through the
+//! chunk's [ConstantResolver], so the serialized form is self-contained.
+//! Although intended for JSON, any serde data format can be used.
+//!
+//! [JsonWriter] streams serialized events as a JSON document structurally
+//! compatible with the output of `jfr print --json`:
+//!
+//! * Events are objects with `type` and `values` keys.
+//! * Field names come from [crate::metadata::FieldElement::name].
+//! * `@Timestamp` fields are ISO-8601 instants in UTC, e.g.
+//!   `2023-09-01T12:00:00.123456789Z`.
+//! * `@Timespan` fields are ISO-8601 durations as emitted by Java's
+//!   `java.time.Duration`, e.g. `PT0.0013S`.
+//! * Types flagged as *simple types*, such as thread states, are rendered as
+//!   the value of their single field.
+
+use crate::{
+    error::{Error, Result},
+    primitive::Primitive,
+    print::{field_formats, FieldFormat},
+    recording::RecordingEvent,
+    resolver::{ConstantPoolValues, ConstantResolver, EventResolver, TimeResolver},
+    value::{ConstantValue, Object, Value},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use rustc_hash::FxHashMap;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::io::Write;
+
+/// Maximum nesting of objects before values are serialized as null.
+///
+/// Protects against reference cycles in constant pools.
+const MAX_DEPTH: usize = 64;
+
+/// Format an instant like Java's `java.time.Instant`.
+///
+/// Fractional seconds are emitted in groups of 3 digits as needed.
+pub fn format_instant(t: &DateTime<Utc>) -> String {
+    let nanos = t.timestamp_subsec_nanos();
+
+    let fraction = if nanos == 0 {
+        String::new()
+    } else if nanos % 1_000_000 == 0 {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos % 1_000 == 0 {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{:09}", nanos)
+    };
+
+    format!("{}{}Z", t.format("%Y-%m-%dT%H:%M:%S"), fraction)
+}
+
+/// Format a duration in nanoseconds like Java's `java.time.Duration`.
+pub fn format_java_duration(nanos: i128) -> String {
+    let seconds = nanos.div_euclid(1_000_000_000);
+    let nanos = nanos.rem_euclid(1_000_000_000);
+
+    if seconds == 0 && nanos == 0 {
+        return "PT0S".to_string();
+    }
+
+    let effective_seconds = if seconds < 0 && nanos > 0 {
+        seconds + 1
+    } else {
+        seconds
+    };
+    let hours = effective_seconds / 3600;
+    let minutes = (effective_seconds % 3600) / 60;
+    let secs = effective_seconds % 60;
+
+    let mut res = "PT".to_string();
+    if hours != 0 {
+        res.push_str(&format!("{}H", hours));
+    }
+    if minutes != 0 {
+        res.push_str(&format!("{}M", minutes));
+    }
+    if secs == 0 && nanos == 0 && res.len() > 2 {
+        return res;
+    }
+
+    if seconds < 0 && nanos > 0 && secs == 0 {
+        res.push_str("-0");
+    } else {
+        res.push_str(&secs.to_string());
+    }
+
+    if nanos > 0 {
+        let fraction = if seconds < 0 {
+            2_000_000_000 - nanos
+        } else {
+            nanos + 1_000_000_000
+        };
+        let fraction = fraction.to_string();
+
+        res.push('.');
+        res.push_str(fraction[1..].trim_end_matches('0'));
+    }
+
+    res.push('S');
+    res
+}
+
+/// Serializes events and values of a single chunk.
+pub struct EventSerializer<'r> {
+    time_resolver: &'r TimeResolver,
+    constants: ConstantPoolValues<'r>,
+    formats: FxHashMap<(i64, usize), FieldFormat>,
+    stack_depth: Option<usize>,
+}
+
+impl<'r> EventSerializer<'r> {
+    /// Construct an instance for the chunk having the given resolver.
+    pub fn new(resolver: &'r EventResolver) -> Result<Self> {
+        Ok(Self {
+            time_resolver: resolver.time_resolver(),
+            constants: resolver.constant_pool_values()?,
+            formats: field_formats(resolver),
+            stack_depth: None,
+        })
+    }
+
+    /// Set the maximum number of frames to serialize in stack traces.
+    ///
+    /// All frames are serialized by default. `jfr print` limits stack traces
+    /// to 5 frames.
+    pub fn set_stack_depth(&mut self, depth: Option<usize>) {
+        self.stack_depth = depth;
+    }
+
+    /// Obtain a serializable form of a value.
+    pub fn value<'s>(&'s self, value: &'s Value<'s>) -> SerializableValue<'s> {
+        SerializableValue {
+            serializer: self,
+            value,
+            format: FieldFormat::Plain,
+            depth: 0,
+        }
+    }
+
+    /// Obtain a serializable form of an event.
+    ///
+    /// The event is serialized as an object with `type` and `values` keys.
+    pub fn event<'s>(&'s self, event: &'s RecordingEvent) -> Result<SerializableEvent<'s>> {
+        Ok(SerializableEvent {
+            serializer: self,
+            value: Value::Object(event.resolve_object()?),
+        })
+    }
+}
+
+/// A serializable [Value].
+pub struct SerializableValue<'s> {
+    serializer: &'s EventSerializer<'s>,
+    value: &'s Value<'s>,
+    format: FieldFormat,
+    depth: usize,
+}
+
+impl<'s> SerializableValue<'s> {
+    fn nested(&self, value: &'s Value<'s>, format: FieldFormat) -> Self {
+        Self {
+            serializer: self.serializer,
+            value,
+            format,
+            depth: self.depth + 1,
+        }
+    }
+
+    fn serialize_integer<S: Serializer>(&self, v: i64, s: S) -> Result<S::Ok, S::Error> {
+        let time_resolver = self.serializer.time_resolver;
+
+        match self.format {
+            FieldFormat::TimestampTicks | FieldFormat::TimestampMilliseconds
+                if v == i64::MIN || v == i64::MAX =>
+            {
+                s.serialize_none()
+            }
+            FieldFormat::TimestampTicks => {
+                s.serialize_str(&format_instant(&time_resolver.date_time_utc(v)))
+            }
+            FieldFormat::TimestampMilliseconds => match Utc.timestamp_millis_opt(v).single() {
+                Some(t) => s.serialize_str(&format_instant(&t)),
+                None => s.serialize_none(),
+            },
+            // Like the JDK, the minimum value is treated as the most negative
+            // duration in seconds.
+            FieldFormat::Timespan(_) if v == i64::MIN => {
+                s.serialize_str(&format_java_duration(v as i128 * 1_000_000_000))
+            }
+            FieldFormat::Timespan(0) => s.serialize_str(&format_java_duration(
+                time_resolver.delta_nanoseconds(0, v) as i128,
+            )),
+            FieldFormat::Timespan(unit) => {
+                s.serialize_str(&format_java_duration(v as i128 * unit as i128))
+            }
+            _ => s.serialize_i64(v),
+        }
+    }
+
+    fn serialize_object<S: Serializer>(&self, o: &'s Object<'s>, s: S) -> Result<S::Ok, S::Error> {
+        let class = o.class();
+
+        let format = |index| {
+            self.serializer
+                .formats
+                .get(&(class.id, index))
+                .copied()
+                .unwrap_or(FieldFormat::Plain)
+        };
+
+        if class.simple_type.as_deref() == Some("true") && class.fields.len() == 1 {
+            if let Some(v) = o.field_at(0) {
+                return self.nested(v, format(0)).serialize(s);
+            }
+        }
+
+        let mut map = s.serialize_map(Some(class.fields.len()))?;
+        for (index, (field, value)) in class.fields.iter().zip(o.iter_fields()).enumerate() {
+            if let (Some(depth), Value::Array(frames)) = (self.serializer.stack_depth, value) {
+                if class.name == "jdk.types.StackTrace" && field.name == "frames" {
+                    let frames = frames
+                        .iter()
+                        .take(depth)
+                        .map(|v| self.nested(v, format(index)))
+                        .collect::<Vec<_>>();
+                    map.serialize_entry(field.name.as_ref(), &frames)?;
+                    continue;
+                }
+            }
+
+            map.serialize_entry(field.name.as_ref(), &self.nested(value, format(index)))?;
+        }
+        map.end()
+    }
+}
+
+impl<'s> Serialize for SerializableValue<'s> {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.depth > MAX_DEPTH {
+            return s.serialize_none();
+        }
+
+        let constants = &self.serializer.constants;
+
+        match self.value {
+            Value::Primitive(p) => {
+                if let Some(v) = p.as_i64() {
+                    return self.serialize_integer(v, s);
+                }
+
+                match p {
+                    Primitive::Boolean(v) => s.serialize_bool(*v),
+                    Primitive::Float(v) => s.serialize_f32(*v),
+                    Primitive::Double(v) => s.serialize_f64(*v),
+                    Primitive::Character(v) => s.serialize_char(*v),
+                    Primitive::String(v) => s.serialize_str(v),
+                    Primitive::StringConstantPool(index) => match constants.get_string(*index) {
+                        ConstantValue::Value(v) => self.nested(v, self.format).serialize(s),
+                        ConstantValue::Null | ConstantValue::Missing => s.serialize_none(),
+                    },
+                    _ => s.serialize_none(),
+                }
+            }
+            Value::Object(o) => self.serialize_object(o, s),
+            Value::Array(values) => {
+                s.collect_seq(values.iter().map(|v| self.nested(v, self.format)))
+            }
+            Value::ConstantPool {
+                class_id,
+                constant_index,
+            } => match constants.get(*class_id, *constant_index) {
+                ConstantValue::Value(v) => self.nested(v, self.format).serialize(s),
+                ConstantValue::Null | ConstantValue::Missing => s.serialize_none(),
+            },
+            Value::ConstantPoolNull => s.serialize_none(),
+        }
+    }
+}
+
+/// A serializable event.
+pub struct SerializableEvent<'s> {
+    serializer: &'s EventSerializer<'s>,
+    /// Always a [Value::Object].
+    value: Value<'s>,
+}
+
+impl<'s> Serialize for SerializableEvent<'s> {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let name = match &self.value {
+            Value::Object(o) => o.class().name.as_ref(),
+            _ => "",
+        };
+        let values = SerializableValue {
+            serializer: self.serializer,
+            value: &self.value,
+            format: FieldFormat::Plain,
+            depth: 0,
+        };
+
+        let mut map = s.serialize_map(Some(2))?;
+        map.serialize_entry("type", name)?;
+        map.serialize_entry("values", &values)?;
+        map.end()
+    }
+}
+
+/// Streams events as a JSON document in the layout of `jfr print --json`.
+///
+/// [Self::finish] must be called to close the document.
+pub struct JsonWriter<W: Write> {
+    dest: W,
+    events: usize,
+}
+
+impl<W: Write> JsonWriter<W> {
+    /// Construct an instance writing to a destination.
+    ///
+    /// The start of the document is written immediately.
+    pub fn new(mut dest: W) -> Result<Self> {
+        write!(dest, "{{\n  \"recording\": {{\n    \"events\": [")?;
+
+        Ok(Self { dest, events: 0 })
+    }
+
+    /// Write an event, typically a [SerializableEvent].
+    pub fn write_event(&mut self, event: &impl Serialize) -> Result<()> {
+        let data = serde_json::to_string_pretty(event).map_err(|e| Error::Write(e.to_string()))?;
+
+        if self.events > 0 {
+            write!(self.dest, ",")?;
+        }
+        write!(self.dest, "\n      ")?;
+
+        // Strings in JSON can't contain literal newlines, so this only
+        // indents the structure.
+        self.dest
+            .write_all(data.replace('\n', "\n      ").as_bytes())?;
+        self.events += 1;
+
+        Ok(())
+    }
+
+    /// Close the document, returning the destination.
+    pub fn finish(mut self) -> Result<W> {
+        if self.events > 0 {
+            write!(self.dest, "\n    ")?;
+        }
+        writeln!(self.dest, "]\n  }}\n}}")?;
+        self.dest.flush()?;
+
+        Ok(self.dest)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            metadata::FieldElement,
+            recording::Recording,
+            test_util::{
+                add_annotation_classes, add_method, add_stack_classes, add_stack_trace, add_thread,
+                annotation, constant, field, long, string, writer, DATA_AMOUNT_ID, INT_ID,
+                LABEL_ID, LONG_ID, STACK_TRACE_ID, STRING_ID, THREAD_ID, TIMESPAN_ID, TIMESTAMP_ID,
+            },
+            writer::primitive_class,
+        },
+        std::borrow::Cow,
+    };
+
+    const EVENT_ID: i64 = 101;
+
+    /// Output of `jfr print --json` from OpenJDK 17 for [recording], without
+    /// trailing whitespace.
+    const JFR_PRINT_JSON: &str = r#"{
+  "recording": {
+    "events": [{
+      "type": "test.Event",
+      "values": {
+        "startTime": "2023-11-14T22:13:21.123456789Z",
+        "duration": "PT0.0015S",
+        "eventThread": {
+          "javaName": "main",
+          "javaThreadId": 1
+        },
+        "stackTrace": {
+          "truncated": false,
+          "frames": [{
+            "method": {
+              "type": {
+                "name": "com\/example\/App"
+              },
+              "name": "work",
+              "descriptor": "(I)J"
+            },
+            "lineNumber": 20
+          }, {
+            "method": {
+              "type": {
+                "name": "com\/example\/App"
+              },
+              "name": "main",
+              "descriptor": "([Ljava\/lang\/String;)V"
+            },
+            "lineNumber": 10
+          }]
+        },
+        "size": 1677721,
+        "message": "hello \"world\"",
+        "values": [-1, 7]
+      }
+    }]
+  }
+}"#;
+
+    fn recording() -> Result<Recording<Vec<u8>>> {
+        let mut w = writer();
+        w.set_nanoseconds_since_epoch(1_700_000_000_000_000_000);
+        add_stack_classes(&mut w);
+        add_annotation_classes(&mut w);
+
+        let mut event = primitive_class(EVENT_ID, "test.Event");
+        event.super_type = Some(Cow::Borrowed("jdk.jfr.Event"));
+        event.annotations = vec![annotation(LABEL_ID, "Test Event")];
+        event.fields = vec![
+            FieldElement {
+                annotations: vec![annotation(TIMESTAMP_ID, "TICKS")],
+                ..field("startTime", LONG_ID, false)
+            },
+            FieldElement {
+                annotations: vec![annotation(TIMESPAN_ID, "TICKS")],
+                ..field("duration", LONG_ID, false)
+            },
+            field("eventThread", THREAD_ID, true),
+            field("stackTrace", STACK_TRACE_ID, true),
+            FieldElement {
+                annotations: vec![annotation(DATA_AMOUNT_ID, "BYTES")],
+                ..field("size", LONG_ID, false)
+            },
+            field("message", STRING_ID, false),
+            FieldElement {
+                dimension: Some(1),
+                ..field("values", INT_ID, false)
+            },
+        ];
+        w.add_class(event);
+
+        add_thread(&mut w, 1, "main")?;
+        add_method(
+            &mut w,
+            1,
+            "com/example/App",
+            "main",
+            "([Ljava/lang/String;)V",
+        )?;
+        add_method(&mut w, 2, "com/example/App", "work", "(I)J")?;
+        add_stack_trace(&mut w, 1, &[(2, 20), (1, 10)])?;
+
+        w.write_event(
+            EVENT_ID,
+            &[
+                long(1_123_456_789),
+                long(1_500_000),
+                constant(THREAD_ID, 1),
+                constant(STACK_TRACE_ID, 1),
+                long(1_677_721),
+                string("hello \"world\""),
+                Value::Array(vec![
+                    Value::Primitive(Primitive::Integer(-1)),
+                    Value::Primitive(Primitive::Integer(7)),
+                ]),
+            ],
+        )?;
+
+        Ok(Recording::from_data(w.finish()?))
+    }
+
+    #[test]
+    fn jfr_print_json() -> Result<()> {
+        let recording = recording()?;
+        let chunk = recording.iter_chunks().next().unwrap()?;
+        let serializer = EventSerializer::new(chunk.resolver())?;
+
+        let mut writer = JsonWriter::new(vec![])?;
+        for event in chunk.iter_events() {
+            writer.write_event(&serializer.event(&event?)?)?;
+        }
+        let output = writer.finish()?;
+
+        // Whitespace and escaping differ from the JDK's. The documents don't.
+        let expected = serde_json::from_str::<serde_json::Value>(JFR_PRINT_JSON).unwrap();
+        let actual = serde_json::from_slice::<serde_json::Value>(&output).unwrap();
+        assert_eq!(actual, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn java_formats() {
+        assert_eq!(format_java_duration(0), "PT0S");
+        assert_eq!(format_java_duration(4_489), "PT0.000004489S");
+        assert_eq!(format_java_duration(1_000_000_000), "PT1S");
+        assert_eq!(format_java_duration(3_723_500_000_000), "PT1H2M3.5S");
+        assert_eq!(
+            format_java_duration(i64::MAX as i128 * 1_000_000),
+            "PT2562047788015H12M55.807S"
+        );
+        assert_eq!(
+            format_java_duration(i64::MIN as i128),
+            "PT-2562047H-47M-16.854775808S"
+        );
+        assert_eq!(format_java_duration(-500_000_000), "PT-0.5S");
+
+        let t = Utc.timestamp_opt(1_693_569_600, 123_456_789).unwrap();
+        assert_eq!(format_instant(&t), "2023-09-01T12:00:00.123456789Z");
+        let t = Utc.timestamp_opt(1_693_569_600, 844_000_000).unwrap();
+        assert_eq!(format_instant(&t), "2023-09-01T12:00:00.844Z");
+        let t = Utc.timestamp_opt(1_693_569_600, 0).unwrap();
+        assert_eq!(format_instant(&t), "2023-09-01T12:00:00Z");
+    }
+}
@@ -33,6 +33,23 @@
 //! you an API for resolving chunk data. You can then construct a
 //! [chunk::SliceReader] to read from the chunk.
 //!
+//! If you want to process all events in a multi-chunk file, see
+//! [recording::Recording]. It handles chunk boundaries for you and emits
+//! events paired with the [resolver::EventResolver] of their chunk. With the
+//! `mmap` feature enabled, `recording::MmapRecording` reads chunks directly
+//! from a memory mapped file without copying them.
+//!
+//! If you want to follow a recording that a JVM is still writing, see
+//! [streaming::StreamingReader].
+//!
+//! If you want to produce JFR data, see [writer::ChunkWriter]. To shrink an
+//! existing recording, see [filter::RecordingFilter]. With the `pprof`
+//! feature enabled, `pprof::ProfileBuilder` converts execution samples to
+//! pprof profiles. [collapsed::CollapsedStacks] produces flame graph input
+//! from any event having a stack trace. [print::EventPrinter] renders events
+//! as human readable text. With the `json` feature enabled,
+//! `json::JsonWriter` exports them as JSON.
+//!
 //! [chunk::SliceReader] exposes some APIs to read from the chunk, but they are
 //! exceptionally low level and probably not useful by themselves. You should obtain
 //! an [resolver::EventResolver] via [chunk::ChunkReader::resolver] on the
@@ -46,18 +63,30 @@
 pub mod annotations;
 pub mod chunk;
 pub mod chunk_event;
+pub mod collapsed;
 pub mod common;
 pub mod constant_pool;
 pub mod error;
 pub mod event;
+pub mod filter;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod metadata;
 #[cfg(feature = "metadata-xml-derive")]
 pub mod metadata_xml;
+#[cfg(feature = "pprof")]
+pub mod pprof;
 pub mod primitive;
+pub mod print;
 pub mod recording;
 pub mod resolver;
 pub mod settings;
 pub mod specification;
+pub mod stack;
+pub mod streaming;
 pub mod string_table;
+#[cfg(test)]
+mod test_util;
 pub mod types;
 pub mod value;
+pub mod writer;
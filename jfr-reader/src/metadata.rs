@@ -96,7 +96,7 @@ pub struct MetadataHeader {
 }
 
 impl MetadataHeader {
-    pub fn parse(s: &[u8]) -> ParseResult<Self> {
+    pub fn parse(s: &[u8]) -> ParseResult<'_, Self> {
         let (s, size) = leb128_i32(s)?;
         let (s, event_type_id) = leb128_i64(s)?;
         let (s, start_time_nanoseconds) = leb128_i64(s)?;
@@ -137,7 +137,7 @@ pub struct ElementRecord {
 }
 
 impl ElementRecord {
-    pub fn parse(s: &[u8]) -> ParseResult<Self> {
+    pub fn parse(s: &[u8]) -> ParseResult<'_, Self> {
         let (s, name_index) = leb128_i32(s)?;
 
         let (s, attribute_count) = leb128_i32(s)?;
@@ -176,7 +176,7 @@ pub struct MetadataRecords<'a> {
 }
 
 impl<'a> MetadataRecords<'a> {
-    pub fn parse(s: &'a [u8]) -> ParseResult<Self> {
+    pub fn parse(s: &'a [u8]) -> ParseResult<'a, Self> {
         let (s, header) = context("parsing metadata event header", MetadataHeader::parse)(s)?;
 
         let (s, string_records) = context(
@@ -35,7 +35,7 @@ pub fn type_to_rust_type<'a>(m: &'a Metadata, name: &str) -> Option<(&'a str, bo
     if let Some(t) = m.find_type(name) {
         Some((t.name.as_str(), true))
     } else if let Some(xml_type) = m.find_xml_type(name) {
-        if let Some(java_type) = xml_type.java_type.as_ref().map(|x| x.as_str()) {
+        if let Some(java_type) = xml_type.java_type.as_deref() {
             match (java_type, xml_type.unsigned.unwrap_or_default()) {
                 ("byte", false) => Some(("i8", false)),
                 ("byte", true) => Some(("u8", false)),
@@ -167,8 +167,7 @@ pub fn event_struct(m: &Metadata, event: &Event) -> Result<TokenStream> {
             .fields
             .iter()
             .map(|field| struct_field(m, &event.name, field))
-            .collect::<Result<Vec<_>>>()?
-            .into_iter(),
+            .collect::<Result<Vec<_>>>()?,
     );
 
     Ok(quote! {
@@ -0,0 +1,640 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Conversion of execution samples to pprof profiles.
+//!
+//! [ProfileBuilder] walks `jdk.ExecutionSample` and `jdk.NativeMethodSample`
+//! events and produces a [pprof](https://github.com/google/pprof) profile,
+//! which can be consumed by `go tool pprof` and similar tools.
+//!
+//! Stack traces, frames, and methods are resolved from the untyped constant
+//! pool values so their constant pool indices are available. Stack traces and
+//! methods are cached by constant pool index within each chunk. Functions and
+//! locations are further deduplicated across chunks by content.
+//!
+//! The profile has 2 sample types: `execution` counting Java execution
+//! samples and `native` counting native method samples. Each sample is
+//! labeled with the name of the sampled thread.
+
+use crate::{
+    error::Result,
+    recording::{Recording, RecordingChunk},
+    resolver::ConstantPoolValues,
+    stack::{frame_line_number, resolve_method, stack_frames},
+    value::Value,
+};
+use rustc_hash::FxHashMap;
+use std::io::Write;
+
+/// Low-level protobuf encoding primitives.
+mod proto {
+    pub fn varint(buf: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        buf.push(v as u8);
+    }
+
+    fn key(buf: &mut Vec<u8>, field: u32, wire_type: u8) {
+        varint(buf, ((field as u64) << 3) | wire_type as u64);
+    }
+
+    /// Write an integer field. Zero values are omitted.
+    pub fn int(buf: &mut Vec<u8>, field: u32, v: i64) {
+        if v != 0 {
+            key(buf, field, 0);
+            varint(buf, v as u64);
+        }
+    }
+
+    /// Write a length delimited field.
+    pub fn bytes(buf: &mut Vec<u8>, field: u32, data: &[u8]) {
+        key(buf, field, 2);
+        varint(buf, data.len() as _);
+        buf.extend_from_slice(data);
+    }
+
+    /// Write a packed repeated integer field.
+    pub fn packed(buf: &mut Vec<u8>, field: u32, values: impl Iterator<Item = i64>) {
+        let mut data = vec![];
+        for v in values {
+            varint(&mut data, v as u64);
+        }
+
+        if !data.is_empty() {
+            bytes(buf, field, &data);
+        }
+    }
+}
+
+/// Sample type index for `jdk.ExecutionSample`.
+const EXECUTION: usize = 0;
+/// Sample type index for `jdk.NativeMethodSample`.
+const NATIVE: usize = 1;
+
+/// A pprof function.
+struct Function {
+    name: i64,
+    system_name: i64,
+}
+
+/// Key identifying an aggregated sample: location IDs and thread name string.
+type SampleKey = (Vec<u64>, i64);
+
+/// Builds a pprof profile from JFR execution samples.
+pub struct ProfileBuilder {
+    strings: Vec<String>,
+    string_indices: FxHashMap<String, i64>,
+    functions: Vec<Function>,
+    function_ids: FxHashMap<(i64, i64), u64>,
+    /// (function ID, line) for each location.
+    locations: Vec<(u64, i64)>,
+    location_ids: FxHashMap<(u64, i64), u64>,
+    samples: FxHashMap<SampleKey, [i64; 2]>,
+    start_nanoseconds: Option<u64>,
+    end_nanoseconds: Option<u64>,
+}
+
+impl Default for ProfileBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProfileBuilder {
+    /// Construct an empty instance.
+    pub fn new() -> Self {
+        Self {
+            // pprof requires the first string to be empty.
+            strings: vec![String::new()],
+            string_indices: FxHashMap::from_iter([(String::new(), 0)]),
+            functions: vec![],
+            function_ids: FxHashMap::default(),
+            locations: vec![],
+            location_ids: FxHashMap::default(),
+            samples: FxHashMap::default(),
+            start_nanoseconds: None,
+            end_nanoseconds: None,
+        }
+    }
+
+    fn string(&mut self, s: &str) -> i64 {
+        if let Some(index) = self.string_indices.get(s) {
+            *index
+        } else {
+            let index = self.strings.len() as i64;
+            self.strings.push(s.to_string());
+            self.string_indices.insert(s.to_string(), index);
+
+            index
+        }
+    }
+
+    fn function_id(&mut self, name: &str, system_name: &str) -> u64 {
+        let key = (self.string(name), self.string(system_name));
+
+        if let Some(id) = self.function_ids.get(&key) {
+            *id
+        } else {
+            self.functions.push(Function {
+                name: key.0,
+                system_name: key.1,
+            });
+            let id = self.functions.len() as u64;
+            self.function_ids.insert(key, id);
+
+            id
+        }
+    }
+
+    fn location_id(&mut self, function_id: u64, line: i64) -> u64 {
+        let key = (function_id, line);
+
+        if let Some(id) = self.location_ids.get(&key) {
+            *id
+        } else {
+            self.locations.push(key);
+            let id = self.locations.len() as u64;
+            self.location_ids.insert(key, id);
+
+            id
+        }
+    }
+
+    /// Resolve the location IDs of a `jdk.types.StackTrace`, leaf first.
+    fn stack_locations(
+        &mut self,
+        stack: &Value,
+        constants: &ConstantPoolValues,
+        functions: &mut FxHashMap<i64, u64>,
+    ) -> Vec<u64> {
+        let mut res = vec![];
+
+        for frame in stack_frames(stack, constants) {
+            let method = if let Some(method) = frame.field("method") {
+                method
+            } else {
+                continue;
+            };
+
+            let cached = if let Value::ConstantPool { constant_index, .. } = method {
+                functions.get(constant_index).copied()
+            } else {
+                None
+            };
+
+            let function_id = if let Some(id) = cached {
+                id
+            } else {
+                let id = if let Some(name) = resolve_method(method, constants) {
+                    self.function_id(
+                        &name.qualified_name(),
+                        &format!("{}{}", name.qualified_name(), name.descriptor),
+                    )
+                } else {
+                    self.function_id("<unknown>", "<unknown>")
+                };
+
+                if let Value::ConstantPool { constant_index, .. } = method {
+                    functions.insert(*constant_index, id);
+                }
+
+                id
+            };
+
+            let line = frame_line_number(frame).unwrap_or_default();
+
+            res.push(self.location_id(function_id, line));
+        }
+
+        res
+    }
+
+    /// Add samples from a chunk.
+    pub fn add_chunk(&mut self, chunk: &RecordingChunk) -> Result<()> {
+        let resolver = chunk.resolver();
+
+        let execution_id = resolver.class_id("jdk.ExecutionSample");
+        let native_id = resolver.class_id("jdk.NativeMethodSample");
+
+        if execution_id.is_none() && native_id.is_none() {
+            return Ok(());
+        }
+
+        let header = chunk.header();
+        let start = header.nanoseconds_since_epoch;
+        let end = start + header.duration_nanoseconds;
+        self.start_nanoseconds = Some(self.start_nanoseconds.map_or(start, |v| v.min(start)));
+        self.end_nanoseconds = Some(self.end_nanoseconds.map_or(end, |v| v.max(end)));
+
+        let constants = resolver.constant_pool_values()?;
+
+        // Constant pool indices are only meaningful within a chunk.
+        let mut stacks = FxHashMap::<i64, Vec<u64>>::default();
+        let mut functions = FxHashMap::<i64, u64>::default();
+        let mut threads = FxHashMap::<i64, i64>::default();
+
+        for event in chunk.iter_events() {
+            let event = event?;
+            let event_type = event.record().header.event_type;
+
+            let sample_type = if Some(event_type) == execution_id {
+                EXECUTION
+            } else if Some(event_type) == native_id {
+                NATIVE
+            } else {
+                continue;
+            };
+
+            let object = event.resolve_object()?;
+
+            let locations = match object.field("stackTrace") {
+                Some(Value::ConstantPool { constant_index, .. }) => {
+                    if let Some(locations) = stacks.get(constant_index) {
+                        locations.clone()
+                    } else {
+                        let stack = object.field("stackTrace").unwrap();
+                        let locations = self.stack_locations(stack, &constants, &mut functions);
+                        stacks.insert(*constant_index, locations.clone());
+
+                        locations
+                    }
+                }
+                Some(stack) => self.stack_locations(stack, &constants, &mut functions),
+                None => vec![],
+            };
+
+            let thread = match object.field("sampledThread") {
+                Some(v @ Value::ConstantPool { constant_index, .. }) => {
+                    if let Some(thread) = threads.get(constant_index) {
+                        *thread
+                    } else {
+                        let name = constants
+                            .deref(v)
+                            .and_then(|v| v.as_object())
+                            .and_then(|o| o.field("javaName").or_else(|| o.field("osName")))
+                            .and_then(|v| constants.resolve_str(v))
+                            .unwrap_or_default();
+                        let thread = self.string(name);
+                        threads.insert(*constant_index, thread);
+
+                        thread
+                    }
+                }
+                _ => 0,
+            };
+
+            self.samples.entry((locations, thread)).or_default()[sample_type] += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Add samples from all chunks in a recording.
+    pub fn add_recording<D: AsRef<[u8]>>(&mut self, recording: &Recording<D>) -> Result<()> {
+        for chunk in recording.iter_chunks() {
+            self.add_chunk(&chunk?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode the profile to uncompressed protobuf.
+    pub fn encode(mut self) -> Vec<u8> {
+        let sample_types = [
+            (self.string("execution"), self.string("count")),
+            (self.string("native"), self.string("count")),
+        ];
+        let thread_key = self.string("thread");
+
+        let mut buf = vec![];
+        let mut message = vec![];
+
+        for (t, unit) in sample_types {
+            message.clear();
+            proto::int(&mut message, 1, t);
+            proto::int(&mut message, 2, unit);
+            proto::bytes(&mut buf, 1, &message);
+        }
+
+        // Sort samples so output is deterministic.
+        let mut samples = self.samples.into_iter().collect::<Vec<_>>();
+        samples.sort_unstable();
+
+        let mut label = vec![];
+        for ((locations, thread), values) in samples {
+            message.clear();
+            proto::packed(&mut message, 1, locations.iter().map(|x| *x as i64));
+            proto::packed(&mut message, 2, values.into_iter());
+
+            if thread != 0 {
+                label.clear();
+                proto::int(&mut label, 1, thread_key);
+                proto::int(&mut label, 2, thread);
+                proto::bytes(&mut message, 3, &label);
+            }
+
+            proto::bytes(&mut buf, 2, &message);
+        }
+
+        let mut line = vec![];
+        for (index, (function_id, line_number)) in self.locations.iter().enumerate() {
+            message.clear();
+            proto::int(&mut message, 1, index as i64 + 1);
+
+            line.clear();
+            proto::int(&mut line, 1, *function_id as i64);
+            proto::int(&mut line, 2, *line_number);
+            proto::bytes(&mut message, 4, &line);
+
+            proto::bytes(&mut buf, 4, &message);
+        }
+
+        for (index, function) in self.functions.iter().enumerate() {
+            message.clear();
+            proto::int(&mut message, 1, index as i64 + 1);
+            proto::int(&mut message, 2, function.name);
+            proto::int(&mut message, 3, function.system_name);
+            proto::bytes(&mut buf, 5, &message);
+        }
+
+        for s in &self.strings {
+            proto::bytes(&mut buf, 6, s.as_bytes());
+        }
+
+        if let (Some(start), Some(end)) = (self.start_nanoseconds, self.end_nanoseconds) {
+            proto::int(&mut buf, 9, start as i64);
+            proto::int(&mut buf, 10, (end - start) as i64);
+        }
+
+        buf
+    }
+
+    /// Write the gzip compressed profile, as expected by `go tool pprof`.
+    pub fn write_gzip(self, dest: impl Write) -> Result<()> {
+        let mut encoder = flate2::write::GzEncoder::new(dest, flate2::Compression::default());
+        encoder.write_all(&self.encode())?;
+        encoder.finish()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        test_util::{
+            add_method, add_stack_classes, add_stack_trace, add_thread, constant, field, long,
+            writer, LONG_ID, STACK_TRACE_ID, THREAD_ID,
+        },
+        writer::primitive_class,
+    };
+
+    const EXECUTION_SAMPLE_ID: i64 = 101;
+    const NATIVE_SAMPLE_ID: i64 = 102;
+
+    /// A decoded protobuf field value.
+    #[derive(Clone, Copy, Debug)]
+    enum Field<'a> {
+        Varint(u64),
+        Bytes(&'a [u8]),
+    }
+
+    impl<'a> Field<'a> {
+        fn int(&self) -> i64 {
+            match self {
+                Self::Varint(v) => *v as i64,
+                Self::Bytes(_) => panic!("expected varint"),
+            }
+        }
+
+        fn bytes(&self) -> &'a [u8] {
+            match self {
+                Self::Bytes(v) => v,
+                Self::Varint(_) => panic!("expected length delimited"),
+            }
+        }
+
+        fn message(&self) -> Vec<(u32, Field<'a>)> {
+            decode(self.bytes())
+        }
+
+        fn packed(&self) -> Vec<i64> {
+            let mut data = self.bytes();
+            let mut res = vec![];
+            while !data.is_empty() {
+                res.push(read_varint(&mut data) as i64);
+            }
+
+            res
+        }
+    }
+
+    fn read_varint(data: &mut &[u8]) -> u64 {
+        let mut v = 0;
+        for shift in (0..64).step_by(7) {
+            let b = data[0];
+            *data = &data[1..];
+            v |= ((b & 0x7f) as u64) << shift;
+
+            if b < 0x80 {
+                break;
+            }
+        }
+
+        v
+    }
+
+    fn decode(mut data: &[u8]) -> Vec<(u32, Field<'_>)> {
+        let mut res = vec![];
+
+        while !data.is_empty() {
+            let key = read_varint(&mut data);
+            let value = match key & 7 {
+                0 => Field::Varint(read_varint(&mut data)),
+                2 => {
+                    let len = read_varint(&mut data) as usize;
+                    let (value, remaining) = data.split_at(len);
+                    data = remaining;
+                    Field::Bytes(value)
+                }
+                t => panic!("unexpected wire type {}", t),
+            };
+
+            res.push(((key >> 3) as u32, value));
+        }
+
+        res
+    }
+
+    /// The integer value of a message field, defaulting to 0.
+    fn int(message: &[(u32, Field)], field: u32) -> i64 {
+        message
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, v)| v.int())
+            .unwrap_or_default()
+    }
+
+    fn recording() -> Result<Recording<Vec<u8>>> {
+        let mut w = writer();
+        add_stack_classes(&mut w);
+
+        for (id, name) in [
+            (EXECUTION_SAMPLE_ID, "jdk.ExecutionSample"),
+            (NATIVE_SAMPLE_ID, "jdk.NativeMethodSample"),
+        ] {
+            let mut event = primitive_class(id, name);
+            event.fields = vec![
+                field("startTime", LONG_ID, false),
+                field("sampledThread", THREAD_ID, true),
+                field("stackTrace", STACK_TRACE_ID, true),
+            ];
+            w.add_class(event);
+        }
+
+        add_thread(&mut w, 1, "main")?;
+        add_method(
+            &mut w,
+            1,
+            "com/example/App",
+            "main",
+            "([Ljava/lang/String;)V",
+        )?;
+        add_method(&mut w, 2, "com/example/App", "work", "(I)J")?;
+        add_stack_trace(&mut w, 1, &[(2, 20), (1, 10)])?;
+        add_stack_trace(&mut w, 2, &[(1, 11)])?;
+
+        for (event, stack) in [
+            (EXECUTION_SAMPLE_ID, 1),
+            (EXECUTION_SAMPLE_ID, 2),
+            (EXECUTION_SAMPLE_ID, 1),
+            (NATIVE_SAMPLE_ID, 1),
+        ] {
+            w.write_event(
+                event,
+                &[
+                    long(0),
+                    constant(THREAD_ID, 1),
+                    constant(STACK_TRACE_ID, stack),
+                ],
+            )?;
+        }
+
+        Ok(Recording::from_data(w.finish()?))
+    }
+
+    #[test]
+    fn encode() -> Result<()> {
+        let mut builder = ProfileBuilder::new();
+        builder.add_recording(&recording()?)?;
+        let profile = builder.encode();
+        let profile = decode(&profile);
+
+        let strings = profile
+            .iter()
+            .filter(|(f, _)| *f == 6)
+            .map(|(_, v)| std::str::from_utf8(v.bytes()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(strings[0], "");
+        let mut unique = strings.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), strings.len());
+
+        let string = |index: i64| strings[index as usize];
+        let messages = |field: u32| {
+            profile
+                .iter()
+                .filter(move |(f, _)| *f == field)
+                .map(|(_, v)| v.message())
+        };
+
+        let sample_types = messages(1)
+            .map(|m| (string(int(&m, 1)), string(int(&m, 2))))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sample_types,
+            vec![("execution", "count"), ("native", "count")]
+        );
+
+        let functions = messages(5)
+            .map(|m| (int(&m, 1), (string(int(&m, 2)), string(int(&m, 3)))))
+            .collect::<FxHashMap<_, _>>();
+        assert_eq!(functions.len(), 2);
+        assert!(functions.values().any(|f| f
+            == &(
+                "com.example.App.main",
+                "com.example.App.main([Ljava/lang/String;)V"
+            )));
+        assert!(functions
+            .values()
+            .any(|f| f == &("com.example.App.work", "com.example.App.work(I)J")));
+
+        let locations = messages(4)
+            .map(|m| {
+                let line = m
+                    .iter()
+                    .find(|(f, _)| *f == 4)
+                    .map(|(_, v)| v.message())
+                    .expect("location has a line");
+
+                (
+                    int(&m, 1),
+                    format!("{}:{}", functions[&int(&line, 1)].0, int(&line, 2)),
+                )
+            })
+            .collect::<FxHashMap<_, _>>();
+        assert_eq!(locations.len(), 3);
+
+        let mut samples = messages(2)
+            .map(|m| {
+                let field = |field: u32| m.iter().find(|(f, _)| *f == field).map(|(_, v)| *v);
+
+                let stack = field(1)
+                    .map(|v| v.packed())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|id| locations[&id].clone())
+                    .collect::<Vec<_>>();
+                let values = field(2).map(|v| v.packed()).unwrap_or_default();
+                let label = field(3).expect("sample has a label").message();
+
+                (
+                    stack,
+                    values,
+                    (string(int(&label, 1)), string(int(&label, 2))),
+                )
+            })
+            .collect::<Vec<_>>();
+        samples.sort();
+
+        assert_eq!(
+            samples,
+            vec![
+                (
+                    vec!["com.example.App.main:11".to_string()],
+                    vec![1, 0],
+                    ("thread", "main")
+                ),
+                (
+                    vec![
+                        "com.example.App.work:20".to_string(),
+                        "com.example.App.main:10".to_string()
+                    ],
+                    vec![2, 1],
+                    ("thread", "main")
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -18,49 +18,49 @@ use {
     std::borrow::Cow,
 };
 
-pub fn parse_boolean(s: &[u8]) -> ParseResult<bool> {
+pub fn parse_boolean(s: &[u8]) -> ParseResult<'_, bool> {
     let (s, v) = be_i8(s)?;
     Ok((s, v != 0))
 }
 
-pub fn parse_float(s: &[u8]) -> ParseResult<f32> {
+pub fn parse_float(s: &[u8]) -> ParseResult<'_, f32> {
     let (s, v) = be_f32(s)?;
     Ok((s, v))
 }
 
-pub fn parse_double(s: &[u8]) -> ParseResult<f64> {
+pub fn parse_double(s: &[u8]) -> ParseResult<'_, f64> {
     let (s, v) = be_f64(s)?;
     Ok((s, v))
 }
 
-pub fn parse_byte(s: &[u8]) -> ParseResult<i8> {
+pub fn parse_byte(s: &[u8]) -> ParseResult<'_, i8> {
     let (s, v) = be_i8(s)?;
     Ok((s, v))
 }
 
-pub fn parse_short(s: &[u8]) -> ParseResult<i16> {
+pub fn parse_short(s: &[u8]) -> ParseResult<'_, i16> {
     let (s, v) = leb128_i16(s)?;
     Ok((s, v))
 }
 
-pub fn parse_int(s: &[u8]) -> ParseResult<i32> {
+pub fn parse_int(s: &[u8]) -> ParseResult<'_, i32> {
     let (s, v) = leb128_i32(s)?;
     Ok((s, v))
 }
 
-pub fn parse_long(s: &[u8]) -> ParseResult<i64> {
+pub fn parse_long(s: &[u8]) -> ParseResult<'_, i64> {
     let (s, v) = leb128_i64(s)?;
     Ok((s, v))
 }
 
-pub fn parse_java_lang_string(s: &[u8]) -> ParseResult<StringValue> {
+pub fn parse_java_lang_string(s: &[u8]) -> ParseResult<'_, StringValue<'_>> {
     let (s, record) = StringRecord::parse(s)?;
     let (_, v) = record.resolve()?;
 
     Ok((s, v))
 }
 
-pub fn parse_char(s: &[u8]) -> ParseResult<char> {
+pub fn parse_char(s: &[u8]) -> ParseResult<'_, char> {
     let (s, v) = leb128_i32(s)?;
 
     let v = char::try_from(v as u32)
@@ -69,6 +69,9 @@ pub fn parse_char(s: &[u8]) -> ParseResult<char> {
     Ok((s, v))
 }
 
+/// A function that parses a [Primitive] from data.
+pub type PrimitiveParser<'a> = fn(&'a [u8]) -> ParseResult<'a, Primitive<'a>>;
+
 /// A Java primitive value.
 #[derive(Clone, Debug)]
 pub enum Primitive<'a> {
@@ -190,7 +193,7 @@ impl<'a> Primitive<'a> {
     }
 
     /// Resolve a parser function for a primitive value, if available.
-    pub fn resolve_parser(name: &str) -> Option<fn(&'a [u8]) -> ParseResult<'a, Primitive<'a>>> {
+    pub fn resolve_parser(name: &str) -> Option<PrimitiveParser<'a>> {
         match name {
             "boolean" => Some(Self::parse_boolean),
             "char" => Some(Self::parse_char),
@@ -257,6 +260,17 @@ impl<'a> Primitive<'a> {
         }
     }
 
+    /// Obtain the value of any integer variant widened to an i64.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Byte(v) => Some(*v as _),
+            Self::Short(v) => Some(*v as _),
+            Self::Integer(v) => Some(*v as _),
+            Self::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+
     pub fn as_float(&self) -> Option<f32> {
         if let Self::Float(v) = self {
             Some(*v)
@@ -0,0 +1,1022 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Human readable rendering of events, metadata, and recording summaries.
+//!
+//! The output formats approximate those of the JDK's `jfr print`,
+//! `jfr metadata`, and `jfr summary` commands.
+//!
+//! Field values are formatted according to their annotations. e.g.
+//! `@Timestamp` fields are rendered as times, `@Timespan` fields as
+//! durations, and `@DataAmount` fields as byte sizes. Common types such as
+//! threads, methods, and stack traces have compact renderings.
+
+use crate::{
+    annotations::{AnnotationValue, DataAmount, Timespan, Timestamp},
+    chunk::ChunkReader,
+    error::Result,
+    metadata::{AnnotationElement, ClassElement},
+    primitive::Primitive,
+    recording::{Recording, RecordingEvent},
+    resolver::{ConstantPoolValues, EventResolver, TimeResolver},
+    stack::{frame_line_number, resolve_method, stack_frames},
+    value::{Object, Value},
+};
+use chrono::TimeZone;
+use rustc_hash::FxHashMap;
+use std::{collections::BTreeMap, fmt::Write as _, io::Write};
+
+/// How a field value should be formatted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum FieldFormat {
+    Plain,
+    TimestampTicks,
+    TimestampMilliseconds,
+    /// A duration. Value is the number of nanoseconds per unit or 0 for ticks.
+    Timespan(i64),
+    Bytes,
+    Percentage,
+    Frequency,
+    MemoryAddress,
+    Unsigned,
+}
+
+/// Resolve the [FieldFormat] from field annotations.
+fn field_format(resolver: &EventResolver, annotations: &[AnnotationElement]) -> FieldFormat {
+    let mut format = FieldFormat::Plain;
+
+    for a in annotations {
+        let value = if let Some(class) = resolver.get_class(a.type_id) {
+            if let Ok(value) = AnnotationValue::from_elements(a, class) {
+                value
+            } else {
+                continue;
+            }
+        } else {
+            continue;
+        };
+
+        format = match value {
+            AnnotationValue::Timestamp(Timestamp::Ticks) => FieldFormat::TimestampTicks,
+            AnnotationValue::Timestamp(Timestamp::MillisecondsSinceEpoch) => {
+                FieldFormat::TimestampMilliseconds
+            }
+            AnnotationValue::Timespan(Timespan::Ticks) => FieldFormat::Timespan(0),
+            AnnotationValue::Timespan(Timespan::Nanoseconds) => FieldFormat::Timespan(1),
+            AnnotationValue::Timespan(Timespan::Microseconds) => FieldFormat::Timespan(1_000),
+            AnnotationValue::Timespan(Timespan::Milliseconds) => FieldFormat::Timespan(1_000_000),
+            AnnotationValue::Timespan(Timespan::Seconds) => FieldFormat::Timespan(1_000_000_000),
+            AnnotationValue::DataAmount(DataAmount::Bytes) => FieldFormat::Bytes,
+            AnnotationValue::Percentage(_) => FieldFormat::Percentage,
+            AnnotationValue::Frequency(_) => FieldFormat::Frequency,
+            AnnotationValue::MemoryAddress(_) => FieldFormat::MemoryAddress,
+            AnnotationValue::Unsigned(_) if format == FieldFormat::Plain => FieldFormat::Unsigned,
+            _ => continue,
+        };
+    }
+
+    format
+}
+
+/// Resolve the [FieldFormat] of every field in a chunk having one.
+///
+/// Keys are the class ID and field index.
+pub(crate) fn field_formats(resolver: &EventResolver) -> FxHashMap<(i64, usize), FieldFormat> {
+    let mut formats = FxHashMap::default();
+
+    for (class_id, _) in resolver.class_ids_and_names() {
+        if let Some(class) = resolver.get_class(class_id) {
+            for (index, field) in class.fields.iter().enumerate() {
+                let format = field_format(resolver, &field.annotations);
+
+                if format != FieldFormat::Plain {
+                    formats.insert((class_id, index), format);
+                }
+            }
+        }
+    }
+
+    formats
+}
+
+/// Format a duration in nanoseconds.
+pub fn format_duration(nanos: i64) -> String {
+    let abs = nanos.unsigned_abs();
+
+    if abs < 1_000 {
+        format!("{} ns", nanos)
+    } else if abs < 1_000_000 {
+        format!("{:.3} us", nanos as f64 / 1_000.0)
+    } else if abs < 1_000_000_000 {
+        format!("{:.3} ms", nanos as f64 / 1_000_000.0)
+    } else {
+        format!("{:.3} s", nanos as f64 / 1_000_000_000.0)
+    }
+}
+
+/// Format a byte count.
+pub fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+
+    if bytes.unsigned_abs() < 1024 {
+        return format!("{} bytes", bytes);
+    }
+
+    let mut v = bytes as f64;
+    let mut unit = "";
+
+    for u in UNITS {
+        if v.abs() < 1024.0 {
+            break;
+        }
+
+        v /= 1024.0;
+        unit = u;
+    }
+
+    format!("{:.1} {}", v, unit)
+}
+
+/// Renders events in the style of `jfr print`.
+///
+/// Instances are bound to a single chunk.
+pub struct EventPrinter<'r, 'a> {
+    resolver: &'r EventResolver<'a>,
+    constants: ConstantPoolValues<'r>,
+    formats: FxHashMap<(i64, usize), FieldFormat>,
+    stack_depth: usize,
+}
+
+impl<'r, 'a> EventPrinter<'r, 'a> {
+    /// The default number of stack frames to render.
+    pub const DEFAULT_STACK_DEPTH: usize = 5;
+
+    /// Construct an instance for the chunk having the given resolver.
+    pub fn new(resolver: &'r EventResolver<'a>) -> Result<Self> {
+        let constants = resolver.constant_pool_values()?;
+
+        Ok(Self {
+            resolver,
+            constants,
+            formats: field_formats(resolver),
+            stack_depth: Self::DEFAULT_STACK_DEPTH,
+        })
+    }
+
+    /// Set the maximum number of stack frames to render.
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack_depth = depth;
+    }
+
+    fn time_resolver(&self) -> &TimeResolver {
+        self.resolver.time_resolver()
+    }
+
+    /// Render an event.
+    pub fn write_event(&self, dest: &mut impl Write, event: &RecordingEvent) -> Result<()> {
+        let object = event.resolve_object()?;
+
+        let mut s = String::new();
+        self.format_object_fields(&mut s, object.class(), object.iter_fields(), 0);
+
+        writeln!(dest, "{} {{", object.class().name)?;
+        dest.write_all(s.as_bytes())?;
+        writeln!(dest, "}}")?;
+        writeln!(dest)?;
+
+        Ok(())
+    }
+
+    fn format_object_fields<'v>(
+        &self,
+        s: &mut String,
+        class: &ClassElement,
+        values: impl Iterator<Item = &'v Value<'v>>,
+        indent: usize,
+    ) {
+        // Like the JDK, the event thread and stack trace are rendered after
+        // other fields and are omitted when absent, as is a zero duration.
+        let mut fields = class
+            .fields
+            .iter()
+            .zip(values)
+            .enumerate()
+            .collect::<Vec<_>>();
+        fields.sort_by_key(|(_, (field, _))| match field.name.as_ref() {
+            "eventThread" => 1,
+            "stackTrace" => 2,
+            _ => 0,
+        });
+
+        for (index, (field, value)) in fields {
+            if indent == 0 {
+                match (field.name.as_ref(), value) {
+                    ("eventThread" | "stackTrace", _) if self.constants.deref(value).is_none() => {
+                        continue
+                    }
+                    ("duration", Value::Primitive(p)) if p.as_i64() == Some(0) => continue,
+                    _ => {}
+                }
+            }
+
+            let format = self
+                .formats
+                .get(&(class.id, index))
+                .copied()
+                .unwrap_or(FieldFormat::Plain);
+
+            let _ = write!(s, "{:width$}{} = ", "", field.name, width = indent + 2);
+            self.format_value(s, value, format, indent + 2);
+            s.push('\n');
+        }
+    }
+
+    /// Resolve the Java source name of a `java.lang.Class`, e.g. `byte[]`.
+    fn class_name(&self, class: &Object) -> String {
+        let name = class
+            .field("name")
+            .and_then(|v| self.constants.resolve_str(v))
+            .unwrap_or("N/A");
+
+        let dimensions = name.chars().take_while(|c| *c == '[').count();
+        if dimensions == 0 {
+            return name.replace('/', ".");
+        }
+
+        let element = match &name[dimensions..] {
+            "B" => "byte".to_string(),
+            "C" => "char".to_string(),
+            "D" => "double".to_string(),
+            "F" => "float".to_string(),
+            "I" => "int".to_string(),
+            "J" => "long".to_string(),
+            "S" => "short".to_string(),
+            "Z" => "boolean".to_string(),
+            element => element
+                .trim_start_matches('L')
+                .trim_end_matches(';')
+                .replace('/', "."),
+        };
+
+        format!("{}{}", element, "[]".repeat(dimensions))
+    }
+
+    fn format_primitive(&self, s: &mut String, p: &Primitive, format: FieldFormat) {
+        if let Some(v) = p.as_i64() {
+            let v = if format == FieldFormat::Unsigned {
+                match p {
+                    Primitive::Byte(x) => *x as u8 as i64,
+                    Primitive::Short(x) => *x as u16 as i64,
+                    Primitive::Integer(x) => *x as u32 as i64,
+                    _ => v,
+                }
+            } else {
+                v
+            };
+
+            let _ = match format {
+                FieldFormat::TimestampTicks => write!(
+                    s,
+                    "{}",
+                    self.time_resolver().date_time(v).format("%H:%M:%S%.3f")
+                ),
+                FieldFormat::TimestampMilliseconds => {
+                    match chrono::Utc.timestamp_millis_opt(v).single() {
+                        Some(t) => write!(s, "{}", t.format("%H:%M:%S%.3f")),
+                        None => write!(s, "{}", v),
+                    }
+                }
+                FieldFormat::Timespan(_) if v == i64::MIN || v == i64::MAX => {
+                    write!(s, "N/A")
+                }
+                FieldFormat::Timespan(0) => write!(
+                    s,
+                    "{}",
+                    format_duration(self.time_resolver().delta_nanoseconds(0, v))
+                ),
+                FieldFormat::Timespan(unit) => {
+                    write!(s, "{}", format_duration(v.saturating_mul(unit)))
+                }
+                FieldFormat::Bytes => write!(s, "{}", format_bytes(v)),
+                FieldFormat::Frequency => write!(s, "{} Hz", v),
+                FieldFormat::MemoryAddress => write!(s, "0x{:08X}", v),
+                _ => write!(s, "{}", v),
+            };
+
+            return;
+        }
+
+        let _ = match p {
+            Primitive::Boolean(v) => write!(s, "{}", v),
+            Primitive::Float(v) if format == FieldFormat::Percentage => {
+                write!(s, "{:.2}%", v * 100.0)
+            }
+            Primitive::Double(v) if format == FieldFormat::Percentage => {
+                write!(s, "{:.2}%", v * 100.0)
+            }
+            Primitive::Float(v) => write!(s, "{}", v),
+            Primitive::Double(v) => write!(s, "{}", v),
+            Primitive::Character(v) => write!(s, "'{}'", v),
+            Primitive::NullString => write!(s, "N/A"),
+            Primitive::String(v) => write!(s, "\"{}\"", v),
+            Primitive::StringConstantPool(_) => {
+                match self.constants.resolve_str(&Value::Primitive(p.clone())) {
+                    Some(v) => write!(s, "\"{}\"", v),
+                    None => write!(s, "N/A"),
+                }
+            }
+            _ => Ok(()),
+        };
+    }
+
+    fn format_value(&self, s: &mut String, value: &Value, format: FieldFormat, indent: usize) {
+        let constant_index = match value {
+            Value::ConstantPool { constant_index, .. } => Some(*constant_index),
+            _ => None,
+        };
+
+        let value = if let Some(v) = self.constants.deref(value) {
+            v
+        } else {
+            s.push_str("N/A");
+            return;
+        };
+
+        match value {
+            Value::Primitive(p) => self.format_primitive(s, p, format),
+            Value::Array(values) => {
+                s.push_str("[\n");
+                for v in values {
+                    let _ = write!(s, "{:width$}", "", width = indent + 2);
+                    self.format_value(s, v, format, indent + 2);
+                    s.push('\n');
+                }
+                let _ = write!(s, "{:width$}]", "", width = indent);
+            }
+            Value::Object(o) => match o.class().name.as_ref() {
+                "jdk.types.StackTrace" => {
+                    s.push_str("[\n");
+
+                    let mut frames = stack_frames(value, &self.constants);
+                    for frame in frames.by_ref().take(self.stack_depth) {
+                        let name = frame
+                            .field("method")
+                            .and_then(|m| resolve_method(m, &self.constants));
+
+                        let _ = write!(s, "{:width$}", "", width = indent + 2);
+                        let _ = match name {
+                            Some(name) => {
+                                write!(s, "{}({})", name.qualified_name(), name.java_parameters())
+                            }
+                            None => write!(s, "<unknown>"),
+                        };
+
+                        if let Some(line) = frame_line_number(frame) {
+                            let _ = write!(s, " line: {}", line);
+                        }
+                        s.push('\n');
+                    }
+
+                    let truncated = matches!(
+                        o.field("truncated").and_then(|v| v.as_primitive()),
+                        Some(Primitive::Boolean(true))
+                    );
+                    if frames.next().is_some() || truncated {
+                        let _ = writeln!(s, "{:width$}...", "", width = indent + 2);
+                    }
+
+                    let _ = write!(s, "{:width$}]", "", width = indent);
+                }
+                "java.lang.Thread" => {
+                    let name = o
+                        .field("javaName")
+                        .and_then(|v| self.constants.resolve_str(v));
+                    let (name, id_field) = match name {
+                        Some(name) => (name, "javaThreadId"),
+                        None => (
+                            o.field("osName")
+                                .and_then(|v| self.constants.resolve_str(v))
+                                .unwrap_or_default(),
+                            "osThreadId",
+                        ),
+                    };
+                    let id = o
+                        .field(id_field)
+                        .and_then(|v| v.as_primitive())
+                        .and_then(|p| p.as_i64())
+                        .unwrap_or_default();
+
+                    let _ = write!(s, "\"{}\" ({} = {})", name, id_field, id);
+                }
+                "jdk.types.Method" => match resolve_method(value, &self.constants) {
+                    Some(name) => {
+                        let _ = write!(s, "{}({})", name.qualified_name(), name.java_parameters());
+                    }
+                    None => s.push_str("N/A"),
+                },
+                "java.lang.Class" => {
+                    let name = self.class_name(o);
+                    let loader = o
+                        .field("classLoader")
+                        .and_then(|v| self.constants.deref(v))
+                        .and_then(|v| v.as_object())
+                        .and_then(|o| o.field("name"))
+                        .and_then(|v| self.constants.resolve_str(v))
+                        .unwrap_or("null");
+
+                    let _ = write!(s, "{} (classLoader = {})", name, loader);
+                }
+                "jdk.types.ClassLoader" => {
+                    let name = o
+                        .field("type")
+                        .and_then(|v| self.constants.deref(v))
+                        .and_then(|v| v.as_object())
+                        .map(|o| self.class_name(o));
+
+                    match (name, constant_index) {
+                        (Some(name), Some(id)) => {
+                            let _ = write!(s, "{} (id = {})", name, id);
+                        }
+                        (Some(name), None) => s.push_str(&name),
+                        (None, _) => s.push_str("null"),
+                    }
+                }
+                "jdk.types.Symbol" => match self.constants.resolve_str(value) {
+                    Some(v) => {
+                        let _ = write!(s, "\"{}\"", v);
+                    }
+                    None => s.push_str("N/A"),
+                },
+                _ => {
+                    // Objects wrapping a single value, like enumerations, are
+                    // rendered as that value.
+                    if o.class().fields.len() == 1 {
+                        if let Some(v) = o.field_at(0) {
+                            self.format_value(s, v, format, indent);
+                            return;
+                        }
+                    }
+
+                    s.push_str("{\n");
+                    self.format_object_fields(s, o.class(), o.iter_fields(), indent);
+                    let _ = write!(s, "{:width$}}}", "", width = indent);
+                }
+            },
+            Value::ConstantPool { .. } | Value::ConstantPoolNull => s.push_str("N/A"),
+        }
+    }
+}
+
+/// Render an annotation in Java syntax.
+fn format_annotation(resolver: &EventResolver, annotation: &AnnotationElement) -> String {
+    let name = resolver.class_name(annotation.type_id).unwrap_or("Unknown");
+    let name = name.rsplit('.').next().unwrap_or(name);
+    let values = &annotation.values;
+
+    if values.is_empty() {
+        format!("@{}", name)
+    } else if values.len() == 1 && values[0].0 == "value" {
+        format!("@{}(\"{}\")", name, values[0].1)
+    } else if values.iter().all(|(k, _)| k.starts_with("value-")) {
+        let values = values
+            .iter()
+            .map(|(_, v)| format!("\"{}\"", v))
+            .collect::<Vec<_>>();
+        format!("@{}({{{}}})", name, values.join(", "))
+    } else {
+        let values = values
+            .iter()
+            .map(|(k, v)| format!("{} = \"{}\"", k, v))
+            .collect::<Vec<_>>();
+        format!("@{}({})", name, values.join(", "))
+    }
+}
+
+/// Render a class definition in the style of `jfr metadata`.
+///
+/// Settings, which `jfr metadata` omits, are rendered as `setting` members.
+pub fn write_class(
+    dest: &mut impl Write,
+    resolver: &EventResolver,
+    class: &ClassElement,
+) -> Result<()> {
+    // Like the JDK, always show the full name.
+    if !class
+        .annotations
+        .iter()
+        .any(|a| resolver.class_name(a.type_id) == Some("jdk.jfr.Name"))
+    {
+        writeln!(dest, "@Name(\"{}\")", class.name)?;
+    }
+    for a in &class.annotations {
+        writeln!(dest, "{}", format_annotation(resolver, a))?;
+    }
+
+    let simple_name = class.name.rsplit('.').next().unwrap_or_default();
+    write!(dest, "class {}", simple_name)?;
+    if let Some(super_type) = &class.super_type {
+        write!(dest, " extends {}", super_type)?;
+    }
+    writeln!(dest, " {{")?;
+
+    for field in &class.fields {
+        for a in &field.annotations {
+            writeln!(dest, "  {}", format_annotation(resolver, a))?;
+        }
+
+        writeln!(
+            dest,
+            "  {}{} {};",
+            resolver.class_name(field.type_id).unwrap_or("unknown"),
+            "[]".repeat(field.dimension.unwrap_or_default().max(0) as usize),
+            field.name
+        )?;
+        writeln!(dest)?;
+    }
+
+    for setting in &class.settings {
+        for a in &setting.annotations {
+            writeln!(dest, "  {}", format_annotation(resolver, a))?;
+        }
+
+        writeln!(
+            dest,
+            "  setting {} {} = \"{}\";",
+            resolver.class_name(setting.type_id).unwrap_or("unknown"),
+            setting.name,
+            setting.default_value
+        )?;
+        writeln!(dest)?;
+    }
+
+    writeln!(dest, "}}")?;
+    writeln!(dest)?;
+
+    Ok(())
+}
+
+/// Per event type counts and sizes.
+type EventStats = BTreeMap<String, (u64, u64)>;
+
+fn write_stats(dest: &mut impl Write, stats: &EventStats) -> Result<()> {
+    let mut stats = stats.iter().collect::<Vec<_>>();
+    stats.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(b.0)));
+
+    writeln!(
+        dest,
+        " {:<40} {:>10} {:>14}",
+        "Event Type", "Count", "Size (bytes)"
+    )?;
+    writeln!(dest, "{}", "=".repeat(67))?;
+    for (name, (count, size)) in stats {
+        writeln!(dest, " {:<40} {:>10} {:>14}", name, count, size)?;
+    }
+
+    Ok(())
+}
+
+/// Render a summary of a recording in the style of `jfr summary`.
+///
+/// Event counts and sizes are reported for the recording as a whole and,
+/// if `per_chunk` is set, for each chunk.
+pub fn write_summary<D: AsRef<[u8]>>(
+    dest: &mut impl Write,
+    recording: &Recording<D>,
+    per_chunk: bool,
+) -> Result<()> {
+    let mut total = EventStats::new();
+    let mut chunks = vec![];
+    let mut start = None;
+    let mut end = None;
+    let mut version = None;
+
+    for chunk in recording.iter_chunks() {
+        let chunk = chunk?;
+        let resolver = chunk.resolver();
+        let header = chunk.header();
+
+        version.get_or_insert((header.major, header.minor));
+        let chunk_start = header.nanoseconds_since_epoch;
+        let chunk_end = chunk_start + header.duration_nanoseconds;
+        start = Some(start.map_or(chunk_start, |v: u64| v.min(chunk_start)));
+        end = Some(end.map_or(chunk_end, |v: u64| v.max(chunk_end)));
+
+        // Like the JDK, report every event type, even those without events.
+        let mut stats = EventStats::new();
+        for (id, name) in resolver.class_ids_and_names() {
+            if resolver.get_class(id).and_then(|c| c.super_type.as_deref()) == Some("jdk.jfr.Event")
+            {
+                stats.insert(name.to_string(), (0, 0));
+            }
+        }
+
+        for record in chunk.reader().event_records() {
+            let header = record?.header;
+
+            let name = match header.event_type {
+                0 => "jdk.Metadata",
+                1 => "jdk.CheckPoint",
+                id => resolver.class_name(id).unwrap_or("unknown"),
+            };
+
+            let entry = stats.entry(name.to_string()).or_default();
+            entry.0 += 1;
+            entry.1 += header.size as u64;
+        }
+
+        for (name, (count, size)) in &stats {
+            let entry = total.entry(name.clone()).or_default();
+            entry.0 += count;
+            entry.1 += size;
+        }
+
+        chunks.push(stats);
+    }
+
+    writeln!(dest)?;
+    if let Some((major, minor)) = version {
+        writeln!(dest, " Version: {}.{}", major, minor)?;
+    }
+    writeln!(dest, " Chunks: {}", chunks.len())?;
+    if let (Some(start), Some(end)) = (start, end) {
+        let start_time = chrono::Utc.timestamp_nanos(start as i64);
+        writeln!(
+            dest,
+            " Start: {} (UTC)",
+            start_time.format("%Y-%m-%d %H:%M:%S")
+        )?;
+        writeln!(dest, " Duration: {}", format_duration((end - start) as i64))?;
+    }
+    writeln!(dest)?;
+
+    write_stats(dest, &total)?;
+
+    if per_chunk {
+        for (index, stats) in chunks.iter().enumerate() {
+            writeln!(dest)?;
+            writeln!(dest, " Chunk {}", index)?;
+            writeln!(dest)?;
+            write_stats(dest, stats)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Render the header of each chunk in a recording.
+pub fn write_chunks<D: AsRef<[u8]>>(dest: &mut impl Write, recording: &Recording<D>) -> Result<()> {
+    let mut offset = 0;
+
+    for (index, reader) in recording.iter_chunk_readers().enumerate() {
+        let reader = reader?;
+        let header = reader.header();
+        let metadata = reader.metadata()?;
+
+        let start = metadata
+            .root
+            .region
+            .fixed_offset()?
+            .timestamp_nanos(header.nanoseconds_since_epoch as _);
+
+        writeln!(dest, "Chunk {} (offset {})", index, offset)?;
+        writeln!(dest, "  Version: {}.{}", header.major, header.minor)?;
+        writeln!(dest, "  Size: {}", header.chunk_size)?;
+        writeln!(
+            dest,
+            "  Constant Pool Position: {}",
+            header.constant_pool_position
+        )?;
+        writeln!(dest, "  Metadata Position: {}", header.metadata_position)?;
+        writeln!(
+            dest,
+            "  Start: {} ({} ns since epoch)",
+            start.to_rfc3339(),
+            header.nanoseconds_since_epoch
+        )?;
+        writeln!(
+            dest,
+            "  Duration: {} ({} ns)",
+            format_duration(header.duration_nanoseconds as i64),
+            header.duration_nanoseconds
+        )?;
+        writeln!(dest, "  Start Ticks: {}", header.start_ticks)?;
+        writeln!(dest, "  Ticks Per Second: {}", header.ticks_per_second)?;
+        writeln!(
+            dest,
+            "  State: {}",
+            if header.is_finished() {
+                "finished"
+            } else if header.is_updating() {
+                "updating"
+            } else {
+                "in progress"
+            }
+        )?;
+        writeln!(dest, "  Flags: 0x{:02x}", header.flags())?;
+        writeln!(dest, "  Final Chunk: {}", header.is_final_chunk())?;
+        writeln!(dest)?;
+
+        offset += header.chunk_size;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::{
+            chunk::ChunkHeader,
+            metadata::{FieldElement, SettingsElement},
+            stack::MethodName,
+            test_util::{
+                add_annotation_classes, add_method, add_stack_classes, add_stack_trace, add_thread,
+                annotation, constant, field, long, string, writer, BOOLEAN_ID, DATA_AMOUNT_ID,
+                LABEL_ID, LONG_ID, STACK_TRACE_ID, STRING_ID, THREAD_ID, TIMESPAN_ID, TIMESTAMP_ID,
+            },
+            writer::primitive_class,
+        },
+        std::borrow::Cow,
+    };
+
+    const EVENT_ID: i64 = 101;
+
+    /// 2023-11-14 22:13:20 UTC.
+    const START_NANOSECONDS: u64 = 1_700_000_000_000_000_000;
+
+    /// A chunk holding 2 `test.Event` events.
+    fn chunk(final_chunk: bool) -> Result<Vec<u8>> {
+        let mut w = writer();
+        w.set_nanoseconds_since_epoch(START_NANOSECONDS);
+        w.set_duration_nanoseconds(2_500_000_000);
+        w.set_final_chunk(final_chunk);
+        add_stack_classes(&mut w);
+        add_annotation_classes(&mut w);
+
+        let mut event = primitive_class(EVENT_ID, "test.Event");
+        event.super_type = Some(Cow::Borrowed("jdk.jfr.Event"));
+        event.annotations = vec![annotation(LABEL_ID, "Test Event")];
+        event.fields = vec![
+            FieldElement {
+                annotations: vec![annotation(TIMESTAMP_ID, "TICKS")],
+                ..field("startTime", LONG_ID, false)
+            },
+            FieldElement {
+                annotations: vec![annotation(TIMESPAN_ID, "TICKS")],
+                ..field("duration", LONG_ID, false)
+            },
+            field("eventThread", THREAD_ID, true),
+            field("stackTrace", STACK_TRACE_ID, true),
+            FieldElement {
+                annotations: vec![annotation(DATA_AMOUNT_ID, "BYTES")],
+                ..field("size", LONG_ID, false)
+            },
+            field("message", STRING_ID, false),
+        ];
+        event.settings = vec![SettingsElement {
+            annotations: vec![annotation(LABEL_ID, "Enabled")],
+            name: Cow::Borrowed("enabled"),
+            type_id: BOOLEAN_ID,
+            default_value: Cow::Borrowed("true"),
+        }];
+        w.add_class(event);
+
+        add_thread(&mut w, 1, "main")?;
+        add_method(
+            &mut w,
+            1,
+            "com/example/App",
+            "main",
+            "([Ljava/lang/String;)V",
+        )?;
+        add_method(&mut w, 2, "com/example/App", "work", "(I)J")?;
+        add_stack_trace(&mut w, 1, &[(2, 20), (1, 10)])?;
+
+        for (start, duration, size, message) in [
+            (1_000_000_000, 1_500_000, 1_677_721, "hello"),
+            (2_000_000_000, 0, 512, "bye"),
+        ] {
+            w.write_event(
+                EVENT_ID,
+                &[
+                    long(start),
+                    long(duration),
+                    constant(THREAD_ID, 1),
+                    constant(STACK_TRACE_ID, 1),
+                    long(size),
+                    string(message),
+                ],
+            )?;
+        }
+
+        w.finish()
+    }
+
+    fn output(f: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Result<String> {
+        let mut dest = vec![];
+        f(&mut dest)?;
+
+        Ok(String::from_utf8(dest).unwrap())
+    }
+
+    #[test]
+    fn write_event() -> Result<()> {
+        let recording = Recording::from_data(chunk(true)?);
+        let chunk = recording.iter_chunks().next().unwrap()?;
+        let events = chunk.iter_events().collect::<Result<Vec<_>>>()?;
+
+        let mut printer = EventPrinter::new(chunk.resolver())?;
+        let first = output(|dest| printer.write_event(dest, &events[0]))?;
+        printer.set_stack_depth(1);
+        let second = output(|dest| printer.write_event(dest, &events[1]))?;
+
+        assert_eq!(
+            first,
+            "test.Event {\n\
+            \x20 startTime = 22:13:21.000\n\
+            \x20 duration = 1.500 ms\n\
+            \x20 size = 1.6 MB\n\
+            \x20 message = \"hello\"\n\
+            \x20 eventThread = \"main\" (javaThreadId = 1)\n\
+            \x20 stackTrace = [\n\
+            \x20   com.example.App.work(int) line: 20\n\
+            \x20   com.example.App.main(String[]) line: 10\n\
+            \x20 ]\n\
+            }\n\
+            \n"
+        );
+
+        // A zero duration is omitted and the stack trace is cut short.
+        assert_eq!(
+            second,
+            "test.Event {\n\
+            \x20 startTime = 22:13:22.000\n\
+            \x20 size = 512 bytes\n\
+            \x20 message = \"bye\"\n\
+            \x20 eventThread = \"main\" (javaThreadId = 1)\n\
+            \x20 stackTrace = [\n\
+            \x20   com.example.App.work(int) line: 20\n\
+            \x20   ...\n\
+            \x20 ]\n\
+            }\n\
+            \n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_class() -> Result<()> {
+        let recording = Recording::from_data(chunk(true)?);
+        let chunk = recording.iter_chunks().next().unwrap()?;
+        let resolver = chunk.resolver();
+        let class = resolver.get_class(EVENT_ID).unwrap();
+
+        assert_eq!(
+            output(|dest| super::write_class(dest, resolver, class))?,
+            "@Name(\"test.Event\")\n\
+            @Label(\"Test Event\")\n\
+            class Event extends jdk.jfr.Event {\n\
+            \x20 @Timestamp(\"TICKS\")\n\
+            \x20 long startTime;\n\
+            \n\
+            \x20 @Timespan(\"TICKS\")\n\
+            \x20 long duration;\n\
+            \n\
+            \x20 java.lang.Thread eventThread;\n\
+            \n\
+            \x20 jdk.types.StackTrace stackTrace;\n\
+            \n\
+            \x20 @DataAmount(\"BYTES\")\n\
+            \x20 long size;\n\
+            \n\
+            \x20 java.lang.String message;\n\
+            \n\
+            \x20 @Label(\"Enabled\")\n\
+            \x20 setting boolean enabled = \"true\";\n\
+            \n\
+            }\n\
+            \n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn summary() -> Result<()> {
+        let data = [chunk(false)?, chunk(true)?].concat();
+        let recording = Recording::from_data(data);
+
+        // Sizes depend on the encoding. Take them from the event records.
+        let mut sizes = BTreeMap::<i64, u64>::new();
+        for reader in recording.iter_chunk_readers() {
+            for record in reader?.event_records() {
+                let header = record?.header;
+                *sizes.entry(header.event_type).or_default() += header.size as u64;
+            }
+        }
+        let row = |name: &str, count: u64, size: u64| {
+            format!(" {:<40} {:>10} {:>14}\n", name, count, size)
+        };
+        let table = |divisor: u64| {
+            format!(
+                " {:<40} {:>10} {:>14}\n{}\n{}{}{}",
+                "Event Type",
+                "Count",
+                "Size (bytes)",
+                "=".repeat(67),
+                row("test.Event", 4 / divisor, sizes[&EVENT_ID] / divisor),
+                row("jdk.CheckPoint", 2 / divisor, sizes[&1] / divisor),
+                row("jdk.Metadata", 2 / divisor, sizes[&0] / divisor),
+            )
+        };
+
+        let header = "\n \
+            Version: 2.1\n \
+            Chunks: 2\n \
+            Start: 2023-11-14 22:13:20 (UTC)\n \
+            Duration: 2.500 s\n\
+            \n";
+
+        assert_eq!(
+            output(|dest| write_summary(dest, &recording, false))?,
+            format!("{}{}", header, table(1))
+        );
+        assert_eq!(
+            output(|dest| write_summary(dest, &recording, true))?,
+            format!(
+                "{}{}\n Chunk 0\n\n{}\n Chunk 1\n\n{}",
+                header,
+                table(1),
+                table(2),
+                table(2)
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunks() -> Result<()> {
+        let first = chunk(false)?;
+        let second = chunk(true)?;
+        let recording = Recording::from_data([first.clone(), second.clone()].concat());
+
+        let mut expected = String::new();
+        for (index, (offset, data)) in [(0, &first), (first.len(), &second)]
+            .into_iter()
+            .enumerate()
+        {
+            let (_, header) = ChunkHeader::parse(data)?;
+
+            expected.push_str(&format!(
+                "Chunk {} (offset {})\n\
+                \x20 Version: 2.1\n\
+                \x20 Size: {}\n\
+                \x20 Constant Pool Position: {}\n\
+                \x20 Metadata Position: {}\n\
+                \x20 Start: 2023-11-14T22:13:20+00:00 (1700000000000000000 ns since epoch)\n\
+                \x20 Duration: 2.500 s (2500000000 ns)\n\
+                \x20 Start Ticks: 0\n\
+                \x20 Ticks Per Second: 1000000000\n\
+                \x20 State: finished\n\
+                \x20 Flags: 0x{:02x}\n\
+                \x20 Final Chunk: {}\n\
+                \n",
+                index,
+                offset,
+                data.len(),
+                header.constant_pool_position,
+                header.metadata_position,
+                if index == 0 { 0x01 } else { 0x03 },
+                index == 1,
+            ));
+        }
+
+        assert_eq!(output(|dest| write_chunks(dest, &recording))?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_duration(999), "999 ns");
+        assert_eq!(format_duration(13_055), "13.055 us");
+        assert_eq!(format_duration(4_086_733_017), "4.087 s");
+        assert_eq!(format_bytes(512), "512 bytes");
+        assert_eq!(format_bytes(1_677_721), "1.6 MB");
+
+        let method = MethodName {
+            class_name: "Spin".into(),
+            name: "main".into(),
+            descriptor: "([Ljava/lang/String;I[[J)V".into(),
+        };
+        assert_eq!(method.java_parameters(), "String[], int, long[][]");
+    }
+}
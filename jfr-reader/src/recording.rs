@@ -13,10 +13,17 @@
 //! *recording*.
 
 use crate::{
-    chunk::{ChunkHeader, SliceReader},
+    chunk::{ChunkHeader, ChunkReader, SliceReader},
+    chunk_event::{ChunkEvent, EventHeader, EventRecord},
     error::{Error, Result},
+    resolver::{EventResolver, TimeResolver},
+    value::{Object, Value},
+};
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    rc::Rc,
 };
-use std::io::{Read, Seek, SeekFrom};
 
 /// Read JFR files.
 pub struct FileReader<T: Read + Seek> {
@@ -81,3 +88,526 @@ impl<T: Read + Seek> FileReader<T> {
         Ok(Some(buf))
     }
 }
+
+/// A JFR recording consisting of 1 or more chunks.
+///
+/// Instances own the raw recording data and expose APIs for iterating
+/// its chunks and the events within them without callers having to
+/// deal with chunk boundaries themselves.
+///
+/// Chunks are standalone: each has its own metadata and constant pools.
+/// So each chunk gets its own [EventResolver]. Chunk-level iteration via
+/// [Self::iter_chunks] constructs the resolver once per chunk and shares
+/// it with all events in that chunk.
+///
+/// The recording data can be any type that dereferences to `[u8]`. By
+/// default it is a heap allocated `Vec<u8>`. With the `mmap` feature, a
+/// memory mapped file can be used instead. See [MmapRecording].
+pub struct Recording<D: AsRef<[u8]> = Vec<u8>> {
+    data: D,
+}
+
+/// A [Recording] backed by a memory mapped file.
+///
+/// Chunk data and events borrow directly from the mapped file. No chunk
+/// data is copied to the heap.
+#[cfg(feature = "mmap")]
+pub type MmapRecording = Recording<memmap2::Mmap>;
+
+impl Recording {
+    /// Construct an instance by reading all data from a reader.
+    pub fn from_reader(mut reader: impl Read) -> Result<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+
+        Ok(Self::from_data(data))
+    }
+
+    /// Construct an instance from the content of a filesystem path.
+    ///
+    /// The entire file is read into memory.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::from_data(std::fs::read(path)?))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Recording<memmap2::Mmap> {
+    /// Construct an instance by memory mapping a file.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the instance is
+    /// alive. Doing so is undefined behavior and can result in the process
+    /// receiving `SIGBUS`. This means the file shouldn't be a recording the
+    /// JVM is still writing to.
+    pub unsafe fn from_file_mmap(file: &std::fs::File) -> Result<Self> {
+        Ok(Self::from_data(memmap2::Mmap::map(file)?))
+    }
+
+    /// Construct an instance by memory mapping the file at a filesystem path.
+    ///
+    /// # Safety
+    ///
+    /// See [Self::from_file_mmap()].
+    pub unsafe fn from_path_mmap(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_file_mmap(&std::fs::File::open(path)?)
+    }
+}
+
+impl<D: AsRef<[u8]>> Recording<D> {
+    /// Construct an instance from raw recording data.
+    pub fn from_data(data: D) -> Self {
+        Self { data }
+    }
+
+    /// The raw data backing this recording.
+    pub fn data(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// Iterate over [SliceReader] for each chunk in this recording.
+    ///
+    /// This is a lightweight operation: only chunk and metadata headers are
+    /// parsed.
+    ///
+    /// Iteration stops after the first error, as a chunk that can't be parsed
+    /// prevents locating the chunks after it.
+    pub fn iter_chunk_readers(&self) -> impl Iterator<Item = Result<SliceReader<'_>>> + '_ {
+        let mut remaining = self.data.as_ref();
+
+        std::iter::repeat(()).map_while(move |_| {
+            if remaining.is_empty() {
+                None
+            } else {
+                match SliceReader::new(remaining) {
+                    Ok((after, reader)) => {
+                        remaining = after;
+
+                        Some(Ok(reader))
+                    }
+                    Err(err) => {
+                        remaining = &[];
+
+                        Some(Err(err))
+                    }
+                }
+            }
+        })
+    }
+
+    /// Iterate over chunks in this recording with their [EventResolver].
+    ///
+    /// Each chunk's metadata is parsed and its constant pools lightly parsed
+    /// as part of iteration.
+    ///
+    /// A failure to construct a chunk's resolver is emitted as an error for
+    /// that chunk and iteration continues with the next chunk.
+    pub fn iter_chunks(&self) -> impl Iterator<Item = Result<RecordingChunk<'_>>> + '_ {
+        self.iter_chunk_readers()
+            .enumerate()
+            .map(|(index, reader)| RecordingChunk::new(index, reader?))
+    }
+
+    /// Iterate over all non-special events in all chunks of this recording.
+    ///
+    /// Events are emitted in chunk order and in on-disk order within each chunk.
+    /// This is not necessarily chronological order. Metadata and constant pool
+    /// events are not emitted.
+    ///
+    /// Each event holds a reference to its chunk's [EventResolver], which can
+    /// be used to resolve the event's fields.
+    pub fn iter_events(&self) -> impl Iterator<Item = Result<RecordingEvent<'_>>> + '_ {
+        self.iter_chunks().flat_map(|chunk| {
+            let (chunk, err) = match chunk {
+                Ok(chunk) => (Some(chunk), None),
+                Err(err) => (None, Some(Err(err))),
+            };
+
+            err.into_iter()
+                .chain(chunk.into_iter().flat_map(|chunk| chunk.iter_events()))
+        })
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<D: AsRef<[u8]> + Sync> Recording<D> {
+    /// Run a function against every chunk in this recording in parallel.
+    ///
+    /// Chunks are standalone so they can be decoded independently of each
+    /// other. Each chunk's [RecordingChunk] (including its [EventResolver])
+    /// is constructed on the thread that calls `f` for it.
+    ///
+    /// Work is performed on the current rayon thread pool. Use
+    /// [rayon::ThreadPool::install] to control which pool is used.
+    ///
+    /// Results are returned in chunk order. If any chunk fails to resolve
+    /// or `f` returns an error, an error is returned.
+    pub fn par_map_chunks<T, F>(&self, f: F) -> Result<Vec<T>>
+    where
+        T: Send,
+        F: Fn(&RecordingChunk) -> Result<T> + Sync + Send,
+    {
+        use rayon::prelude::*;
+
+        let readers = self.iter_chunk_readers().collect::<Result<Vec<_>>>()?;
+
+        readers
+            .into_par_iter()
+            .enumerate()
+            .map(|(index, reader)| f(&RecordingChunk::new(index, reader)?))
+            .collect::<Result<Vec<_>>>()
+    }
+
+    /// Run a function against every chunk in parallel and merge the results.
+    ///
+    /// This is like [Self::par_map_chunks()] except results are merged with
+    /// `reduce` as they become available instead of being collected. `identity`
+    /// produces the initial value for each merge.
+    ///
+    /// `reduce` must be associative: results are merged in chunk order, but
+    /// adjacent results may be merged in any grouping. `reduce` doesn't need to
+    /// be commutative.
+    pub fn par_map_reduce_chunks<T, F, ID, R>(&self, map: F, identity: ID, reduce: R) -> Result<T>
+    where
+        T: Send,
+        F: Fn(&RecordingChunk) -> Result<T> + Sync + Send,
+        ID: Fn() -> T + Sync + Send,
+        R: Fn(T, T) -> T + Sync + Send,
+    {
+        use rayon::prelude::*;
+
+        let readers = self.iter_chunk_readers().collect::<Result<Vec<_>>>()?;
+
+        readers
+            .into_par_iter()
+            .enumerate()
+            .map(|(index, reader)| map(&RecordingChunk::new(index, reader)?))
+            .try_reduce(&identity, |a, b| Ok(reduce(a, b)))
+    }
+}
+
+/// A chunk within a [Recording] along with its [EventResolver].
+pub struct RecordingChunk<'a> {
+    index: usize,
+    reader: SliceReader<'a>,
+    resolver: Rc<EventResolver<'a>>,
+}
+
+impl<'a> RecordingChunk<'a> {
+    /// Construct an instance from a chunk reader.
+    ///
+    /// `index` is the 0-based index of the chunk in its recording.
+    pub fn new(index: usize, reader: SliceReader<'a>) -> Result<Self> {
+        let resolver = reader.data_resolver()?;
+
+        Ok(Self::with_resolver(index, reader, resolver))
+    }
+
+    /// Construct an instance from a chunk reader and a resolver for it.
+    pub fn with_resolver(
+        index: usize,
+        reader: SliceReader<'a>,
+        resolver: EventResolver<'a>,
+    ) -> Self {
+        Self {
+            index,
+            reader,
+            resolver: Rc::new(resolver),
+        }
+    }
+
+    /// The 0-based index of this chunk in its recording.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The [SliceReader] for this chunk.
+    pub fn reader(&self) -> &SliceReader<'a> {
+        &self.reader
+    }
+
+    /// The parsed header of this chunk.
+    pub fn header(&self) -> &ChunkHeader {
+        self.reader.header()
+    }
+
+    /// The [EventResolver] for this chunk.
+    pub fn resolver(&self) -> &EventResolver<'a> {
+        &self.resolver
+    }
+
+    /// The [TimeResolver] for this chunk.
+    pub fn time_resolver(&self) -> &TimeResolver {
+        self.resolver.time_resolver()
+    }
+
+    /// Iterate over the non-special events in this chunk.
+    ///
+    /// Metadata and constant pool events are not emitted.
+    pub fn iter_events(&self) -> impl Iterator<Item = Result<RecordingEvent<'a>>> + 'a {
+        self.iter_events_from(ChunkHeader::HEADER_SIZE as _)
+    }
+
+    /// Iterate over the non-special events in this chunk starting at a chunk offset.
+    ///
+    /// The offset must be the start of an event record.
+    pub fn iter_events_from(
+        &self,
+        offset: usize,
+    ) -> impl Iterator<Item = Result<RecordingEvent<'a>>> + 'a {
+        let chunk_index = self.index;
+        let resolver = self.resolver.clone();
+
+        self.reader
+            .event_records_from(offset)
+            .filter_map(move |record| match record {
+                Ok(record) if record.is_special_event() => None,
+                Ok(record) => Some(Ok(RecordingEvent {
+                    chunk_index,
+                    record,
+                    resolver: resolver.clone(),
+                })),
+                Err(err) => Some(Err(err)),
+            })
+    }
+}
+
+/// An event in a [Recording] along with the [EventResolver] of its chunk.
+#[derive(Clone)]
+pub struct RecordingEvent<'a> {
+    chunk_index: usize,
+    record: EventRecord<'a>,
+    resolver: Rc<EventResolver<'a>>,
+}
+
+impl<'a> RecordingEvent<'a> {
+    /// The 0-based index of the chunk this event came from.
+    pub fn chunk_index(&self) -> usize {
+        self.chunk_index
+    }
+
+    /// The lightly parsed record of this event.
+    pub fn record(&self) -> &EventRecord<'a> {
+        &self.record
+    }
+
+    /// The [EventResolver] for the chunk this event came from.
+    pub fn resolver(&self) -> &EventResolver<'a> {
+        &self.resolver
+    }
+
+    /// The [TimeResolver] for the chunk this event came from.
+    pub fn time_resolver(&self) -> &TimeResolver {
+        self.resolver.time_resolver()
+    }
+
+    /// The name of this event's class.
+    ///
+    /// Evaluates to [None] if the class isn't defined in the chunk metadata.
+    pub fn class_name(&self) -> Option<&str> {
+        self.resolver.class_name(self.record.header.event_type)
+    }
+
+    /// Resolve a [Value] for this event.
+    pub fn resolve_value(&self) -> Result<Value<'_>> {
+        self.record.resolve_value(&self.resolver)
+    }
+
+    /// Resolve the fields of this event into an [Object].
+    pub fn resolve_object(&self) -> Result<Object<'_>> {
+        self.record.resolve_object(&self.resolver)
+    }
+}
+
+impl<'a> ChunkEvent<'a> for RecordingEvent<'a> {
+    fn header(&self) -> &EventHeader {
+        &self.record.header
+    }
+
+    fn event_data(&self) -> Result<&'a [u8]> {
+        self.record.event_data()
+    }
+
+    fn fields_data(&self) -> Result<&'a [u8]> {
+        self.record.fields_data()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        test_util::{add_thread, constant, field, long, writer, LONG_ID, THREAD_ID},
+        writer::primitive_class,
+    };
+
+    const EVENT_ID: i64 = 101;
+
+    /// A chunk holding events whose start times are the given values.
+    fn chunk(start_times: &[i64]) -> Result<Vec<u8>> {
+        let mut w = writer();
+
+        let mut event = primitive_class(EVENT_ID, "test.Event");
+        event.fields = vec![
+            field("startTime", LONG_ID, false),
+            field("eventThread", THREAD_ID, true),
+        ];
+        w.add_class(event);
+
+        add_thread(&mut w, 1, "main")?;
+
+        for t in start_times {
+            w.write_event(EVENT_ID, &[long(*t), constant(THREAD_ID, 1)])?;
+        }
+
+        w.finish()
+    }
+
+    fn start_time(event: &RecordingEvent) -> Option<i64> {
+        event
+            .resolve_object()
+            .ok()?
+            .field("startTime")?
+            .as_primitive()?
+            .as_i64()
+    }
+
+    #[test]
+    fn iter_chunks() -> Result<()> {
+        let first = chunk(&[1, 2])?;
+        let second = chunk(&[3])?;
+        let recording = Recording::from_data([first.clone(), second.clone()].concat());
+
+        let chunks = recording.iter_chunks().collect::<Result<Vec<_>>>()?;
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].index(), 0);
+        assert_eq!(chunks[0].header().chunk_size, first.len() as u64);
+        assert_eq!(chunks[1].index(), 1);
+        assert_eq!(chunks[1].header().chunk_size, second.len() as u64);
+        assert_eq!(chunks[1].resolver().class_id("test.Event"), Some(EVENT_ID));
+
+        assert_eq!(chunks[0].iter_events().count(), 2);
+        assert_eq!(chunks[1].iter_events().count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn iter_events() -> Result<()> {
+        let recording = Recording::from_data([chunk(&[1, 2])?, chunk(&[3])?].concat());
+
+        let events = recording.iter_events().collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| (e.chunk_index(), start_time(e)))
+                .collect::<Vec<_>>(),
+            vec![(0, Some(1)), (0, Some(2)), (1, Some(3))]
+        );
+        assert!(events.iter().all(|e| e.class_name() == Some("test.Event")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stops_after_error() -> Result<()> {
+        let first = chunk(&[1])?;
+        let mut corrupt = chunk(&[2])?;
+        corrupt[0] = b'X';
+
+        let recording = Recording::from_data([first, corrupt, chunk(&[3])?].concat());
+
+        let chunks = recording.iter_chunks().collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].is_ok());
+        assert!(chunks[1].is_err());
+
+        let events = recording.iter_events().collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], Ok(e) if start_time(e) == Some(1)));
+        assert!(events[1].is_err());
+
+        Ok(())
+    }
+
+    /// Chunk headers and the chunk index and data of each event.
+    #[cfg(feature = "mmap")]
+    type Contents = (Vec<ChunkHeader>, Vec<(usize, Vec<u8>)>);
+
+    #[cfg(feature = "mmap")]
+    fn contents<D: AsRef<[u8]>>(recording: &Recording<D>) -> Result<Contents> {
+        let headers = recording
+            .iter_chunks()
+            .map(|chunk| Ok(*chunk?.header()))
+            .collect::<Result<Vec<_>>>()?;
+        let events = recording
+            .iter_events()
+            .map(|event| {
+                let event = event?;
+                Ok((event.chunk_index(), event.event_data()?.to_vec()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((headers, events))
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap() -> Result<()> {
+        let data = [chunk(&[1, 2])?, chunk(&[3])?].concat();
+
+        let mut file = tempfile::tempfile()?;
+        std::io::Write::write_all(&mut file, &data)?;
+
+        let mapped = unsafe { Recording::from_file_mmap(&file)? };
+        let heap = Recording::from_data(data);
+
+        assert_eq!(mapped.data(), heap.data());
+
+        let (headers, events) = contents(&mapped)?;
+        assert_eq!(headers.len(), 2);
+        assert_eq!(events.len(), 3);
+        assert_eq!((headers, events), contents(&heap)?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn par_map_reduce_chunks() -> Result<()> {
+        let recording = Recording::from_data(
+            (0..16)
+                .map(|i| chunk(&[i * 2, i * 2 + 1]))
+                .collect::<Result<Vec<_>>>()?
+                .concat(),
+        );
+
+        let start_times = |chunk: &RecordingChunk| -> Result<Vec<i64>> {
+            chunk
+                .iter_events()
+                .map(|event| Ok(start_time(&event?).unwrap_or_default()))
+                .collect()
+        };
+
+        let sequential =
+            recording
+                .iter_chunks()
+                .try_fold(vec![], |mut acc, chunk| -> Result<_> {
+                    acc.extend(start_times(&chunk?)?);
+                    Ok(acc)
+                })?;
+
+        // Concatenation is associative but not commutative.
+        let parallel = recording.par_map_reduce_chunks(start_times, Vec::new, |mut a, b| {
+            a.extend(b);
+            a
+        })?;
+
+        assert_eq!(sequential, (0..32).collect::<Vec<_>>());
+        assert_eq!(parallel, sequential);
+
+        Ok(())
+    }
+}
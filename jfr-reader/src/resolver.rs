@@ -16,10 +16,10 @@ use crate::{
     chunk::ChunkHeader,
     common::{leb128_i32, leb128_i64},
     constant_pool::ConstantPoolEvent,
-    error::{Error, ParseResult, Result},
+    error::{Error, Result},
     event::GenericEvent,
     metadata::{ClassElement, FieldElement, Metadata},
-    primitive::Primitive,
+    primitive::{Primitive, PrimitiveParser},
     value::{ConstantValue, Object, ResolvedConstantValue, Value},
 };
 use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc};
@@ -55,6 +55,48 @@ pub struct ConstantPoolValues<'a> {
     string_class_id: Option<i64>,
 }
 
+impl<'a> ConstantPoolValues<'a> {
+    /// Follow a constant pool reference.
+    ///
+    /// Values that aren't constant pool references are returned as-is. Null
+    /// and missing constants evaluate to [None].
+    pub fn deref<'v>(&'v self, value: &'v Value<'a>) -> Option<&'v Value<'a>> {
+        match value {
+            Value::ConstantPool {
+                class_id,
+                constant_index,
+            } => match self.get(*class_id, *constant_index) {
+                ConstantValue::Value(v) => Some(v),
+                ConstantValue::Null | ConstantValue::Missing => None,
+            },
+            Value::ConstantPoolNull => None,
+            v => Some(v),
+        }
+    }
+
+    /// Resolve the string held by a value, following constant pool references.
+    ///
+    /// `jdk.types.Symbol` instances are resolved to the string they wrap.
+    pub fn resolve_str<'v>(&'v self, value: &'v Value<'a>) -> Option<&'v str> {
+        match self.deref(value)? {
+            Value::Primitive(Primitive::String(s)) => Some(s.as_ref()),
+            Value::Primitive(Primitive::StringConstantPool(index)) => {
+                if let ConstantValue::Value(Value::Primitive(Primitive::String(s))) =
+                    self.get_string(*index)
+                {
+                    Some(s.as_ref())
+                } else {
+                    None
+                }
+            }
+            Value::Object(o) if o.class().name == "jdk.types.Symbol" => {
+                self.resolve_str(o.field("string")?)
+            }
+            _ => None,
+        }
+    }
+}
+
 impl<'a> ConstantResolver<'a> for ConstantPoolValues<'a> {
     fn get(&self, class_id: i64, index: i64) -> ConstantValue<'a, '_> {
         match self.inner.get(&class_id) {
@@ -109,12 +151,18 @@ impl TimeResolver {
         })
     }
 
+    /// Convert a number of ticks to nanoseconds.
+    ///
+    /// Computed in 128 bits so the tick frequency needn't be a multiple of 1 GHz.
+    #[inline]
+    fn ticks_to_nanoseconds(&self, ticks: i64) -> i64 {
+        (ticks as i128 * 1_000_000_000 / self.ticks_per_second as i128) as i64
+    }
+
     /// Number of nanoseconds between the specified ticks value and the chunk start.
     #[inline]
     pub fn chunk_start_delta_nanoseconds(&self, ticks: i64) -> i64 {
-        let delta_ticks = ticks - self.start_ticks as i64;
-
-        delta_ticks / (self.ticks_per_second / 1_000_000_000) as i64
+        self.ticks_to_nanoseconds(ticks - self.start_ticks as i64)
     }
 
     /// The time between ticks and chunk start expressed as a [Duration].
@@ -140,8 +188,7 @@ impl TimeResolver {
     /// Obtain the time between 2 ticks in nanoseconds.
     #[inline]
     pub fn delta_nanoseconds(&self, start_ticks: i64, end_ticks: i64) -> i64 {
-        let delta_ticks = end_ticks - start_ticks;
-        delta_ticks / (self.ticks_per_second / 1_000_000_000) as i64
+        self.ticks_to_nanoseconds(end_ticks - start_ticks)
     }
 
     /// Obtain the amount of time between 2 tick values as a [Duration].
@@ -165,7 +212,7 @@ impl TimeResolver {
 pub struct EventResolver<'a> {
     classes: FxHashMap<i64, ClassElement<'a>>,
     constant_pools: Vec<ConstantPoolEvent<'a>>,
-    primitive_parsers: FxHashMap<i64, fn(&'a [u8]) -> ParseResult<'a, Primitive<'a>>>,
+    primitive_parsers: FxHashMap<i64, PrimitiveParser<'a>>,
     time_resolver: TimeResolver,
 }
 
@@ -259,7 +306,7 @@ impl<'a> EventResolver<'a> {
         s: &'a [u8],
         class_id: i64,
         cr: &'cr CR,
-    ) -> Result<(&[u8], GenericEvent<'slf, 'cr, CR>)> {
+    ) -> Result<(&'slf [u8], GenericEvent<'slf, 'cr, CR>)> {
         let (s, o) = self.parse_event_object(s, class_id)?;
 
         let res = GenericEvent::new(o, cr);
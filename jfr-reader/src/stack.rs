@@ -0,0 +1,148 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Stack trace resolution.
+//!
+//! Many events have a `stackTrace` field referencing a `jdk.types.StackTrace`
+//! in the constant pool. Stack traces hold an array of `jdk.types.StackFrame`,
+//! which reference a `jdk.types.Method`, which in turn references its
+//! `java.lang.Class` and `jdk.types.Symbol` names.
+//!
+//! The functions in this module walk these untyped [Value]s. Unlike
+//! deserializing into the typed `types` structs, this preserves constant pool
+//! indices, which consumers can use as cache keys.
+
+use crate::{
+    resolver::ConstantPoolValues,
+    value::{Object, Value},
+};
+
+/// The resolved name of a `jdk.types.Method`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct MethodName {
+    /// Fully qualified class name using `.` as the package separator.
+    pub class_name: String,
+    /// The method name.
+    pub name: String,
+    /// The JVM method descriptor, e.g. `(I)V`.
+    pub descriptor: String,
+}
+
+impl MethodName {
+    /// The name of the method in `package.Class.method` form.
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", self.class_name, self.name)
+    }
+
+    /// The parameter types from the descriptor as Java source, e.g. `int, String[]`.
+    ///
+    /// Class names are rendered without their package.
+    pub fn java_parameters(&self) -> String {
+        let params = self
+            .descriptor
+            .strip_prefix('(')
+            .and_then(|s| s.split(')').next())
+            .unwrap_or_default();
+
+        let mut res = vec![];
+        let mut dimensions = 0;
+        let mut chars = params.chars();
+
+        while let Some(c) = chars.next() {
+            let name = match c {
+                '[' => {
+                    dimensions += 1;
+                    continue;
+                }
+                'B' => "byte".to_string(),
+                'C' => "char".to_string(),
+                'D' => "double".to_string(),
+                'F' => "float".to_string(),
+                'I' => "int".to_string(),
+                'J' => "long".to_string(),
+                'S' => "short".to_string(),
+                'Z' => "boolean".to_string(),
+                'L' => {
+                    let class = chars.by_ref().take_while(|c| *c != ';').collect::<String>();
+                    class.rsplit('/').next().unwrap_or_default().to_string()
+                }
+                c => c.to_string(),
+            };
+
+            res.push(format!("{}{}", name, "[]".repeat(dimensions)));
+            dimensions = 0;
+        }
+
+        res.join(", ")
+    }
+}
+
+/// Resolve the name of a `jdk.types.Method` value.
+///
+/// Unresolvable components are rendered as `<unknown>`. Evaluates to [None]
+/// if the method itself can't be resolved.
+pub fn resolve_method(method: &Value, constants: &ConstantPoolValues) -> Option<MethodName> {
+    let method = constants.deref(method)?.as_object()?;
+
+    let class_name = method
+        .field("type")
+        .and_then(|v| constants.deref(v))
+        .and_then(|v| v.as_object())
+        .and_then(|class| class.field("name"))
+        .and_then(|v| constants.resolve_str(v))
+        .map(|s| s.replace('/', "."))
+        .unwrap_or_else(|| "<unknown>".to_string());
+    let name = method
+        .field("name")
+        .and_then(|v| constants.resolve_str(v))
+        .unwrap_or("<unknown>")
+        .to_string();
+    let descriptor = method
+        .field("descriptor")
+        .and_then(|v| constants.resolve_str(v))
+        .unwrap_or_default()
+        .to_string();
+
+    Some(MethodName {
+        class_name,
+        name,
+        descriptor,
+    })
+}
+
+/// Obtain the `jdk.types.StackFrame` objects of a `jdk.types.StackTrace` value.
+///
+/// Frames are emitted leaf / most recent call first.
+pub fn stack_frames<'v, 'a>(
+    stack: &'v Value<'a>,
+    constants: &'v ConstantPoolValues<'a>,
+) -> impl Iterator<Item = &'v Object<'a>> + 'v {
+    let frames = match constants
+        .deref(stack)
+        .and_then(|v| v.as_object())
+        .and_then(|o| o.field("frames"))
+    {
+        Some(Value::Array(frames)) => frames.as_slice(),
+        _ => &[],
+    };
+
+    frames
+        .iter()
+        .filter_map(move |frame| constants.deref(frame).and_then(|v| v.as_object()))
+}
+
+/// Obtain the line number of a `jdk.types.StackFrame`.
+///
+/// Evaluates to [None] if no line number is known.
+pub fn frame_line_number(frame: &Object) -> Option<i64> {
+    frame
+        .field("lineNumber")
+        .and_then(|v| v.as_primitive())
+        .and_then(|p| p.as_i64())
+        .filter(|line| *line > 0)
+}
@@ -0,0 +1,600 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading recordings that are still being written.
+//!
+//! When a JVM streams JFR data to disk, the chunk header is periodically
+//! rewritten to reflect the data flushed so far. While the header is being
+//! rewritten, its file state byte is set to
+//! [ChunkHeader::FILE_STATE_UPDATING] and readers should back off. Once the
+//! chunk is complete, the file state byte is 0.
+//!
+//! Each flush can append new events, constant pool events, and a new
+//! metadata event (if new event types were registered). The chunk header's
+//! constant pool and metadata positions are updated to point at the latest
+//! ones. So after each flush a new [crate::resolver::EventResolver] needs to
+//! be derived to resolve the newly flushed events. Constant pool events
+//! located by previous flushes are remembered so only new ones are parsed.
+//!
+//! [StreamingReader] implements this polling protocol. It behaves like
+//! `jdk.jfr.consumer.EventStream.openFile()` in Java.
+
+use crate::{
+    chunk::{ChunkHeader, ChunkReader, SliceReader},
+    constant_pool::{ConstantPoolEvent, ConstantPoolHeader},
+    error::Result,
+    recording::{RecordingChunk, RecordingEvent},
+};
+use std::{
+    io::{Read, Seek, SeekFrom},
+    time::{Duration, Instant},
+};
+
+/// The result of polling a [StreamingReader] for new data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StreamState {
+    /// No new data is available yet. Poll again later.
+    Pending,
+    /// New events are available.
+    Ready,
+    /// The recording is complete. No new data will become available.
+    Finished,
+}
+
+/// Follows a JFR file that is being written to.
+///
+/// Instances read a chunk incrementally, as the writer flushes data to it.
+/// When a chunk is finished, reading continues with the chunk following it,
+/// if any.
+///
+/// Call [Self::refresh()] to poll for new data then [Self::next_batch()] to
+/// obtain newly flushed events. Or call [Self::wait_batch()] to do both,
+/// blocking until new events are available.
+pub struct StreamingReader<T: Read + Seek> {
+    reader: T,
+    /// Offset of the current chunk in the stream.
+    chunk_start: u64,
+    /// 0-based index of the current chunk.
+    chunk_index: usize,
+    /// Data for the current chunk read so far, inclusive of header.
+    data: Vec<u8>,
+    /// Offset within the chunk of the first event not yet emitted.
+    events_offset: usize,
+    /// Offset within the chunk where data read by the last refresh ends.
+    ready_offset: usize,
+    /// Whether the current chunk has been fully read.
+    chunk_finished: bool,
+    /// Whether the current chunk is the last of the recording.
+    final_chunk: bool,
+    /// Constant pool events of the current chunk located so far.
+    ///
+    /// In the order of the chunk's constant pool chain, newest first.
+    constant_pools: Vec<LocatedConstantPool>,
+    poll_interval: Duration,
+}
+
+/// A constant pool event located in the data of a chunk.
+#[derive(Clone, Copy, Debug)]
+struct LocatedConstantPool {
+    /// Offset of the event within the chunk.
+    offset: i64,
+    header: ConstantPoolHeader,
+    /// Offset within the chunk of the data following the event header.
+    pool_data_offset: usize,
+}
+
+impl<T: Read + Seek> StreamingReader<T> {
+    /// The default amount of time to wait between polls.
+    pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Construct an instance from a readable and seekable stream.
+    ///
+    /// The current stream position is assumed to be the start of a chunk.
+    pub fn from_stream(mut reader: T) -> Result<Self> {
+        let chunk_start = reader.stream_position()?;
+
+        Ok(Self {
+            reader,
+            chunk_start,
+            chunk_index: 0,
+            data: vec![],
+            events_offset: ChunkHeader::HEADER_SIZE as _,
+            ready_offset: ChunkHeader::HEADER_SIZE as _,
+            chunk_finished: false,
+            final_chunk: false,
+            constant_pools: vec![],
+            poll_interval: Self::DEFAULT_POLL_INTERVAL,
+        })
+    }
+
+    /// Set the amount of time [Self::wait_batch()] sleeps between polls.
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// The 0-based index of the chunk currently being read.
+    pub fn chunk_index(&self) -> usize {
+        self.chunk_index
+    }
+
+    /// Attempt to read a consistent chunk header at the current chunk offset.
+    ///
+    /// Returns [None] if a consistent header isn't available.
+    fn read_header(
+        &mut self,
+    ) -> Result<Option<(ChunkHeader, [u8; ChunkHeader::HEADER_SIZE as usize])>> {
+        let mut buf = [0u8; ChunkHeader::HEADER_SIZE as usize];
+
+        self.reader.seek(SeekFrom::Start(self.chunk_start))?;
+        if !read_full(&mut self.reader, &mut buf)? {
+            return Ok(None);
+        }
+
+        let (_, header) = ChunkHeader::parse(&buf)?;
+
+        if header.is_updating() {
+            return Ok(None);
+        }
+
+        // Verify the header didn't change while we were reading it.
+        let mut state = [0u8; 1];
+        self.reader.seek(SeekFrom::Start(
+            self.chunk_start + ChunkHeader::FILE_STATE_OFFSET,
+        ))?;
+        self.reader.read_exact(&mut state)?;
+
+        if state[0] != header.file_state() {
+            return Ok(None);
+        }
+
+        Ok(Some((header, buf)))
+    }
+
+    /// Advance to the chunk following the current one.
+    fn advance_chunk(&mut self) {
+        self.chunk_start += self.data.len() as u64;
+        self.chunk_index += 1;
+        self.data.clear();
+        self.events_offset = ChunkHeader::HEADER_SIZE as _;
+        self.ready_offset = ChunkHeader::HEADER_SIZE as _;
+        self.chunk_finished = false;
+        self.final_chunk = false;
+        self.constant_pools.clear();
+    }
+
+    /// Poll the underlying stream for new data.
+    ///
+    /// This reads the chunk header and any newly flushed chunk data. It
+    /// doesn't parse events.
+    pub fn refresh(&mut self) -> Result<StreamState> {
+        if self.events_offset < self.ready_offset {
+            return Ok(StreamState::Ready);
+        }
+
+        if self.chunk_finished {
+            // Move on to the next chunk if one has started.
+            let next_start = self.chunk_start + self.data.len() as u64;
+            let end = self.reader.seek(SeekFrom::End(0))?;
+
+            // The writer may not have started the next chunk yet.
+            if end <= next_start {
+                return Ok(if self.final_chunk {
+                    StreamState::Finished
+                } else {
+                    StreamState::Pending
+                });
+            }
+
+            self.advance_chunk();
+        }
+
+        let (header, header_data) = if let Some(x) = self.read_header()? {
+            x
+        } else {
+            return Ok(StreamState::Pending);
+        };
+
+        // Nothing useful has been flushed yet.
+        if header.metadata_position == 0 || header.chunk_size <= ChunkHeader::HEADER_SIZE {
+            return Ok(StreamState::Pending);
+        }
+
+        let chunk_size = header.chunk_size as usize;
+
+        // Chunk data before the advertised size is never rewritten. So we only
+        // need to read the new data and refresh the header.
+        if chunk_size > self.data.len() {
+            let existing = self.data.len();
+
+            self.data.resize(chunk_size, 0);
+            self.reader
+                .seek(SeekFrom::Start(self.chunk_start + existing as u64))?;
+
+            if !read_full(&mut self.reader, &mut self.data[existing..])? {
+                // The advertised data isn't fully on disk yet.
+                self.data.truncate(existing);
+                return Ok(StreamState::Pending);
+            }
+        }
+
+        self.data[0..header_data.len()].copy_from_slice(&header_data);
+        self.ready_offset = chunk_size;
+        self.chunk_finished = header.is_finished();
+        self.final_chunk = header.is_final_chunk();
+
+        if self.events_offset < self.ready_offset {
+            Ok(StreamState::Ready)
+        } else if self.chunk_finished {
+            self.refresh()
+        } else {
+            Ok(StreamState::Pending)
+        }
+    }
+
+    /// Obtain events flushed since the last batch.
+    ///
+    /// Does not poll for new data: call [Self::refresh()] to do that.
+    ///
+    /// Returns [None] if no new events are available.
+    pub fn next_batch(&mut self) -> Result<Option<StreamBatch<'_>>> {
+        if self.events_offset >= self.ready_offset {
+            return Ok(None);
+        }
+
+        let start_offset = self.events_offset;
+        self.events_offset = self.ready_offset;
+
+        let data = &self.data[0..self.ready_offset];
+        let (_, reader) = SliceReader::new(data)?;
+
+        locate_constant_pools(
+            data,
+            reader.header().constant_pool_position as _,
+            &mut self.constant_pools,
+        )?;
+
+        let resolver =
+            reader.data_resolver_with_constant_pools(self.constant_pools.iter().map(|cp| {
+                ConstantPoolEvent {
+                    header: cp.header,
+                    pool_data: &data[cp.pool_data_offset..],
+                }
+            }))?;
+        let chunk = RecordingChunk::with_resolver(self.chunk_index, reader, resolver);
+
+        Ok(Some(StreamBatch {
+            chunk,
+            start_offset,
+        }))
+    }
+
+    /// Wait for new events to become available and return them.
+    ///
+    /// Polls the underlying stream until new events are flushed, the
+    /// recording is finished, or the timeout expires. Returns [None] in the
+    /// latter 2 cases.
+    pub fn wait_batch(&mut self, timeout: Option<Duration>) -> Result<Option<StreamBatch<'_>>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+
+        loop {
+            match self.refresh()? {
+                StreamState::Ready => break,
+                StreamState::Finished => return Ok(None),
+                StreamState::Pending => {
+                    if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                        return Ok(None);
+                    }
+
+                    std::thread::sleep(self.poll_interval);
+                }
+            }
+        }
+
+        self.next_batch()
+    }
+}
+
+/// Events flushed to a chunk since the previous batch.
+pub struct StreamBatch<'a> {
+    chunk: RecordingChunk<'a>,
+    start_offset: usize,
+}
+
+impl<'a> StreamBatch<'a> {
+    /// The chunk the events belong to.
+    ///
+    /// The chunk only reflects data flushed so far. Its resolver reflects the
+    /// latest metadata and constant pools.
+    pub fn chunk(&self) -> &RecordingChunk<'a> {
+        &self.chunk
+    }
+
+    /// Iterate over the non-special events in this batch.
+    pub fn iter_events(&self) -> impl Iterator<Item = Result<RecordingEvent<'a>>> + 'a {
+        self.chunk.iter_events_from(self.start_offset)
+    }
+}
+
+/// Update the located constant pool events of a chunk.
+///
+/// Flushes prepend constant pool events to the chain. So the chain is walked
+/// from its head until reaching an event that was already located.
+fn locate_constant_pools(
+    data: &[u8],
+    position: i64,
+    located: &mut Vec<LocatedConstantPool>,
+) -> Result<()> {
+    let known = located.first().map(|cp| cp.offset);
+    let mut new = vec![];
+    let mut offset = 0;
+    let mut delta = position;
+
+    while delta != 0 {
+        offset += delta;
+
+        if Some(offset) == known {
+            new.append(located);
+            break;
+        }
+
+        let (_, cp) = SliceReader::parse_constant_pool_event(data, offset as _)?;
+        delta = cp.header.delta;
+
+        new.push(LocatedConstantPool {
+            offset,
+            header: cp.header,
+            pool_data_offset: data.len() - cp.pool_data.len(),
+        });
+    }
+
+    *located = new;
+
+    Ok(())
+}
+
+/// Fill a buffer from a reader.
+///
+/// Returns false if end of stream was reached before the buffer was filled.
+fn read_full(reader: &mut impl Read, mut buf: &mut [u8]) -> Result<bool> {
+    while !buf.is_empty() {
+        match reader.read(buf) {
+            Ok(0) => return Ok(false),
+            Ok(n) => buf = &mut buf[n..],
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        test_util::{add_thread, constant, field, long, writer, LONG_ID, THREAD_ID},
+        writer::{primitive_class, ChunkWriter},
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    const EVENT_ID: i64 = 101;
+
+    /// A file whose content can be changed while a reader has it open.
+    #[derive(Clone, Default)]
+    struct SharedFile {
+        data: Rc<RefCell<Vec<u8>>>,
+        position: u64,
+    }
+
+    impl SharedFile {
+        fn set(&self, data: Vec<u8>) {
+            *self.data.borrow_mut() = data;
+        }
+    }
+
+    impl Read for SharedFile {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let data = self.data.borrow();
+            let start = (self.position as usize).min(data.len());
+            let count = (&data[start..]).read(buf)?;
+            self.position += count as u64;
+
+            Ok(count)
+        }
+    }
+
+    impl Seek for SharedFile {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.position = match pos {
+                SeekFrom::Start(v) => v,
+                SeekFrom::End(v) => (self.data.borrow().len() as i64 + v) as u64,
+                SeekFrom::Current(v) => (self.position as i64 + v) as u64,
+            };
+
+            Ok(self.position)
+        }
+    }
+
+    fn event_writer() -> Result<ChunkWriter<'static>> {
+        let mut w = writer();
+
+        let mut event = primitive_class(EVENT_ID, "test.Event");
+        event.fields = vec![
+            field("startTime", LONG_ID, false),
+            field("eventThread", THREAD_ID, true),
+        ];
+        w.add_class(event);
+
+        add_thread(&mut w, 1, "main")?;
+
+        Ok(w)
+    }
+
+    fn write_event(w: &mut ChunkWriter, start_time: i64, thread: i64) -> Result<()> {
+        w.write_event(EVENT_ID, &[long(start_time), constant(THREAD_ID, thread)])
+    }
+
+    /// The start time and thread name of each event in a batch.
+    fn batch_events(batch: &StreamBatch) -> Result<Vec<(i64, String)>> {
+        batch
+            .iter_events()
+            .map(|event| {
+                let event = event?;
+                let constants = event.resolver().constant_pool_values()?;
+                let object = event.resolve_object()?.resolve_constants(&constants)?;
+
+                let start_time = object
+                    .field("startTime")
+                    .and_then(|v| v.as_primitive())
+                    .and_then(|v| v.as_i64())
+                    .expect("startTime is a long");
+                let thread = object
+                    .field("eventThread")
+                    .and_then(|v| v.as_object())
+                    .and_then(|o| o.field("javaName"))
+                    .and_then(|v| v.as_primitive())
+                    .and_then(|v| v.as_str())
+                    .expect("eventThread is resolved")
+                    .to_string();
+
+                Ok((start_time, thread))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn updating_header() -> Result<()> {
+        let file = SharedFile::default();
+        let mut reader = StreamingReader::from_stream(file.clone())?;
+
+        assert_eq!(reader.refresh()?, StreamState::Pending);
+
+        let mut w = event_writer()?;
+        write_event(&mut w, 1, 1)?;
+        let data = w.flush();
+
+        let mut updating = data.clone();
+        updating[ChunkHeader::FILE_STATE_OFFSET as usize] = ChunkHeader::FILE_STATE_UPDATING;
+        let (_, header) = ChunkHeader::parse(&updating)?;
+        assert!(header.is_updating());
+        assert!(!header.is_finished());
+
+        file.set(updating);
+        assert_eq!(reader.refresh()?, StreamState::Pending);
+        assert!(reader.next_batch()?.is_none());
+
+        file.set(data);
+        assert_eq!(reader.refresh()?, StreamState::Ready);
+        let batch = reader.next_batch()?.expect("batch is ready");
+        assert_eq!(batch_events(&batch)?, vec![(1, "main".to_string())]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_batches() -> Result<()> {
+        let file = SharedFile::default();
+        let mut reader = StreamingReader::from_stream(file.clone())?;
+
+        let mut w = event_writer()?;
+        write_event(&mut w, 1, 1)?;
+        write_event(&mut w, 2, 1)?;
+        file.set(w.flush());
+
+        assert_eq!(reader.refresh()?, StreamState::Ready);
+        let batch = reader.next_batch()?.expect("batch is ready");
+        let header = batch.chunk().header();
+        assert!(!header.is_updating());
+        assert!(!header.is_finished());
+        assert!(!header.is_final_chunk());
+        assert_eq!(
+            batch_events(&batch)?,
+            vec![(1, "main".to_string()), (2, "main".to_string())]
+        );
+
+        // Nothing was flushed since.
+        assert_eq!(reader.refresh()?, StreamState::Pending);
+        assert!(reader.next_batch()?.is_none());
+
+        // New constants are written to a new constant pool event. Events can
+        // refer to constants in both.
+        add_thread(&mut w, 2, "worker")?;
+        write_event(&mut w, 3, 2)?;
+        write_event(&mut w, 4, 1)?;
+        file.set(w.flush());
+
+        assert_eq!(reader.refresh()?, StreamState::Ready);
+        let batch = reader.next_batch()?.expect("batch is ready");
+        assert!(!batch.chunk().header().is_finished());
+        assert_eq!(
+            batch_events(&batch)?,
+            vec![(3, "worker".to_string()), (4, "main".to_string())]
+        );
+
+        write_event(&mut w, 5, 2)?;
+        file.set(w.finish()?);
+
+        assert_eq!(reader.refresh()?, StreamState::Ready);
+        let batch = reader.next_batch()?.expect("batch is ready");
+        let header = batch.chunk().header();
+        assert!(header.is_finished());
+        assert!(header.is_final_chunk());
+        assert_eq!(batch_events(&batch)?, vec![(5, "worker".to_string())]);
+
+        assert_eq!(reader.refresh()?, StreamState::Finished);
+        assert_eq!(reader.chunk_index(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn next_chunk() -> Result<()> {
+        let file = SharedFile::default();
+        let mut reader = StreamingReader::from_stream(file.clone())?;
+
+        let mut w = event_writer()?;
+        w.set_final_chunk(false);
+        write_event(&mut w, 1, 1)?;
+        let first = w.finish()?;
+        file.set(first.clone());
+
+        assert_eq!(reader.refresh()?, StreamState::Ready);
+        let batch = reader.next_batch()?.expect("batch is ready");
+        assert_eq!(batch.chunk().index(), 0);
+        assert!(batch.chunk().header().is_finished());
+        assert!(!batch.chunk().header().is_final_chunk());
+        assert_eq!(batch_events(&batch)?, vec![(1, "main".to_string())]);
+
+        // The next chunk hasn't been started.
+        assert_eq!(reader.refresh()?, StreamState::Pending);
+
+        let mut w = event_writer()?;
+        write_event(&mut w, 2, 1)?;
+        file.set([first.clone(), w.flush()].concat());
+
+        assert_eq!(reader.refresh()?, StreamState::Ready);
+        assert_eq!(reader.chunk_index(), 1);
+        let batch = reader.next_batch()?.expect("batch is ready");
+        assert_eq!(batch.chunk().index(), 1);
+        assert!(!batch.chunk().header().is_finished());
+        assert_eq!(batch_events(&batch)?, vec![(2, "main".to_string())]);
+
+        write_event(&mut w, 3, 1)?;
+        file.set([first, w.finish()?].concat());
+
+        assert_eq!(reader.refresh()?, StreamState::Ready);
+        let batch = reader.next_batch()?.expect("batch is ready");
+        assert!(batch.chunk().header().is_final_chunk());
+        assert_eq!(batch_events(&batch)?, vec![(3, "main".to_string())]);
+
+        assert_eq!(reader.refresh()?, StreamState::Finished);
+        assert_eq!(reader.chunk_index(), 1);
+
+        Ok(())
+    }
+}
@@ -75,7 +75,7 @@ impl StringRecordHeader {
     ///
     /// Any inline string data is not read. If inline string data is present,
     /// it will follow this decoded record.
-    pub fn parse(s: &[u8]) -> ParseResult<Self> {
+    pub fn parse(s: &[u8]) -> ParseResult<'_, Self> {
         let (s, encoding) = be_u8(s)?;
 
         let encoding = Encoding::try_from(encoding).map_err(|_| {
@@ -151,7 +151,7 @@ impl<'a> StringRecord<'a> {
     /// inline string data is. Assuming this to be true, any errors should indicate
     /// how many remaining bytes of data need to be acquired to obtain a reference
     /// to inline string data.
-    pub fn parse(s: &'a [u8]) -> ParseResult<Self> {
+    pub fn parse(s: &'a [u8]) -> ParseResult<'a, Self> {
         let (s, header) = StringRecordHeader::parse(s)?;
 
         let (s, res) = match header {
@@ -181,7 +181,7 @@ impl<'a> StringRecord<'a> {
     /// Attempt to coerce this record to a string.
     ///
     /// The decoded string always has a static lifetime.
-    pub fn resolve(&self) -> ParseResult<StringValue<'static>> {
+    pub fn resolve(&self) -> ParseResult<'_, StringValue<'static>> {
         match self {
             Self::Null => Ok((&[], StringValue::Null)),
             Self::Empty => Ok((&[], StringValue::String(Cow::Borrowed("")))),
@@ -201,7 +201,7 @@ impl<'a> StringRecord<'a> {
                 {
                     Ok(chars) => Ok((
                         &[],
-                        StringValue::String(Cow::Owned(String::from_iter(chars.into_iter()))),
+                        StringValue::String(Cow::Owned(String::from_iter(chars))),
                     )),
                     Err(err) => {
                         let mut bytes = Vec::with_capacity(4 * raw.len());
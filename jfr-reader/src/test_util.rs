@@ -0,0 +1,216 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fixtures shared by tests.
+//!
+//! Tests produce the chunks they read with [ChunkWriter]. The helpers here
+//! declare the types most of them need.
+
+use crate::{
+    error::Result,
+    metadata::{AnnotationElement, FieldElement},
+    primitive::Primitive,
+    value::{Object, Value},
+    writer::{primitive_class, primitive_classes, ChunkWriter},
+};
+use std::borrow::Cow;
+
+pub const BOOLEAN_ID: i64 = 4;
+pub const INT_ID: i64 = 10;
+pub const LONG_ID: i64 = 11;
+pub const STRING_ID: i64 = 20;
+pub const SYMBOL_ID: i64 = 30;
+pub const CLASS_ID: i64 = 31;
+pub const METHOD_ID: i64 = 32;
+pub const FRAME_ID: i64 = 33;
+pub const STACK_TRACE_ID: i64 = 34;
+pub const THREAD_ID: i64 = 100;
+pub const LABEL_ID: i64 = 200;
+pub const TIMESTAMP_ID: i64 = 201;
+pub const TIMESPAN_ID: i64 = 202;
+pub const DATA_AMOUNT_ID: i64 = 203;
+
+/// Construct a field without annotations.
+pub fn field(name: &'static str, type_id: i64, constant_pool: bool) -> FieldElement<'static> {
+    FieldElement {
+        annotations: vec![],
+        name: Cow::Borrowed(name),
+        type_id,
+        dimension: None,
+        constant_pool: constant_pool.then_some(Cow::Borrowed("true")),
+    }
+}
+
+/// Construct a writer with the primitive types and `java.lang.Thread` registered.
+pub fn writer() -> ChunkWriter<'static> {
+    let mut w = ChunkWriter::new();
+    w.add_classes(primitive_classes());
+
+    let mut thread = primitive_class(THREAD_ID, "java.lang.Thread");
+    thread.fields = vec![
+        field("javaName", STRING_ID, false),
+        field("javaThreadId", LONG_ID, false),
+    ];
+    w.add_class(thread);
+
+    w
+}
+
+pub fn long(v: i64) -> Value<'static> {
+    Value::Primitive(Primitive::Long(v))
+}
+
+pub fn string(s: impl ToString) -> Value<'static> {
+    Value::Primitive(Primitive::String(s.to_string().into()))
+}
+
+pub fn constant(class_id: i64, constant_index: i64) -> Value<'static> {
+    Value::ConstantPool {
+        class_id,
+        constant_index,
+    }
+}
+
+/// Add a constant holding an object of a registered class.
+pub fn add_object(
+    w: &mut ChunkWriter,
+    class_id: i64,
+    index: i64,
+    fields: Vec<Value>,
+) -> Result<()> {
+    let class = w.get_class(class_id).expect("class is registered").clone();
+
+    w.add_constant(class_id, index, &Value::Object(Object::new(&class, fields)))
+}
+
+/// Add a `java.lang.Thread` constant whose thread ID is its constant index.
+pub fn add_thread(w: &mut ChunkWriter, index: i64, name: &str) -> Result<()> {
+    add_object(w, THREAD_ID, index, vec![string(name), long(index)])
+}
+
+/// Register the annotation types that affect how values are rendered.
+pub fn add_annotation_classes(w: &mut ChunkWriter) {
+    for (id, name) in [
+        (LABEL_ID, "jdk.jfr.Label"),
+        (TIMESTAMP_ID, "jdk.jfr.Timestamp"),
+        (TIMESPAN_ID, "jdk.jfr.Timespan"),
+        (DATA_AMOUNT_ID, "jdk.jfr.DataAmount"),
+    ] {
+        let mut class = primitive_class(id, name);
+        class.super_type = Some(Cow::Borrowed("java.lang.annotation.Annotation"));
+        class.fields = vec![field("value", STRING_ID, false)];
+        w.add_class(class);
+    }
+}
+
+/// Construct an annotation having a single `value`.
+pub fn annotation(type_id: i64, value: &'static str) -> AnnotationElement<'static> {
+    AnnotationElement {
+        type_id,
+        values: vec![(Cow::Borrowed("value"), Cow::Borrowed(value))],
+    }
+}
+
+/// Register the types stack traces are composed of.
+pub fn add_stack_classes(w: &mut ChunkWriter) {
+    let mut symbol = primitive_class(SYMBOL_ID, "jdk.types.Symbol");
+    symbol.simple_type = Some(Cow::Borrowed("true"));
+    symbol.fields = vec![field("string", STRING_ID, false)];
+
+    let mut class = primitive_class(CLASS_ID, "java.lang.Class");
+    class.fields = vec![field("name", SYMBOL_ID, true)];
+
+    let mut method = primitive_class(METHOD_ID, "jdk.types.Method");
+    method.fields = vec![
+        field("type", CLASS_ID, true),
+        field("name", SYMBOL_ID, true),
+        field("descriptor", SYMBOL_ID, true),
+    ];
+
+    let mut frame = primitive_class(FRAME_ID, "jdk.types.StackFrame");
+    frame.fields = vec![
+        field("method", METHOD_ID, true),
+        field("lineNumber", INT_ID, false),
+    ];
+
+    let mut stack_trace = primitive_class(STACK_TRACE_ID, "jdk.types.StackTrace");
+    stack_trace.fields = vec![
+        field("truncated", BOOLEAN_ID, false),
+        FieldElement {
+            dimension: Some(1),
+            ..field("frames", FRAME_ID, false)
+        },
+    ];
+
+    w.add_classes([symbol, class, method, frame, stack_trace]);
+}
+
+/// Add a `jdk.types.Method` constant along with its class and symbols.
+///
+/// `class_name` uses `/` as the package separator, like the JVM does. The
+/// class and symbol constants are assigned indices derived from `index`.
+pub fn add_method(
+    w: &mut ChunkWriter,
+    index: i64,
+    class_name: &str,
+    name: &str,
+    descriptor: &str,
+) -> Result<()> {
+    let symbols = [class_name, name, descriptor];
+    for (offset, symbol) in symbols.iter().enumerate() {
+        add_object(
+            w,
+            SYMBOL_ID,
+            index * 10 + offset as i64,
+            vec![string(symbol)],
+        )?;
+    }
+
+    add_object(w, CLASS_ID, index, vec![constant(SYMBOL_ID, index * 10)])?;
+    add_object(
+        w,
+        METHOD_ID,
+        index,
+        vec![
+            constant(CLASS_ID, index),
+            constant(SYMBOL_ID, index * 10 + 1),
+            constant(SYMBOL_ID, index * 10 + 2),
+        ],
+    )
+}
+
+/// Add a `jdk.types.StackTrace` constant.
+///
+/// `frames` holds the method constant index and line number of each frame,
+/// leaf first.
+pub fn add_stack_trace(w: &mut ChunkWriter, index: i64, frames: &[(i64, i32)]) -> Result<()> {
+    let frame = w.get_class(FRAME_ID).expect("class is registered").clone();
+
+    let frames = frames
+        .iter()
+        .map(|(method, line)| {
+            Value::Object(Object::new(
+                &frame,
+                vec![
+                    constant(METHOD_ID, *method),
+                    Value::Primitive(Primitive::Integer(*line)),
+                ],
+            ))
+        })
+        .collect();
+
+    add_object(
+        w,
+        STACK_TRACE_ID,
+        index,
+        vec![
+            Value::Primitive(Primitive::Boolean(false)),
+            Value::Array(frames),
+        ],
+    )
+}
@@ -53,12 +53,12 @@ impl<'a> Object<'a> {
     }
 
     /// Obtain the class being described.
-    pub fn class(&self) -> &ClassElement {
+    pub fn class(&self) -> &ClassElement<'_> {
         self.class
     }
 
     /// Iterate over field values in this instance.
-    pub fn iter_fields(&self) -> impl Iterator<Item = &Value<'a>> + '_ {
+    pub fn iter_fields(&self) -> impl ExactSizeIterator<Item = &Value<'a>> + '_ {
         self.fields.iter()
     }
 
@@ -67,6 +67,13 @@ impl<'a> Object<'a> {
         self.fields.get(index)
     }
 
+    /// Obtain the field [Value] for the field with the given name.
+    pub fn field(&self, name: &str) -> Option<&Value<'a>> {
+        let index = self.class.fields.iter().position(|f| f.name == name)?;
+
+        self.fields.get(index)
+    }
+
     /// Resolve all constants references in this instance recursively.
     pub fn resolve_constants(mut self, constants: &impl ConstantResolver<'a>) -> Result<Self> {
         self.fields = self
@@ -223,7 +230,7 @@ impl<'a> Value<'a> {
     where
         T: Deserialize<'de>,
     {
-        Ok(T::deserialize(ValueDeserializer::new(self, constants))?)
+        T::deserialize(ValueDeserializer::new(self, constants))
     }
 
     /// Deserialize into an enum.
@@ -243,9 +250,7 @@ impl<'a> Value<'a> {
     where
         T: Deserialize<'de>,
     {
-        Ok(T::deserialize(EventsEnumDeserializer::new(
-            self, constants,
-        ))?)
+        T::deserialize(EventsEnumDeserializer::new(self, constants))
     }
 }
 
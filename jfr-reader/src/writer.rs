@@ -0,0 +1,842 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Writing JFR chunks.
+//!
+//! [ChunkWriter] produces chunks that can be read back by this crate and by
+//! other JFR readers. It is the inverse of [crate::chunk::SliceReader].
+//!
+//! Types are declared up front using [ClassElement] and [FieldElement]
+//! instances, the same types used to describe parsed metadata. They are
+//! serialized into the chunk's metadata event when the chunk is finished.
+//!
+//! Events and constants are expressed as [Value] instances and are encoded
+//! using the field definitions of their declared class. All integers are
+//! written with LEB-128 compression.
+//!
+//! A recording consisting of multiple chunks is simply the concatenation of
+//! the bytes of each chunk.
+
+use crate::{
+    chunk::ChunkHeader,
+    chunk_event::{EventHeader, EVENT_TYPE_CONSTANT_POOL, EVENT_TYPE_METADATA},
+    common::{write_leb128_i16, write_leb128_i32, write_leb128_i64},
+    error::{Error, Result},
+    metadata::{AnnotationElement, ClassElement, ElementRecord, FieldElement, SettingsElement},
+    primitive::Primitive,
+    string_table::Encoding,
+    value::Value,
+};
+use rustc_hash::FxHashMap;
+use std::{borrow::Cow, collections::BTreeMap};
+
+/// File state of a chunk that is still being written.
+///
+/// Readers treat any state other than 0 (finished) and
+/// [ChunkHeader::FILE_STATE_UPDATING] as in progress.
+const FILE_STATE_IN_PROGRESS: u8 = 1;
+
+/// Construct a [ClassElement] describing a primitive type.
+///
+/// `name` should be one of the Java primitive type names (e.g. `long`) or
+/// `java.lang.String`.
+pub fn primitive_class(id: i64, name: &str) -> ClassElement<'static> {
+    ClassElement {
+        annotations: vec![],
+        fields: vec![],
+        settings: vec![],
+        name: Cow::Owned(name.to_string()),
+        super_type: None,
+        simple_type: None,
+        id,
+    }
+}
+
+/// Construct [ClassElement]s for all the primitive types.
+///
+/// Primitive types use the IDs assigned by HotSpot's `BasicType` enumeration.
+/// `java.lang.String` is assigned ID 20.
+pub fn primitive_classes() -> Vec<ClassElement<'static>> {
+    [
+        (4, "boolean"),
+        (5, "char"),
+        (6, "float"),
+        (7, "double"),
+        (8, "byte"),
+        (9, "short"),
+        (10, "int"),
+        (11, "long"),
+        (20, "java.lang.String"),
+    ]
+    .into_iter()
+    .map(|(id, name)| primitive_class(id, name))
+    .collect()
+}
+
+/// Write a string in string table / `java.lang.String` encoding.
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    if s.is_empty() {
+        buf.push(Encoding::EmptyString as u8);
+    } else {
+        buf.push(Encoding::Utf8ByteArray as u8);
+        write_leb128_i32(buf, s.len() as _);
+        buf.extend_from_slice(s.as_bytes());
+    }
+}
+
+/// Write an event record consisting of a size, type, and body.
+///
+/// The size is inclusive of itself. So we need to account for the width of
+/// its own LEB-128 encoding.
+fn write_event_record(buf: &mut Vec<u8>, event_type: i64, body: &[u8]) {
+    let mut type_data = vec![];
+    write_leb128_i64(&mut type_data, event_type);
+
+    let payload_size = type_data.len() + body.len();
+
+    let mut size_data = vec![];
+    let mut size_width = 1;
+
+    loop {
+        size_data.clear();
+        write_leb128_i64(&mut size_data, (payload_size + size_width) as _);
+
+        if size_data.len() == size_width {
+            break;
+        }
+
+        size_width = size_data.len();
+    }
+
+    buf.extend_from_slice(&size_data);
+    buf.extend_from_slice(&type_data);
+    buf.extend_from_slice(body);
+}
+
+/// Serialize an [ElementRecord] tree.
+fn write_element(buf: &mut Vec<u8>, el: &ElementRecord) {
+    write_leb128_i32(buf, el.name_index as _);
+    write_leb128_i32(buf, el.attributes.len() as _);
+
+    for (k, v) in &el.attributes {
+        write_leb128_i32(buf, *k);
+        write_leb128_i32(buf, *v);
+    }
+
+    write_leb128_i32(buf, el.children.len() as _);
+
+    for child in &el.children {
+        write_element(buf, child);
+    }
+}
+
+/// Builds the string table of a metadata event.
+#[derive(Default)]
+struct StringTableBuilder {
+    strings: Vec<String>,
+    indices: FxHashMap<String, i32>,
+}
+
+impl StringTableBuilder {
+    /// Obtain the index of a string, adding it to the table if necessary.
+    fn index(&mut self, s: &str) -> i32 {
+        if let Some(index) = self.indices.get(s) {
+            *index
+        } else {
+            let index = self.strings.len() as i32;
+            self.strings.push(s.to_string());
+            self.indices.insert(s.to_string(), index);
+
+            index
+        }
+    }
+
+    /// Construct an [ElementRecord] from string name and attributes.
+    fn element(
+        &mut self,
+        name: &str,
+        attributes: &[(&str, &str)],
+        children: Vec<ElementRecord>,
+    ) -> ElementRecord {
+        ElementRecord {
+            name_index: self.index(name) as _,
+            attributes: attributes
+                .iter()
+                .map(|(k, v)| (self.index(k), self.index(v)))
+                .collect(),
+            children,
+        }
+    }
+
+    fn annotation(&mut self, annotation: &AnnotationElement) -> ElementRecord {
+        let type_id = annotation.type_id.to_string();
+
+        let mut attributes = vec![("class", type_id.as_str())];
+        attributes.extend(
+            annotation
+                .values
+                .iter()
+                .map(|(k, v)| (k.as_ref(), v.as_ref())),
+        );
+
+        self.element("annotation", &attributes, vec![])
+    }
+
+    fn field(&mut self, field: &FieldElement) -> ElementRecord {
+        let type_id = field.type_id.to_string();
+        let dimension = field.dimension.map(|x| x.to_string());
+
+        let mut attributes = vec![("name", field.name.as_ref()), ("class", type_id.as_str())];
+        if let Some(dimension) = &dimension {
+            attributes.push(("dimension", dimension.as_str()));
+        }
+        if let Some(cp) = &field.constant_pool {
+            attributes.push(("constantPool", cp.as_ref()));
+        }
+
+        let children = field
+            .annotations
+            .iter()
+            .map(|a| self.annotation(a))
+            .collect();
+
+        self.element("field", &attributes, children)
+    }
+
+    fn setting(&mut self, setting: &SettingsElement) -> ElementRecord {
+        let type_id = setting.type_id.to_string();
+
+        let children = setting
+            .annotations
+            .iter()
+            .map(|a| self.annotation(a))
+            .collect();
+
+        self.element(
+            "setting",
+            &[
+                ("name", setting.name.as_ref()),
+                ("class", type_id.as_str()),
+                ("defaultValue", setting.default_value.as_ref()),
+            ],
+            children,
+        )
+    }
+
+    fn class(&mut self, class: &ClassElement) -> ElementRecord {
+        let id = class.id.to_string();
+
+        let mut attributes = vec![("name", class.name.as_ref())];
+        if let Some(v) = &class.super_type {
+            attributes.push(("superType", v.as_ref()));
+        }
+        if let Some(v) = &class.simple_type {
+            attributes.push(("simpleType", v.as_ref()));
+        }
+        attributes.push(("id", id.as_str()));
+
+        let mut children = class
+            .annotations
+            .iter()
+            .map(|a| self.annotation(a))
+            .collect::<Vec<_>>();
+        children.extend(class.fields.iter().map(|f| self.field(f)));
+        children.extend(class.settings.iter().map(|s| self.setting(s)));
+
+        self.element("class", &attributes, children)
+    }
+}
+
+/// Writes a single JFR chunk.
+///
+/// Classes must be registered via [Self::add_class] before events or
+/// constants referencing them are written. This includes primitive types:
+/// see [primitive_classes].
+///
+/// Constants added with [Self::add_constant] are buffered and written as a
+/// constant pool event when [Self::flush_constant_pool] or [Self::finish] is
+/// called.
+///
+/// Event data is buffered in memory. Call [Self::finish] to obtain the chunk
+/// data. [Self::flush] obtains the data written so far as a chunk that is
+/// still being written.
+pub struct ChunkWriter<'a> {
+    classes: FxHashMap<i64, ClassElement<'a>>,
+    /// Class IDs in registration order.
+    class_order: Vec<i64>,
+    locale: String,
+    gmt_offset: i32,
+    nanoseconds_since_epoch: u64,
+    duration_nanoseconds: u64,
+    start_ticks: u64,
+    ticks_per_second: u64,
+    final_chunk: bool,
+    /// Chunk data, inclusive of space reserved for the header.
+    data: Vec<u8>,
+    /// Encoded constants not yet written. Keyed by class ID.
+    pending_constants: BTreeMap<i64, (i32, Vec<u8>)>,
+    /// Offset of the most recently written constant pool event.
+    constant_pool_position: u64,
+    /// Offset of the most recently written metadata event.
+    metadata_position: u64,
+}
+
+impl<'a> Default for ChunkWriter<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> ChunkWriter<'a> {
+    /// Construct a new instance with no registered classes.
+    ///
+    /// The chunk defaults to starting at UNIX epoch, having nanosecond ticks
+    /// starting at 0, and being in UTC.
+    pub fn new() -> Self {
+        Self {
+            classes: FxHashMap::default(),
+            class_order: vec![],
+            locale: "en_US".to_string(),
+            gmt_offset: 0,
+            nanoseconds_since_epoch: 0,
+            duration_nanoseconds: 0,
+            start_ticks: 0,
+            ticks_per_second: 1_000_000_000,
+            final_chunk: true,
+            data: vec![0; ChunkHeader::HEADER_SIZE as usize],
+            pending_constants: BTreeMap::new(),
+            constant_pool_position: 0,
+            metadata_position: 0,
+        }
+    }
+
+    /// Register a class definition.
+    ///
+    /// Replaces an existing class having the same ID.
+    pub fn add_class(&mut self, class: ClassElement<'a>) {
+        if self.classes.insert(class.id, class.clone()).is_none() {
+            self.class_order.push(class.id);
+        }
+    }
+
+    /// Register multiple class definitions.
+    pub fn add_classes(&mut self, classes: impl IntoIterator<Item = ClassElement<'a>>) {
+        for class in classes {
+            self.add_class(class);
+        }
+    }
+
+    /// Obtain a registered class definition.
+    pub fn get_class(&self, id: i64) -> Option<&ClassElement<'a>> {
+        self.classes.get(&id)
+    }
+
+    /// Set the chunk start time in nanoseconds since UNIX epoch.
+    pub fn set_nanoseconds_since_epoch(&mut self, v: u64) {
+        self.nanoseconds_since_epoch = v;
+    }
+
+    /// Set the chunk duration in nanoseconds.
+    pub fn set_duration_nanoseconds(&mut self, v: u64) {
+        self.duration_nanoseconds = v;
+    }
+
+    /// Set the ticks value at chunk start and the tick frequency.
+    ///
+    /// `ticks_per_second` must not be 0.
+    pub fn set_ticks(&mut self, start_ticks: u64, ticks_per_second: u64) -> Result<()> {
+        if ticks_per_second == 0 {
+            return Err(Error::Write("ticks per second must not be 0".to_string()));
+        }
+
+        self.start_ticks = start_ticks;
+        self.ticks_per_second = ticks_per_second;
+
+        Ok(())
+    }
+
+    /// Set the locale and timezone offset (in milliseconds) of the recording.
+    pub fn set_region(&mut self, locale: impl ToString, gmt_offset: i32) {
+        self.locale = locale.to_string();
+        self.gmt_offset = gmt_offset;
+    }
+
+    /// Set whether this is the final chunk in a recording.
+    ///
+    /// Defaults to true.
+    pub fn set_final_chunk(&mut self, v: bool) {
+        self.final_chunk = v;
+    }
+
+    /// Encode a value of the given class.
+    fn write_value(&self, buf: &mut Vec<u8>, class_id: i64, value: &Value) -> Result<()> {
+        let class = self
+            .classes
+            .get(&class_id)
+            .ok_or(Error::ClassNotFound(class_id))?;
+
+        if Primitive::resolve_parser(class.name.as_ref()).is_some() {
+            let p = value.as_primitive().ok_or_else(|| {
+                Error::Write(format!("expected primitive value for {}", class.name))
+            })?;
+
+            match (class.name.as_ref(), p) {
+                ("boolean", Primitive::Boolean(v)) => buf.push(*v as u8),
+                ("byte", Primitive::Byte(v)) => buf.push(*v as u8),
+                ("short", Primitive::Short(v)) => write_leb128_i16(buf, *v),
+                ("int", Primitive::Integer(v)) => write_leb128_i32(buf, *v),
+                ("long", Primitive::Long(v)) => write_leb128_i64(buf, *v),
+                ("float", Primitive::Float(v)) => buf.extend_from_slice(&v.to_be_bytes()),
+                ("double", Primitive::Double(v)) => buf.extend_from_slice(&v.to_be_bytes()),
+                ("char", Primitive::Character(v)) => write_leb128_i32(buf, *v as i32),
+                ("java.lang.String", Primitive::NullString) => buf.push(Encoding::Null as u8),
+                ("java.lang.String", Primitive::String(s)) => write_string(buf, s),
+                ("java.lang.String", Primitive::StringConstantPool(index)) => {
+                    buf.push(Encoding::ConstantPool as u8);
+                    write_leb128_i64(buf, *index);
+                }
+                (name, p) => {
+                    return Err(Error::Write(format!(
+                        "primitive {:?} incompatible with {}",
+                        p, name
+                    )));
+                }
+            }
+
+            return Ok(());
+        }
+
+        let object = value
+            .as_object()
+            .ok_or_else(|| Error::Write(format!("expected object value for {}", class.name)))?;
+
+        self.write_fields(buf, class, object.iter_fields())
+    }
+
+    /// Encode values for all fields of a class.
+    ///
+    /// There must be exactly 1 value per field.
+    fn write_fields<'v>(
+        &self,
+        buf: &mut Vec<u8>,
+        class: &ClassElement,
+        values: impl ExactSizeIterator<Item = &'v Value<'v>>,
+    ) -> Result<()> {
+        if values.len() != class.fields.len() {
+            return Err(Error::Write(format!(
+                "{} has {} fields; got {} values",
+                class.name,
+                class.fields.len(),
+                values.len()
+            )));
+        }
+
+        for (field, value) in class.fields.iter().zip(values) {
+            if field.is_array_type() {
+                if let Value::Array(els) = value {
+                    write_leb128_i32(buf, els.len() as _);
+
+                    for v in els {
+                        self.write_field_single(buf, field, v)?;
+                    }
+                } else {
+                    return Err(Error::Write(format!(
+                        "expected array value for {}.{}",
+                        class.name, field.name
+                    )));
+                }
+            } else {
+                self.write_field_single(buf, field, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encode a non-array field value.
+    fn write_field_single(
+        &self,
+        buf: &mut Vec<u8>,
+        field: &FieldElement,
+        value: &Value,
+    ) -> Result<()> {
+        if field.constant_pool.is_some() {
+            match value {
+                Value::ConstantPool { constant_index, .. } => {
+                    write_leb128_i64(buf, *constant_index);
+                    Ok(())
+                }
+                Value::ConstantPoolNull => {
+                    write_leb128_i64(buf, 0);
+                    Ok(())
+                }
+                _ => Err(Error::Write(format!(
+                    "field {} requires a constant pool reference",
+                    field.name
+                ))),
+            }
+        } else {
+            self.write_value(buf, field.type_id, value)
+        }
+    }
+
+    /// Add a constant to the constant pool.
+    ///
+    /// `index` is the value events use to reference the constant. Index 0 is
+    /// reserved to represent null.
+    pub fn add_constant(&mut self, class_id: i64, index: i64, value: &Value) -> Result<()> {
+        let mut encoded = vec![];
+        write_leb128_i64(&mut encoded, index);
+        self.write_value(&mut encoded, class_id, value)?;
+
+        let entry = self.pending_constants.entry(class_id).or_default();
+        entry.0 += 1;
+        entry.1.extend_from_slice(&encoded);
+
+        Ok(())
+    }
+
+    /// Write an event having the given class / event type.
+    ///
+    /// `fields` contains a value for each field in the class definition.
+    pub fn write_event(&mut self, class_id: i64, fields: &[Value]) -> Result<()> {
+        let class = self
+            .classes
+            .get(&class_id)
+            .ok_or(Error::ClassNotFound(class_id))?;
+
+        let mut body = vec![];
+        self.write_fields(&mut body, class, fields.iter())?;
+
+        write_event_record(&mut self.data, class_id, &body);
+
+        Ok(())
+    }
+
+    /// Write an already encoded event record.
+    ///
+    /// `data` is the full event record, inclusive of its size and type header.
+    /// This can be used to copy events between chunks having compatible
+    /// metadata.
+    pub fn write_raw_event(&mut self, data: &[u8]) -> Result<()> {
+        let (_, header) = EventHeader::parse(data)?;
+
+        if header.size as usize != data.len() {
+            return Err(Error::Write(format!(
+                "event record declares size {} but has {} bytes",
+                header.size,
+                data.len()
+            )));
+        }
+
+        self.data.extend_from_slice(data);
+
+        Ok(())
+    }
+
+    /// Write buffered constants as a constant pool event.
+    ///
+    /// Does nothing if there are no buffered constants.
+    pub fn flush_constant_pool(&mut self) {
+        if !self.pending_constants.is_empty() {
+            self.write_constant_pool();
+        }
+    }
+
+    fn write_constant_pool(&mut self) {
+        let position = self.data.len() as u64;
+
+        // Constant pool events are a linked list. Each points to the previous
+        // one via a relative offset. 0 terminates the list.
+        let delta = if self.constant_pool_position == 0 {
+            0
+        } else {
+            self.constant_pool_position as i64 - position as i64
+        };
+
+        let mut body = vec![];
+        write_leb128_i64(&mut body, self.start_ticks as _);
+        write_leb128_i64(&mut body, 0);
+        write_leb128_i64(&mut body, delta);
+        body.push(0);
+        write_leb128_i32(&mut body, self.pending_constants.len() as _);
+
+        for (class_id, (count, data)) in std::mem::take(&mut self.pending_constants) {
+            write_leb128_i64(&mut body, class_id);
+            write_leb128_i32(&mut body, count);
+            body.extend_from_slice(&data);
+        }
+
+        write_event_record(&mut self.data, EVENT_TYPE_CONSTANT_POOL, &body);
+        self.constant_pool_position = position;
+    }
+
+    fn write_metadata(&mut self) {
+        self.metadata_position = self.data.len() as u64;
+
+        let mut st = StringTableBuilder::default();
+
+        let classes = self
+            .class_order
+            .iter()
+            .map(|id| st.class(&self.classes[id]))
+            .collect::<Vec<_>>();
+
+        let metadata = st.element("metadata", &[], classes);
+        let gmt_offset = self.gmt_offset.to_string();
+        let region = st.element(
+            "region",
+            &[("locale", &self.locale), ("gmtOffset", &gmt_offset)],
+            vec![],
+        );
+        let root = st.element("root", &[], vec![metadata, region]);
+
+        let mut body = vec![];
+        write_leb128_i64(&mut body, self.start_ticks as _);
+        write_leb128_i64(&mut body, 0);
+        // Metadata ID.
+        write_leb128_i64(&mut body, 1);
+        write_leb128_i32(&mut body, st.strings.len() as _);
+
+        for s in &st.strings {
+            write_string(&mut body, s);
+        }
+
+        write_element(&mut body, &root);
+
+        write_event_record(&mut self.data, EVENT_TYPE_METADATA, &body);
+    }
+
+    /// Write buffered constants and the metadata event.
+    fn write_checkpoint(&mut self) {
+        // Readers expect at least 1 constant pool event.
+        if !self.pending_constants.is_empty() || self.constant_pool_position == 0 {
+            self.write_constant_pool();
+        }
+
+        self.write_metadata();
+    }
+
+    /// Construct the chunk header describing the data written so far.
+    fn header(&self, file_state: u8, final_chunk: bool) -> ChunkHeader {
+        let mut flags = ChunkHeader::FLAG_COMPRESSED_INTEGERS;
+        if final_chunk {
+            flags |= ChunkHeader::FLAG_FINAL_CHUNK;
+        }
+
+        ChunkHeader {
+            major: 2,
+            minor: 1,
+            chunk_size: self.data.len() as _,
+            constant_pool_position: self.constant_pool_position,
+            metadata_position: self.metadata_position,
+            nanoseconds_since_epoch: self.nanoseconds_since_epoch,
+            duration_nanoseconds: self.duration_nanoseconds,
+            start_ticks: self.start_ticks,
+            ticks_per_second: self.ticks_per_second,
+            state_and_flags: ((file_state as u32) << 24) | flags as u32,
+        }
+    }
+
+    /// Flush the chunk and obtain the data written so far.
+    ///
+    /// This behaves like a JVM streaming a recording to disk: buffered
+    /// constants and the metadata event are written, and the returned data's
+    /// header describes a chunk that is still being written. Data returned by
+    /// later calls, and by [Self::finish], extends this data. Only the header
+    /// changes.
+    pub fn flush(&mut self) -> Vec<u8> {
+        self.write_checkpoint();
+
+        let mut data = self.data.clone();
+        data[0..ChunkHeader::HEADER_SIZE as usize]
+            .copy_from_slice(&self.header(FILE_STATE_IN_PROGRESS, false).to_bytes());
+
+        data
+    }
+
+    /// Finish writing the chunk and obtain its data.
+    ///
+    /// Buffered constants and the metadata event are written and the chunk
+    /// header is populated.
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        self.write_checkpoint();
+
+        let header = self.header(0, self.final_chunk);
+        self.data[0..ChunkHeader::HEADER_SIZE as usize].copy_from_slice(&header.to_bytes());
+
+        Ok(self.data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        chunk::SliceReader,
+        common::leb128_i64,
+        recording::Recording,
+        test_util::{
+            add_thread, constant, field, long, string, writer, INT_ID, LONG_ID, STRING_ID,
+            THREAD_ID,
+        },
+    };
+
+    const EVENT_ID: i64 = 101;
+
+    fn event_writer() -> ChunkWriter<'static> {
+        let mut w = writer();
+
+        let mut event = primitive_class(EVENT_ID, "test.Event");
+        event.super_type = Some(Cow::Borrowed("jdk.jfr.Event"));
+        event.fields = vec![
+            field("startTime", LONG_ID, false),
+            field("eventThread", THREAD_ID, true),
+            field("message", STRING_ID, false),
+            FieldElement {
+                dimension: Some(1),
+                ..field("values", INT_ID, false)
+            },
+        ];
+        w.add_class(event);
+
+        w
+    }
+
+    #[test]
+    fn leb128_roundtrip() {
+        for v in [0, 1, 127, 128, 300, i64::MAX, -1, i64::MIN] {
+            let mut buf = vec![];
+            write_leb128_i64(&mut buf, v);
+            assert_eq!(leb128_i64(&buf).unwrap(), (&[][..], v));
+        }
+    }
+
+    #[test]
+    fn chunk_roundtrip() -> Result<()> {
+        let mut w = event_writer();
+        w.set_nanoseconds_since_epoch(1_000_000_000);
+
+        add_thread(&mut w, 1, "main")?;
+
+        for i in 0..3 {
+            w.write_event(
+                EVENT_ID,
+                &[
+                    long(i * 1000),
+                    constant(THREAD_ID, 1),
+                    string(format!("event {}", i)),
+                    Value::Array(vec![Value::Primitive(Primitive::Integer(-1)); i as usize]),
+                ],
+            )?;
+        }
+
+        let data = w.finish()?;
+
+        let (_, reader) = SliceReader::new(&data)?;
+        assert_eq!(reader.chunk_size(), data.len());
+
+        let recording = Recording::from_data(data.clone());
+        let events = recording.iter_events().collect::<Result<Vec<_>>>()?;
+        assert_eq!(events.len(), 3);
+
+        for (i, event) in events.iter().enumerate() {
+            assert_eq!(event.class_name(), Some("test.Event"));
+
+            let resolver = event.resolver();
+            let constants = resolver.constant_pool_values()?;
+            let object = event.resolve_object()?.resolve_constants(&constants)?;
+
+            assert!(matches!(
+                object.field_at(0),
+                Some(Value::Primitive(Primitive::Long(v))) if *v == i as i64 * 1000
+            ));
+            let thread = object.field_at(1).unwrap().as_object().unwrap();
+            assert!(matches!(
+                thread.field_at(0),
+                Some(Value::Primitive(Primitive::String(s))) if s == "main"
+            ));
+            assert!(matches!(
+                object.field_at(2),
+                Some(Value::Primitive(Primitive::String(s))) if *s == format!("event {}", i)
+            ));
+            assert!(matches!(object.field_at(3), Some(Value::Array(a)) if a.len() == i));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn ticks() -> Result<()> {
+        // e.g. the 10 MHz performance counter used on Windows.
+        let mut w = event_writer();
+        w.set_nanoseconds_since_epoch(1_000_000_000);
+        w.set_ticks(1_000, 10_000_000)?;
+
+        add_thread(&mut w, 1, "main")?;
+        w.write_event(
+            EVENT_ID,
+            &[
+                long(1_015),
+                constant(THREAD_ID, 1),
+                string("event"),
+                Value::Array(vec![]),
+            ],
+        )?;
+
+        let recording = Recording::from_data(w.finish()?);
+        let events = recording.iter_events().collect::<Result<Vec<_>>>()?;
+        let time_resolver = events[0].time_resolver();
+
+        assert_eq!(time_resolver.chunk_start_delta_nanoseconds(1_015), 1_500);
+        assert_eq!(time_resolver.epoch_nanoseconds(1_015), 1_000_001_500);
+        assert_eq!(time_resolver.delta_nanoseconds(0, 3), 300);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_mismatched_values() {
+        let mut w = event_writer();
+
+        assert!(w
+            .write_event(EVENT_ID, &[Value::Primitive(Primitive::Long(0))])
+            .is_err());
+        assert!(w
+            .write_event(
+                EVENT_ID,
+                &[
+                    Value::Primitive(Primitive::Integer(0)),
+                    Value::ConstantPoolNull,
+                    Value::Primitive(Primitive::NullString),
+                    Value::Array(vec![]),
+                ]
+            )
+            .is_err());
+        assert!(w
+            .write_event(
+                EVENT_ID,
+                &[
+                    long(0),
+                    Value::ConstantPoolNull,
+                    Value::Primitive(Primitive::NullString),
+                    Value::Array(vec![]),
+                    long(0),
+                ]
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_ticks() {
+        let mut w = event_writer();
+
+        assert!(w.set_ticks(0, 0).is_err());
+        assert!(w.set_ticks(10, 10_000_000).is_ok());
+    }
+}
@@ -0,0 +1,369 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Dynamic agent loading.
+//!
+//! The `load` attach command loads a JVMTI agent library into a running
+//! JVM and calls its `Agent_OnAttach` function. It takes 3 arguments: the
+//! library name or path, whether it is an absolute path, and options.
+//!
+//! Java agents are loaded via the JDK's `instrument` library, whose options
+//! are `<jar path>[=<agent options>]`.
+//!
+//! Agent files are opened by the JVM, so their paths must be valid in the
+//! JVM's mount namespace. [load] copies files into the JVM's `/tmp` when the
+//! JVM is in a different mount namespace. Copies aren't removed because the
+//! JVM may reopen them later (e.g. to load classes from a JAR).
+//!
+//! The JVM's `/tmp` is writable by the JVM. Copies are created under
+//! unpredictable names without following symlinks, so a JVM can't redirect
+//! them to files outside its root filesystem.
+
+use crate::{CommandResponse, Error, Result, UnixSocketConnection};
+use std::{
+    ffi::CString,
+    fs::File,
+    io::Read,
+    os::unix::{
+        fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+        io::{AsRawFd, FromRawFd},
+    },
+    path::{Path, PathBuf},
+};
+
+/// Return code of `Agent_OnAttach` when memory is exhausted.
+const JNI_ENOMEM: i32 = -4;
+
+/// `instrument` return code for a missing JAR or `Agent-Class` attribute.
+const ATTACH_ERROR_BADJAR: i32 = 100;
+
+/// `instrument` return code for failure to add the JAR to the class path.
+const ATTACH_ERROR_NOTONCP: i32 = 101;
+
+/// `instrument` return code for an exception raised by `agentmain`.
+const ATTACH_ERROR_STARTFAIL: i32 = 102;
+
+/// How many names to try when staging a file.
+const STAGE_ATTEMPTS: usize = 16;
+
+/// Failures loading an agent.
+#[derive(Clone, Debug, Eq, thiserror::Error, PartialEq)]
+pub enum AgentError {
+    #[error("failed to load agent library: {0}")]
+    LoadFailed(String),
+
+    #[error("insufficient memory")]
+    OutOfMemory,
+
+    #[error("agent JAR not found or no Agent-Class attribute")]
+    BadJar,
+
+    #[error("unable to add JAR file to system class path")]
+    NotOnClassPath,
+
+    #[error("agent JAR loaded but agent failed to initialize")]
+    StartFailed,
+
+    #[error("Agent_OnAttach failed with return code {0}")]
+    InitializationFailed(i32),
+}
+
+/// An agent to load.
+#[derive(Clone, Debug)]
+pub enum Agent {
+    /// A Java agent JAR with an `Agent-Class` manifest attribute.
+    Jar {
+        path: PathBuf,
+        options: Option<String>,
+    },
+    /// A native agent library at a path.
+    Native {
+        path: PathBuf,
+        options: Option<String>,
+    },
+    /// A native agent library found by name in the JVM's library paths.
+    /// e.g. `jdwp` for `libjdwp.so`.
+    Library {
+        name: String,
+        options: Option<String>,
+    },
+}
+
+impl Agent {
+    /// Map a non-zero `Agent_OnAttach` return code to an error.
+    fn error(&self, code: i32) -> AgentError {
+        match (self, code) {
+            (_, JNI_ENOMEM) => AgentError::OutOfMemory,
+            (Self::Jar { .. }, ATTACH_ERROR_BADJAR) => AgentError::BadJar,
+            (Self::Jar { .. }, ATTACH_ERROR_NOTONCP) => AgentError::NotOnClassPath,
+            (Self::Jar { .. }, ATTACH_ERROR_STARTFAIL) => AgentError::StartFailed,
+            (_, code) => AgentError::InitializationFailed(code),
+        }
+    }
+}
+
+/// Whether a process is in our mount namespace.
+fn same_mount_namespace(procfs: &Path, pid: i32) -> bool {
+    let ours = std::fs::metadata(procfs.join("self").join("ns").join("mnt"));
+    let theirs = std::fs::metadata(procfs.join(pid.to_string()).join("ns").join("mnt"));
+
+    match (ours, theirs) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// A random value for naming files.
+fn random_u64() -> Result<u64> {
+    let mut buf = [0u8; 8];
+    File::open("/dev/urandom")?.read_exact(&mut buf)?;
+
+    Ok(u64::from_ne_bytes(buf))
+}
+
+/// Make a file readable by a process, returning its path in the process's mount namespace.
+///
+/// If the process is in another mount namespace, or we can't tell, the file
+/// is copied into the `/tmp` of the process's root filesystem.
+pub fn stage_file(pid: i32, path: &Path) -> Result<PathBuf> {
+    stage_file_in(Path::new("/proc"), pid, path)
+}
+
+/// Make a file readable by a process using the given procfs.
+///
+/// See [stage_file].
+pub fn stage_file_in(procfs: &Path, pid: i32, path: &Path) -> Result<PathBuf> {
+    let path = std::fs::canonicalize(path)?;
+
+    if same_mount_namespace(procfs, pid) {
+        return Ok(path);
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::InvalidArgument(format!("{} has no file name", path.display())))?;
+
+    let mut source = File::open(&path)?;
+
+    // The process controls its /tmp. Don't follow it if it is a symlink.
+    let tmp = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY | libc::O_NOFOLLOW)
+        .open(procfs.join(pid.to_string()).join("root").join("tmp"))?;
+
+    for _ in 0..STAGE_ATTEMPTS {
+        let name = format!(
+            "jvm-attach-{}-{:016x}-{}",
+            std::process::id(),
+            random_u64()?,
+            file_name.to_string_lossy()
+        );
+        let c_name = CString::new(name.as_bytes())
+            .map_err(|e| Error::InvalidArgument(format!("{}: {}", path.display(), e)))?;
+
+        // Like OpenOptions::create_new(), but relative to the directory we
+        // opened and without following a symlink planted at the name.
+        let fd = unsafe {
+            libc::openat(
+                tmp.as_raw_fd(),
+                c_name.as_ptr(),
+                libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+                0o644 as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            let e = std::io::Error::last_os_error();
+
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                continue;
+            }
+
+            return Err(e.into());
+        }
+        let mut staged = unsafe { File::from_raw_fd(fd) };
+
+        std::io::copy(&mut source, &mut staged)?;
+        // The mode passed to openat() is subject to the umask.
+        staged.set_permissions(std::fs::Permissions::from_mode(0o644))?;
+
+        return Ok(Path::new("/tmp").join(name));
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        "unable to find an unused name to stage file",
+    )
+    .into())
+}
+
+/// Load an agent into a JVM.
+pub fn load(connection: &UnixSocketConnection, agent: &Agent) -> Result<()> {
+    let (library, absolute, options) = match agent {
+        Agent::Jar { path, options } => {
+            let path = stage_file_in(&connection.options().procfs, connection.pid(), path)?;
+            let path = path.to_string_lossy();
+
+            // instrument splits its options at the first `=`.
+            if path.contains('=') {
+                return Err(Error::InvalidArgument(format!(
+                    "agent JAR path {} contains '='",
+                    path
+                )));
+            }
+
+            let options = match options {
+                Some(options) => format!("{}={}", path, options),
+                None => path.to_string(),
+            };
+
+            ("instrument".to_string(), false, options)
+        }
+        Agent::Native { path, options } => (
+            stage_file_in(&connection.options().procfs, connection.pid(), path)?
+                .to_string_lossy()
+                .to_string(),
+            true,
+            options.clone().unwrap_or_default(),
+        ),
+        Agent::Library { name, options } => {
+            (name.clone(), false, options.clone().unwrap_or_default())
+        }
+    };
+
+    let absolute = if absolute { "true" } else { "false" };
+
+    match connection.send_command("load", vec![&library, absolute, &options])? {
+        CommandResponse::Success(mut sock) => {
+            let mut output = String::new();
+            sock.read_to_string(&mut output)?;
+
+            let code = output
+                .lines()
+                .next()
+                .and_then(|line| line.strip_prefix("return code: "))
+                .and_then(|code| code.trim().parse::<i32>().ok())
+                .ok_or_else(|| AgentError::LoadFailed(output.trim().to_string()))?;
+
+            if code == 0 {
+                Ok(())
+            } else {
+                Err(agent.error(code).into())
+            }
+        }
+        CommandResponse::Error(message) => {
+            Err(AgentError::LoadFailed(message.trim().to_string()).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A procfs holding a process in another mount namespace.
+    fn procfs(pid: i32) -> Result<tempfile::TempDir> {
+        let procfs = tempfile::tempdir()?;
+
+        for dir in ["self".to_string(), pid.to_string()] {
+            let ns = procfs.path().join(dir).join("ns");
+            std::fs::create_dir_all(&ns)?;
+            std::fs::write(ns.join("mnt"), "")?;
+        }
+        std::fs::create_dir_all(procfs.path().join(pid.to_string()).join("root").join("tmp"))?;
+
+        Ok(procfs)
+    }
+
+    #[test]
+    fn stage() -> Result<()> {
+        let procfs = procfs(42)?;
+        let source = tempfile::tempdir()?;
+        let agent = source.path().join("agent.jar");
+        std::fs::write(&agent, "agent")?;
+
+        let staged = stage_file_in(procfs.path(), 42, &agent)?;
+        assert_eq!(staged.parent(), Some(Path::new("/tmp")));
+
+        let name = staged.file_name().unwrap().to_string_lossy();
+        assert!(name.starts_with(&format!("jvm-attach-{}-", std::process::id())));
+        assert!(name.ends_with("-agent.jar"));
+
+        let copy = procfs
+            .path()
+            .join("42")
+            .join("root")
+            .join("tmp")
+            .join(&*name);
+        assert_eq!(std::fs::read_to_string(&copy)?, "agent");
+        assert_eq!(
+            std::fs::metadata(&copy)?.permissions().mode() & 0o777,
+            0o644
+        );
+
+        // Names aren't reused.
+        assert_ne!(stage_file_in(procfs.path(), 42, &agent)?, staged);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stage_same_namespace() -> Result<()> {
+        let procfs = procfs(42)?;
+        let ns = procfs.path().join("42").join("ns").join("mnt");
+        std::fs::remove_file(&ns)?;
+        std::fs::hard_link(procfs.path().join("self").join("ns").join("mnt"), &ns)?;
+
+        let source = tempfile::tempdir()?;
+        let agent = source.path().join("agent.jar");
+        std::fs::write(&agent, "agent")?;
+
+        assert_eq!(
+            stage_file_in(procfs.path(), 42, &agent)?,
+            std::fs::canonicalize(&agent)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn stage_symlinked_tmp() -> Result<()> {
+        let procfs = procfs(42)?;
+        let target = tempfile::tempdir()?;
+        let tmp = procfs.path().join("42").join("root").join("tmp");
+        std::fs::remove_dir(&tmp)?;
+        std::os::unix::fs::symlink(target.path(), &tmp)?;
+
+        let agent = procfs.path().join("agent.jar");
+        std::fs::write(&agent, "agent")?;
+
+        assert!(stage_file_in(procfs.path(), 42, &agent).is_err());
+        assert_eq!(std::fs::read_dir(target.path())?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn return_codes() {
+        let jar = Agent::Jar {
+            path: "agent.jar".into(),
+            options: None,
+        };
+        let native = Agent::Library {
+            name: "agent".into(),
+            options: None,
+        };
+
+        assert_eq!(jar.error(-4), AgentError::OutOfMemory);
+        assert_eq!(jar.error(100), AgentError::BadJar);
+        assert_eq!(jar.error(101), AgentError::NotOnClassPath);
+        assert_eq!(jar.error(102), AgentError::StartFailed);
+        assert_eq!(jar.error(1), AgentError::InitializationFailed(1));
+        assert_eq!(native.error(-4), AgentError::OutOfMemory);
+        assert_eq!(native.error(100), AgentError::InitializationFailed(100));
+    }
+}
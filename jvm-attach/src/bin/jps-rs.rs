@@ -0,0 +1,61 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A replacement for the JDK's `jps` tool.
+
+use jvm_attach::{discovery::find_jvms, Result};
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: {} [-m] [-v]",
+        std::env::current_exe().unwrap().display()
+    );
+    std::process::exit(1);
+}
+
+fn main() -> Result<()> {
+    let mut show_args = false;
+    let mut show_jvm_args = false;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-m" => show_args = true,
+            "-v" => show_jvm_args = true,
+            _ => usage(),
+        }
+    }
+
+    for jvm in find_jvms()? {
+        let mut line = jvm.pid.to_string();
+
+        if jvm.ns_pid != jvm.pid {
+            line.push_str(&format!(" ({})", jvm.ns_pid));
+        }
+
+        line.push(' ');
+        line.push_str(
+            jvm.main
+                .as_deref()
+                .unwrap_or("-- main class information unavailable"),
+        );
+
+        if show_args && !jvm.args.is_empty() {
+            line.push(' ');
+            line.push_str(&jvm.args.join(" "));
+        }
+
+        if show_jvm_args && !jvm.jvm_args.is_empty() {
+            line.push(' ');
+            line.push_str(&jvm.jvm_args.join(" "));
+        }
+
+        println!("{}", line);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A replacement for the JDK's `jstat` tool.
+
+use jvm_attach::{
+    jstat::{ClassStats, CompilerStats, GcStats, GcUtil, Sampler, Statistics},
+    Result,
+};
+use std::{
+    io::{BufWriter, Write},
+    time::Duration,
+};
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: {} -gc|-gcutil|-class|-compiler [-h<lines>] <pid> [<interval>[s|ms] [<count>]]",
+        std::env::current_exe().unwrap().display()
+    );
+    std::process::exit(1);
+}
+
+fn parse_interval(s: &str) -> Option<Duration> {
+    if let Some(v) = s.strip_suffix("ms") {
+        v.parse().ok().map(Duration::from_millis)
+    } else if let Some(v) = s.strip_suffix('s') {
+        v.parse().ok().map(Duration::from_secs)
+    } else {
+        s.parse().ok().map(Duration::from_millis)
+    }
+}
+
+fn run<S: Statistics>(sampler: Sampler, header_lines: Option<usize>) -> Result<()> {
+    let mut dest = BufWriter::new(std::io::stdout().lock());
+
+    for (index, perf) in sampler.enumerate() {
+        let perf = perf?;
+
+        if index == 0 || matches!(header_lines, Some(n) if index % n == 0) {
+            writeln!(dest, "{}", S::format_header())?;
+        }
+
+        writeln!(dest, "{}", S::from_perf_data(&perf).format_row())?;
+        dest.flush()?;
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut option = None;
+    let mut header_lines = None;
+    let mut positional = vec![];
+
+    for arg in std::env::args().skip(1) {
+        if let Some(lines) = arg.strip_prefix("-h") {
+            header_lines = Some(
+                lines
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|v| *v > 0)
+                    .unwrap_or_else(|| usage()),
+            );
+        } else if let Some(name) = arg.strip_prefix('-').filter(|_| option.is_none()) {
+            option = Some(name.to_string());
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    let option = option.unwrap_or_else(|| usage());
+
+    let (pid, interval, count) = match positional.as_slice() {
+        [pid] => (pid, None, Some(1)),
+        [pid, interval] => (pid, Some(interval), None),
+        [pid, interval, count] => (
+            pid,
+            Some(interval),
+            Some(count.parse().unwrap_or_else(|_| usage())),
+        ),
+        _ => usage(),
+    };
+
+    let pid = pid.parse().unwrap_or_else(|_| usage());
+    let interval = interval
+        .map(|v| parse_interval(v).unwrap_or_else(|| usage()))
+        .unwrap_or_default();
+
+    let mut sampler = Sampler::new(pid, interval)?;
+    sampler.set_count(count);
+
+    match option.as_str() {
+        GcStats::OPTION => run::<GcStats>(sampler, header_lines),
+        GcUtil::OPTION => run::<GcUtil>(sampler, header_lines),
+        ClassStats::OPTION => run::<ClassStats>(sampler, header_lines),
+        CompilerStats::OPTION => run::<CompilerStats>(sampler, header_lines),
+        _ => usage(),
+    }
+}
@@ -0,0 +1,706 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Typed diagnostic commands.
+//!
+//! HotSpot diagnostic commands (what `jcmd` invokes) are sent over the
+//! attach socket as a single `jcmd` attach command whose argument is the
+//! command line. e.g. `VM.flags -all`.
+//!
+//! Each type implementing [DiagnosticCommand] represents a command, its
+//! arguments, and the parsed form of its output. Execute them with
+//! [crate::UnixSocketConnection::execute]. [RawCommand] is available for
+//! commands without a dedicated type. JFR commands are in [crate::jfr].
+
+use crate::{threaddump::ThreadDump, Error, Result};
+use std::{collections::BTreeMap, fmt::Display};
+
+/// Maximum length in bytes of an attach command argument with attach protocol
+/// version 1, including its NULL terminator.
+///
+/// HotSpot rejects longer arguments. Protocol version 2 has no such limit.
+pub const ARGUMENT_LENGTH_MAX: usize = 1024;
+
+/// Arguments to a diagnostic command.
+///
+/// Values are quoted as needed. Values that can't be represented are
+/// rejected.
+#[derive(Clone, Debug, Default)]
+pub struct Arguments {
+    parts: Vec<String>,
+}
+
+impl Arguments {
+    /// Add a boolean flag. e.g. `-all`. Nothing is added if not enabled.
+    pub fn flag(&mut self, name: &str, enabled: bool) {
+        if enabled {
+            self.parts.push(format!("-{}", name));
+        }
+    }
+
+    /// Add a `name=value` option.
+    pub fn option(&mut self, name: &str, value: impl Display) -> Result<()> {
+        self.parts
+            .push(format!("{}={}", name, quote(&value.to_string())?));
+
+        Ok(())
+    }
+
+    /// Add a `name=value` option if a value is present.
+    pub fn option_opt(&mut self, name: &str, value: Option<impl Display>) -> Result<()> {
+        if let Some(value) = value {
+            self.option(name, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a positional argument.
+    pub fn positional(&mut self, value: impl Display) -> Result<()> {
+        self.parts.push(quote(&value.to_string())?);
+
+        Ok(())
+    }
+}
+
+/// Quote a value for the diagnostic command argument parser.
+///
+/// Arguments are separated by spaces. Values containing spaces must be
+/// enclosed in single or double quotes. There is no escape mechanism.
+fn quote(value: &str) -> Result<String> {
+    if value.contains(['\0', '\n', '\r']) {
+        return Err(Error::InvalidArgument(format!(
+            "{:?} contains control characters",
+            value
+        )));
+    }
+
+    if !value.is_empty() && !value.contains([' ', '"', '\'']) {
+        Ok(value.to_string())
+    } else if !value.contains('"') {
+        Ok(format!("\"{}\"", value))
+    } else if !value.contains('\'') {
+        Ok(format!("'{}'", value))
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "{:?} contains both quote characters",
+            value
+        )))
+    }
+}
+
+/// A diagnostic command.
+pub trait DiagnosticCommand {
+    /// The parsed output of the command.
+    type Response;
+
+    /// The name of the command. e.g. `VM.version`.
+    fn name(&self) -> &str;
+
+    /// Register arguments to the command.
+    fn arguments(&self, args: &mut Arguments) -> Result<()>;
+
+    /// Parse the output of the command.
+    fn parse_response(&self, output: String) -> Result<Self::Response>;
+
+    /// The full command line sent to the JVM.
+    ///
+    /// Command lines of [ARGUMENT_LENGTH_MAX] bytes or more require attach
+    /// protocol version 2.
+    fn command_line(&self) -> Result<String> {
+        let mut args = Arguments::default();
+        self.arguments(&mut args)?;
+
+        Ok(std::iter::once(self.name().to_string())
+            .chain(args.parts)
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+}
+
+/// A command without a dedicated type. The response is the raw output.
+#[derive(Clone, Debug)]
+pub struct RawCommand {
+    pub name: String,
+    pub arguments: Vec<String>,
+}
+
+impl RawCommand {
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            arguments: vec![],
+        }
+    }
+
+    /// Add an argument. It is sent verbatim.
+    pub fn arg(mut self, arg: impl ToString) -> Self {
+        self.arguments.push(arg.to_string());
+        self
+    }
+}
+
+impl DiagnosticCommand for RawCommand {
+    type Response = String;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        if self.name.is_empty() || self.name.contains(char::is_whitespace) {
+            return Err(Error::InvalidArgument(format!(
+                "invalid command name: {:?}",
+                self.name
+            )));
+        }
+
+        for arg in &self.arguments {
+            if arg.contains(['\0', '\n', '\r']) {
+                return Err(Error::InvalidArgument(format!(
+                    "{:?} contains control characters",
+                    arg
+                )));
+            }
+
+            args.parts.push(arg.clone());
+        }
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        Ok(output)
+    }
+}
+
+/// `VM.version`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VmVersion;
+
+/// Response to [VmVersion].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VmVersionInfo {
+    /// e.g. `OpenJDK 64-Bit Server VM`.
+    pub vm_name: String,
+    /// e.g. `17.0.8+7`.
+    pub vm_version: String,
+    /// e.g. `17.0.8`.
+    pub jdk_version: Option<String>,
+}
+
+impl DiagnosticCommand for VmVersion {
+    type Response = VmVersionInfo;
+
+    fn name(&self) -> &str {
+        "VM.version"
+    }
+
+    fn arguments(&self, _: &mut Arguments) -> Result<()> {
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        let mut lines = output.lines();
+
+        let (vm_name, vm_version) = lines
+            .next()
+            .and_then(|line| line.rsplit_once(" version "))
+            .ok_or(Error::ResponseParse("VM version line not found"))?;
+
+        let jdk_version = lines.find_map(|line| line.strip_prefix("JDK ").map(String::from));
+
+        Ok(VmVersionInfo {
+            vm_name: vm_name.to_string(),
+            vm_version: vm_version.trim().to_string(),
+            jdk_version,
+        })
+    }
+}
+
+/// `VM.flags`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VmFlags {
+    /// Print all flags, not just those set on the command line or ergonomically.
+    pub all: bool,
+}
+
+/// A JVM flag.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VmFlag {
+    pub name: String,
+    /// The value. Booleans are `true` or `false`.
+    pub value: String,
+    /// The type of the flag. e.g. `uintx`. Only available with [VmFlags::all].
+    pub flag_type: Option<String>,
+    /// The kind of the flag. e.g. `product`. Only available with [VmFlags::all].
+    pub kind: Option<String>,
+    /// Where the value comes from. e.g. `default`. Only available with [VmFlags::all].
+    pub origin: Option<String>,
+}
+
+/// Response to [VmFlags].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VmFlagValues {
+    pub flags: Vec<VmFlag>,
+}
+
+impl VmFlagValues {
+    /// Obtain a flag by name.
+    pub fn get(&self, name: &str) -> Option<&VmFlag> {
+        self.flags.iter().find(|flag| flag.name == name)
+    }
+}
+
+/// Split a trailing `{...}` group from a string.
+fn split_braced_suffix(s: &str) -> Option<(&str, &str)> {
+    let s = s.trim_end().strip_suffix('}')?;
+    let (head, value) = s.rsplit_once('{')?;
+
+    Some((head, value))
+}
+
+impl DiagnosticCommand for VmFlags {
+    type Response = VmFlagValues;
+
+    fn name(&self) -> &str {
+        "VM.flags"
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        args.flag("all", self.all);
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        let mut flags = vec![];
+
+        for line in output.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if line.starts_with("-XX:") {
+                // Flags set on the command line or ergonomically, on one line.
+                for arg in line.split_whitespace() {
+                    let arg = arg
+                        .strip_prefix("-XX:")
+                        .ok_or(Error::ResponseParse("flag doesn't begin with -XX:"))?;
+
+                    let (name, value) = if let Some(name) = arg.strip_prefix('+') {
+                        (name, "true")
+                    } else if let Some(name) = arg.strip_prefix('-') {
+                        (name, "false")
+                    } else {
+                        arg.split_once('=')
+                            .ok_or(Error::ResponseParse("flag has no value"))?
+                    };
+
+                    flags.push(VmFlag {
+                        name: name.to_string(),
+                        value: value.to_string(),
+                        flag_type: None,
+                        kind: None,
+                        origin: None,
+                    });
+                }
+            } else if line.starts_with('[') {
+                // Section header. e.g. `[Global flags]`.
+                continue;
+            } else {
+                // <type> <name> = <value> {<kind>} {<origin>}
+                let (head, origin) = split_braced_suffix(line)
+                    .ok_or(Error::ResponseParse("flag origin not found"))?;
+                let (head, kind) =
+                    split_braced_suffix(head).ok_or(Error::ResponseParse("flag kind not found"))?;
+                let (decl, value) = head
+                    .split_once(" = ")
+                    .ok_or(Error::ResponseParse("flag value not found"))?;
+                let (flag_type, name) = decl
+                    .trim()
+                    .split_once(' ')
+                    .ok_or(Error::ResponseParse("flag type not found"))?;
+
+                flags.push(VmFlag {
+                    name: name.trim().to_string(),
+                    value: value.trim().to_string(),
+                    flag_type: Some(flag_type.to_string()),
+                    kind: Some(kind.to_string()),
+                    origin: Some(origin.to_string()),
+                });
+            }
+        }
+
+        Ok(VmFlagValues { flags })
+    }
+}
+
+/// `VM.system_properties`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VmSystemProperties;
+
+/// Unescape a key or value of a Java properties file.
+fn unescape_property(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => res.push('\t'),
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some('f') => res.push('\x0c'),
+            Some('u') => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    res.push(c);
+                }
+            }
+            Some(c) => res.push(c),
+            None => {}
+        }
+    }
+
+    res
+}
+
+impl DiagnosticCommand for VmSystemProperties {
+    type Response = BTreeMap<String, String>;
+
+    fn name(&self) -> &str {
+        "VM.system_properties"
+    }
+
+    fn arguments(&self, _: &mut Arguments) -> Result<()> {
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        let mut res = BTreeMap::new();
+
+        for line in output.lines().map(|l| l.trim_start()) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+
+            // The key ends at the first unescaped `=` or `:`.
+            let mut escaped = false;
+            let split = line.char_indices().find(|(_, c)| {
+                let found = !escaped && (*c == '=' || *c == ':');
+                escaped = !escaped && *c == '\\';
+                found
+            });
+
+            let (key, value) = match split {
+                Some((i, _)) => (&line[..i], &line[i + 1..]),
+                None => (line, ""),
+            };
+
+            res.insert(unescape_property(key.trim_end()), unescape_property(value));
+        }
+
+        Ok(res)
+    }
+}
+
+/// `GC.heap_info`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GcHeapInfo;
+
+/// A line of [GcHeapInfo] output describing a heap area.
+///
+/// The areas vary by garbage collector. Sizes are in KB.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HeapArea {
+    /// e.g. `def new generation`, `eden space`, `Metaspace`.
+    pub name: String,
+    /// Nesting level. Areas are children of the preceding area with a lower depth.
+    pub depth: usize,
+    /// The size of the area. e.g. `total 28864K` or `eden space 25664K`.
+    pub capacity: Option<u64>,
+    pub used: Option<u64>,
+    /// Percentage of the area that is used, when reported instead of [Self::used].
+    pub used_percent: Option<u64>,
+    pub committed: Option<u64>,
+    pub reserved: Option<u64>,
+    /// The original line.
+    pub line: String,
+}
+
+/// Parse a `<N>K` size.
+fn parse_kb(token: &str) -> Option<u64> {
+    token.trim_end_matches(',').strip_suffix('K')?.parse().ok()
+}
+
+impl DiagnosticCommand for GcHeapInfo {
+    type Response = Vec<HeapArea>;
+
+    fn name(&self) -> &str {
+        "GC.heap_info"
+    }
+
+    fn arguments(&self, _: &mut Arguments) -> Result<()> {
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        let mut res = vec![];
+
+        for line in output.lines().filter(|l| !l.trim().is_empty()) {
+            let mut area = HeapArea {
+                depth: (line.len() - line.trim_start().len()).saturating_sub(1),
+                line: line.to_string(),
+                ..Default::default()
+            };
+
+            let mut name = vec![];
+            let mut tokens = line.split_whitespace().peekable();
+
+            while let Some(token) = tokens.next() {
+                // Address ranges end the interesting content.
+                if token.starts_with('[') {
+                    break;
+                }
+
+                let field = match token {
+                    "total" => &mut area.capacity,
+                    "used" => &mut area.used,
+                    "committed" => &mut area.committed,
+                    "reserved" => &mut area.reserved,
+                    _ => {
+                        if let Some(kb) = parse_kb(token) {
+                            area.capacity.get_or_insert(kb);
+                        } else if let Some(percent) = token.strip_suffix('%') {
+                            area.used_percent = percent.parse().ok();
+                            tokens.next_if_eq(&"used");
+                        } else if area.capacity.is_none() && area.used.is_none() {
+                            name.push(token);
+                        }
+                        continue;
+                    }
+                };
+
+                *field = tokens.next().and_then(parse_kb);
+            }
+
+            if name.is_empty() {
+                return Err(Error::ResponseParse("heap area has no name"));
+            }
+
+            area.name = name.join(" ");
+            res.push(area);
+        }
+
+        Ok(res)
+    }
+}
+
+/// `Thread.print`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadPrint {
+    /// Print information about `java.util.concurrent` locks (`-l`).
+    pub locks: bool,
+    /// Print extended thread information (`-e`).
+    pub extended: bool,
+}
+
+impl DiagnosticCommand for ThreadPrint {
+    type Response = ThreadDump;
+
+    fn name(&self) -> &str {
+        "Thread.print"
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        args.flag("l", self.locks);
+        args.flag("e", self.extended);
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        ThreadDump::parse(&output)
+    }
+}
+
+/// `GC.class_histogram`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GcClassHistogram {
+    /// Inspect all objects, including unreachable ones, instead of forcing a full GC first.
+    pub all: bool,
+    /// Number of parallel threads to use for heap inspection. 0 lets the JVM choose.
+    pub parallel: Option<u32>,
+}
+
+/// An entry in a [ClassHistogram].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClassHistogramEntry {
+    pub rank: u32,
+    pub instances: u64,
+    pub bytes: u64,
+    /// The class name. Arrays use the JVM's descriptor syntax. e.g. `[B`.
+    pub class_name: String,
+    /// The module and version defining the class. e.g. `java.base@17.0.8`.
+    pub module: Option<String>,
+}
+
+/// Response to [GcClassHistogram].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ClassHistogram {
+    /// Entries in descending order of size.
+    pub entries: Vec<ClassHistogramEntry>,
+    pub total_instances: u64,
+    pub total_bytes: u64,
+}
+
+impl DiagnosticCommand for GcClassHistogram {
+    type Response = ClassHistogram;
+
+    fn name(&self) -> &str {
+        "GC.class_histogram"
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        args.flag("all", self.all);
+        if let Some(parallel) = self.parallel {
+            args.parts.push(format!("-parallel={}", parallel));
+        }
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        let mut res = ClassHistogram::default();
+
+        for line in output.lines() {
+            let mut parts = line.split_whitespace();
+
+            match parts.next() {
+                Some("Total") => {
+                    res.total_instances = parts
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .ok_or(Error::ResponseParse("invalid histogram total"))?;
+                    res.total_bytes = parts
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .ok_or(Error::ResponseParse("invalid histogram total"))?;
+                }
+                Some(rank) if rank.ends_with(':') => {
+                    let rank = rank
+                        .trim_end_matches(':')
+                        .parse()
+                        .map_err(|_| Error::ResponseParse("invalid histogram rank"))?;
+                    let instances = parts
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .ok_or(Error::ResponseParse("invalid histogram instances"))?;
+                    let bytes = parts
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .ok_or(Error::ResponseParse("invalid histogram bytes"))?;
+                    let class_name = parts
+                        .next()
+                        .ok_or(Error::ResponseParse("missing histogram class name"))?;
+                    let module = parts
+                        .next()
+                        .and_then(|v| v.strip_prefix('('))
+                        .and_then(|v| v.strip_suffix(')'));
+
+                    res.entries.push(ClassHistogramEntry {
+                        rank,
+                        instances,
+                        bytes,
+                        class_name: class_name.to_string(),
+                        module: module.map(String::from),
+                    });
+                }
+                // Header lines.
+                _ => {}
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_line() -> Result<()> {
+        assert_eq!(VmFlags { all: true }.command_line()?, "VM.flags -all");
+        assert_eq!(quote("my recording")?, "\"my recording\"");
+        assert_eq!(quote("/tmp/a\"b.jfr")?, "'/tmp/a\"b.jfr'");
+        assert!(quote("a'\"").is_err());
+        assert!(quote("a\nb").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_responses() -> Result<()> {
+        let flags = VmFlags { all: true }.parse_response(
+            "[Global flags]\n     \
+             int ActiveProcessorCount                     = -1                                        {product} {default}\n   \
+             ccstr AbortVMOnException                       =                                           {diagnostic} {default}\n"
+                .into(),
+        )?;
+        let flag = flags.get("ActiveProcessorCount").unwrap();
+        assert_eq!(flag.value, "-1");
+        assert_eq!(flag.flag_type.as_deref(), Some("int"));
+        assert_eq!(flag.origin.as_deref(), Some("default"));
+        assert_eq!(flags.get("AbortVMOnException").unwrap().value, "");
+
+        let flags = VmFlags::default().parse_response(
+            "-XX:CICompilerCount=2 -XX:-THPStackMitigation -XX:+UseSerialGC \n".into(),
+        )?;
+        assert_eq!(flags.get("CICompilerCount").unwrap().value, "2");
+        assert_eq!(flags.get("UseSerialGC").unwrap().value, "true");
+
+        let areas = GcHeapInfo.parse_response(
+            " def new generation   total 28864K, used 8232K [0x00000000a2200000, 0x00000000a4150000, 0x00000000c16a0000)\n  \
+             eden space 25664K,  32% used [0x00000000a2200000, 0x00000000a2a0a228, 0x00000000a3b10000)\n \
+             Metaspace       used 131K, committed 320K, reserved 1114112K\n"
+                .into(),
+        )?;
+        assert_eq!(areas[0].name, "def new generation");
+        assert_eq!(
+            (areas[0].capacity, areas[0].used),
+            (Some(28864), Some(8232))
+        );
+        assert_eq!(areas[1].depth, 1);
+        assert_eq!(areas[1].used_percent, Some(32));
+        assert_eq!(areas[2].reserved, Some(1114112));
+
+        let histogram = GcClassHistogram::default().parse_response(
+            " num     #instances         #bytes  class name (module)\n\
+             -------------------------------------------------------\n   \
+             1:          2093         108800  [B (java.base@17.0.15)\n\
+             Total         10553         501704\n"
+                .into(),
+        )?;
+        assert_eq!(histogram.entries[0].class_name, "[B");
+        assert_eq!(
+            histogram.entries[0].module.as_deref(),
+            Some("java.base@17.0.15")
+        );
+        assert_eq!(histogram.total_bytes, 501704);
+
+        let props = VmSystemProperties.parse_response(
+            "#Sat Oct 17 01:15:38 UTC 2026\njava.home=/usr/lib/jvm\na\\:b=c\\u00e9\n".into(),
+        )?;
+        assert_eq!(props["java.home"], "/usr/lib/jvm");
+        assert_eq!(props["a:b"], "c\u{e9}");
+
+        Ok(())
+    }
+}
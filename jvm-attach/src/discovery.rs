@@ -0,0 +1,501 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Discovery of running JVMs.
+//!
+//! This is the equivalent of the JDK's `jps`. Processes are enumerated from
+//! procfs. A process is considered a HotSpot JVM if one of the following holds:
+//!
+//! 1. A `hsperfdata_<user>/<pid>` file exists in the `tmp` directory of the
+//!    process's root filesystem, `/proc/<pid>/root/tmp`. The PID is the PID
+//!    in the process's PID namespace. This finds JVMs running in containers.
+//! 2. A `hsperfdata_<user>/<pid>` file exists in our `/tmp`. This covers
+//!    processes whose root filesystem we can't inspect.
+//! 3. `libjvm.so` is mapped into the process. This finds JVMs running with
+//!    `-XX:-UsePerfData`.
+//!
+//! The main class and arguments are parsed from `/proc/<pid>/cmdline`.
+//!
+//! The cgroup of each process is read from `/proc/<pid>/cgroup`. Container
+//! runtimes name cgroups after container IDs, which allows finding the JVMs
+//! in a container with [find_jvms_in_container].
+//!
+//! [check_attachable] verifies that a process can be attached to before it is
+//! sent `SIGQUIT`, which terminates processes that don't handle it.
+
+use crate::{namespace_pid, perfdata::PerfData, Error, Result};
+use std::{
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+/// Options of the `java` launcher whose value is the following argument.
+const LAUNCHER_OPTIONS_WITH_VALUE: &[&str] = &[
+    "-cp",
+    "-classpath",
+    "--class-path",
+    "-p",
+    "--module-path",
+    "--upgrade-module-path",
+    "--add-modules",
+    "--limit-modules",
+    "--add-reads",
+    "--add-exports",
+    "--add-opens",
+    "--patch-module",
+    "--enable-native-access",
+    "--source",
+    "-d",
+    "--describe-module",
+];
+
+/// A running JVM.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct JvmProcess {
+    /// The PID of the process, as seen from our PID namespace.
+    pub pid: i32,
+
+    /// The PID of the process, as seen from its own PID namespace.
+    ///
+    /// Equivalent to [Self::pid] if the process is in our PID namespace.
+    pub ns_pid: i32,
+
+    /// The user ID owning the process.
+    pub uid: u32,
+
+    /// The main class, `-jar` path, or `-m` module of the application.
+    ///
+    /// [None] if it couldn't be determined, e.g. for JVMs embedded via JNI.
+    pub main: Option<String>,
+
+    /// Arguments passed to the JVM. e.g. `-Xmx1g`.
+    pub jvm_args: Vec<String>,
+
+    /// Arguments passed to the application's main method.
+    pub args: Vec<String>,
+
+    /// Path to the process's hsperfdata file, if found.
+    ///
+    /// The path is accessible from our mount namespace.
+    pub perf_data_path: Option<PathBuf>,
+
+    /// The cgroup of the process, relative to our cgroup namespace.
+    ///
+    /// The unified (v2) hierarchy is preferred over v1 hierarchies.
+    pub cgroup: Option<String>,
+
+    /// The ID of the innermost container the process is in, if any.
+    pub container_id: Option<String>,
+}
+
+impl JvmProcess {
+    /// Whether the process is in a container or cgroup.
+    ///
+    /// `id` can be a container ID or a prefix of one, and matches containers
+    /// at any nesting level. A cgroup path must match exactly.
+    pub fn in_container(&self, id: &str) -> bool {
+        let cgroup = if let Some(cgroup) = &self.cgroup {
+            cgroup
+        } else {
+            return false;
+        };
+
+        cgroup == id
+            || (!id.is_empty()
+                && container_ids(cgroup)
+                    .iter()
+                    .any(|container| container.starts_with(id)))
+    }
+}
+
+/// The main class and arguments parsed from a command line.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct JavaCommandLine {
+    /// See [JvmProcess::main].
+    pub main: Option<String>,
+    /// See [JvmProcess::jvm_args].
+    pub jvm_args: Vec<String>,
+    /// See [JvmProcess::args].
+    pub args: Vec<String>,
+}
+
+impl JavaCommandLine {
+    /// Parse the arguments of a `java` invocation, excluding the executable.
+    pub fn parse<S: AsRef<str>>(args: impl IntoIterator<Item = S>) -> Self {
+        let mut res = Self::default();
+        let mut args = args.into_iter().map(|s| s.as_ref().to_string());
+
+        while let Some(arg) = args.next() {
+            if arg == "-jar" || arg == "-m" || arg == "--module" {
+                res.main = args.next();
+                break;
+            } else if let Some(module) = arg.strip_prefix("--module=") {
+                res.main = Some(module.to_string());
+                break;
+            } else if LAUNCHER_OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+                res.jvm_args.push(arg);
+                res.jvm_args.extend(args.next());
+            } else if arg.starts_with('-') {
+                res.jvm_args.push(arg);
+            } else {
+                res.main = Some(arg);
+                break;
+            }
+        }
+
+        res.args = args.collect();
+
+        res
+    }
+}
+
+/// Read the NULL delimited arguments from a `/proc/<pid>/cmdline` file.
+fn read_cmdline(path: &Path) -> Result<Vec<String>> {
+    let data = std::fs::read(path)?;
+
+    Ok(data
+        .split(|c| *c == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).to_string())
+        .collect())
+}
+
+/// Resolve the cgroup path from a `/proc/<pid>/cgroup` file.
+fn parse_cgroup(data: &str) -> Option<String> {
+    // Lines are `<hierarchy ID>:<controllers>:<path>`. The unified hierarchy
+    // has ID 0 and no controllers.
+    let entries = data
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ':');
+            Some((parts.next()?, parts.next()?, parts.next()?))
+        })
+        .collect::<Vec<_>>();
+
+    entries
+        .iter()
+        .find(|(id, controllers, path)| *id == "0" && controllers.is_empty() && *path != "/")
+        .or_else(|| entries.iter().find(|(_, _, path)| *path != "/"))
+        .or_else(|| entries.first())
+        .map(|(_, _, path)| path.to_string())
+}
+
+/// Extract container IDs from a cgroup path, outermost first.
+///
+/// Container IDs are 64 hex digits. Runtimes name cgroups either after the
+/// bare ID (e.g. `/docker/<id>`) or with a prefix and suffix (e.g.
+/// `cri-containerd-<id>.scope`).
+fn container_ids(cgroup: &str) -> Vec<&str> {
+    cgroup
+        .split('/')
+        .filter_map(|component| {
+            let component = component.strip_suffix(".scope").unwrap_or(component);
+            let id = component.rsplit(['-', ':']).next()?;
+
+            if id.len() == 64 && id.bytes().all(|c| c.is_ascii_hexdigit()) {
+                Some(id)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Find a `hsperfdata_<user>/<pid>` file in a tmp directory.
+pub(crate) fn find_perf_data(tmp: &Path, pid: i32) -> Option<PathBuf> {
+    std::fs::read_dir(tmp)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .map_or(false, |name| name.starts_with("hsperfdata_"))
+        })
+        .map(|entry| entry.path().join(pid.to_string()))
+        .find(|path| path.is_file())
+}
+
+/// Whether a process has `libjvm.so` mapped into memory.
+///
+/// Returns an error if the process's memory maps can't be read.
+pub fn has_libjvm(procfs: &Path, pid: i32) -> Result<bool> {
+    let maps = std::fs::read_to_string(procfs.join(pid.to_string()).join("maps"))?;
+
+    Ok(maps.lines().any(|line| line.ends_with("/libjvm.so")))
+}
+
+/// Find the reason JVM arguments prevent attaching, if any.
+///
+/// Later arguments override earlier ones.
+fn attach_blocking_argument<S: AsRef<str>>(
+    args: impl IntoIterator<Item = S>,
+) -> Option<&'static str> {
+    let mut reduce_signals = None;
+    let mut disable_attach = None;
+
+    for arg in args {
+        match arg.as_ref() {
+            "-Xrs" => reduce_signals = Some("JVM ignores SIGQUIT (-Xrs)"),
+            "-XX:+ReduceSignalUsage" => {
+                reduce_signals = Some("JVM ignores SIGQUIT (-XX:+ReduceSignalUsage)")
+            }
+            "-XX:-ReduceSignalUsage" => reduce_signals = None,
+            "-XX:+DisableAttachMechanism" => {
+                disable_attach = Some("attach mechanism is disabled (-XX:+DisableAttachMechanism)")
+            }
+            "-XX:-DisableAttachMechanism" => disable_attach = None,
+            _ => {}
+        }
+    }
+
+    disable_attach.or(reduce_signals)
+}
+
+/// Verify that a process is a JVM that can be attached to.
+///
+/// This checks that the process is owned by us (unless we are root), that it
+/// has `libjvm.so` mapped, and that neither its command line nor its
+/// hsperfdata show attach being disabled or `SIGQUIT` being ignored. A
+/// missing hsperfdata file isn't an error, as JVMs running with
+/// `-XX:-UsePerfData` can still be attached to.
+///
+/// JVMs ignoring `SIGQUIT` start listening for attach requests at startup, so
+/// they are only attachable while their socket file exists.
+///
+/// Failures are reported as [Error::NotAttachable], or [Error::PermissionDenied]
+/// if we lack the privileges to attach.
+pub fn check_attachable(procfs: &Path, pid: i32) -> Result<()> {
+    let proc_dir = procfs.join(pid.to_string());
+
+    let uid = match std::fs::metadata(&proc_dir) {
+        Ok(metadata) => metadata.uid(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::NotAttachable("no such process"));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let euid = unsafe { libc::geteuid() };
+    if euid != 0 && euid != uid {
+        return Err(Error::PermissionDenied("process is owned by another user"));
+    }
+
+    match has_libjvm(procfs, pid) {
+        Ok(true) => {}
+        Ok(false) => return Err(Error::NotAttachable("process is not a JVM")),
+        Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::NotAttachable("no such process"));
+        }
+        Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            return Err(Error::PermissionDenied(
+                "process memory maps aren't readable",
+            ));
+        }
+        Err(e) => return Err(e),
+    }
+
+    let cmdline = read_cmdline(&proc_dir.join("cmdline"))?;
+    let command = JavaCommandLine::parse(cmdline.iter().skip(1));
+
+    if let Some(reason) = attach_blocking_argument(&command.jvm_args) {
+        return Err(Error::NotAttachable(reason));
+    }
+
+    let ns_pid = namespace_pid(pid, procfs)?.get_or_default(pid);
+
+    if let Some(perf_data) = find_perf_data(&proc_dir.join("root").join("tmp"), ns_pid)
+        .and_then(|path| PerfData::from_path(path).ok())
+    {
+        // The first character is whether the attach listener is supported.
+        if perf_data
+            .string("sun.rt.jvmCapabilities")
+            .map_or(false, |caps| caps.starts_with('0'))
+        {
+            return Err(Error::NotAttachable("attach mechanism is disabled"));
+        }
+
+        // Includes arguments from JAVA_TOOL_OPTIONS and argument files, which
+        // aren't on the command line.
+        if let Some(reason) = perf_data
+            .string("java.rt.vmArgs")
+            .and_then(|args| attach_blocking_argument(args.split_whitespace()))
+        {
+            return Err(Error::NotAttachable(reason));
+        }
+    }
+
+    Ok(())
+}
+
+/// Inspect a single process, returning a [JvmProcess] if it is a JVM.
+fn inspect_process(procfs: &Path, tmp: &Path, pid: i32) -> Result<Option<JvmProcess>> {
+    let proc_dir = procfs.join(pid.to_string());
+
+    let uid = std::fs::metadata(&proc_dir)?.uid();
+    let ns_pid = namespace_pid(pid, procfs)?.get_or_default(pid);
+
+    let perf_data_path = find_perf_data(&proc_dir.join("root").join("tmp"), ns_pid).or_else(|| {
+        if ns_pid == pid {
+            find_perf_data(tmp, pid)
+        } else {
+            None
+        }
+    });
+
+    if perf_data_path.is_none() && !has_libjvm(procfs, pid).unwrap_or(false) {
+        return Ok(None);
+    }
+
+    let cmdline = read_cmdline(&proc_dir.join("cmdline"))?;
+    let command = JavaCommandLine::parse(cmdline.iter().skip(1));
+
+    let cgroup = std::fs::read_to_string(proc_dir.join("cgroup"))
+        .ok()
+        .and_then(|data| parse_cgroup(&data));
+    let container_id = cgroup
+        .as_deref()
+        .and_then(|cgroup| container_ids(cgroup).last().map(|id| id.to_string()));
+
+    Ok(Some(JvmProcess {
+        pid,
+        ns_pid,
+        uid,
+        main: command.main,
+        jvm_args: command.jvm_args,
+        args: command.args,
+        perf_data_path,
+        cgroup,
+        container_id,
+    }))
+}
+
+/// Find running JVMs using the given procfs and tmp directories.
+///
+/// Processes that exit during the scan or that can't be inspected are
+/// ignored. Results are sorted by PID.
+pub fn find_jvms_in(procfs: &Path, tmp: &Path) -> Result<Vec<JvmProcess>> {
+    let mut res = vec![];
+
+    for entry in std::fs::read_dir(procfs)? {
+        let entry = entry?;
+
+        let pid = if let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<i32>().ok())
+        {
+            pid
+        } else {
+            continue;
+        };
+
+        if let Ok(Some(jvm)) = inspect_process(procfs, tmp, pid) {
+            res.push(jvm);
+        }
+    }
+
+    res.sort_by_key(|jvm| jvm.pid);
+
+    Ok(res)
+}
+
+/// Find running JVMs on this machine.
+pub fn find_jvms() -> Result<Vec<JvmProcess>> {
+    find_jvms_in(Path::new("/proc"), Path::new("/tmp"))
+}
+
+/// Find running JVMs in a container or cgroup using the given procfs and tmp directories.
+///
+/// See [JvmProcess::in_container] for how `id` is matched.
+pub fn find_jvms_in_container_in(procfs: &Path, tmp: &Path, id: &str) -> Result<Vec<JvmProcess>> {
+    Ok(find_jvms_in(procfs, tmp)?
+        .into_iter()
+        .filter(|jvm| jvm.in_container(id))
+        .collect())
+}
+
+/// Find running JVMs in a container or cgroup on this machine.
+pub fn find_jvms_in_container(id: &str) -> Result<Vec<JvmProcess>> {
+    find_jvms_in_container_in(Path::new("/proc"), Path::new("/tmp"), id)
+}
+
+/// Inspect a process by PID using the given procfs and tmp directories.
+pub fn find_jvm_in(procfs: &Path, tmp: &Path, pid: i32) -> Result<Option<JvmProcess>> {
+    inspect_process(procfs, tmp, pid)
+}
+
+/// Inspect a process by PID, returning a [JvmProcess] if it is a JVM.
+pub fn find_jvm(pid: i32) -> Result<Option<JvmProcess>> {
+    find_jvm_in(Path::new("/proc"), Path::new("/tmp"), pid)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blocking_arguments() {
+        assert_eq!(attach_blocking_argument(["-Xmx1g"]), None);
+        assert_eq!(
+            attach_blocking_argument(["-Xrs"]),
+            Some("JVM ignores SIGQUIT (-Xrs)")
+        );
+        assert_eq!(
+            attach_blocking_argument(["-XX:+ReduceSignalUsage", "-XX:-ReduceSignalUsage"]),
+            None
+        );
+        assert_eq!(
+            attach_blocking_argument(["-Xrs", "-XX:+DisableAttachMechanism"]),
+            Some("attach mechanism is disabled (-XX:+DisableAttachMechanism)")
+        );
+    }
+
+    #[test]
+    fn cgroups() {
+        let docker = "a".repeat(64);
+        let inner = "0123456789abcdef".repeat(4);
+
+        let data = format!("12:pids:/docker/{}\n1:name=systemd:/\n0::/\n", docker);
+        let cgroup = parse_cgroup(&data).unwrap();
+        assert_eq!(cgroup, format!("/docker/{}", docker));
+        assert_eq!(container_ids(&cgroup), vec![docker.as_str()]);
+
+        let data = format!(
+            "0::/docker/{}/kubepods/besteffort/pod1234/cri-containerd-{}.scope\n",
+            docker, inner
+        );
+        let cgroup = parse_cgroup(&data).unwrap();
+        assert_eq!(
+            container_ids(&cgroup),
+            vec![docker.as_str(), inner.as_str()]
+        );
+
+        assert_eq!(parse_cgroup("0::/\n").as_deref(), Some("/"));
+        assert!(container_ids("/user.slice/user-1000.slice").is_empty());
+    }
+
+    #[test]
+    fn parse_command_line() {
+        let command =
+            JavaCommandLine::parse(["-Xmx1g", "-cp", "a.jar:b.jar", "com.example.Main", "x"]);
+        assert_eq!(command.main.as_deref(), Some("com.example.Main"));
+        assert_eq!(command.jvm_args, vec!["-Xmx1g", "-cp", "a.jar:b.jar"]);
+        assert_eq!(command.args, vec!["x"]);
+
+        let command = JavaCommandLine::parse(["-Dfoo=bar", "-jar", "app.jar", "--port", "80"]);
+        assert_eq!(command.main.as_deref(), Some("app.jar"));
+        assert_eq!(command.jvm_args, vec!["-Dfoo=bar"]);
+        assert_eq!(command.args, vec!["--port", "80"]);
+
+        let command = JavaCommandLine::parse(["--module=app/com.example.Main"]);
+        assert_eq!(command.main.as_deref(), Some("app/com.example.Main"));
+
+        assert_eq!(JavaCommandLine::parse(["-version"]).main, None);
+    }
+}
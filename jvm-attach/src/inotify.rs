@@ -0,0 +1,158 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Waiting for directory entries to appear with inotify.
+
+use std::{
+    ffi::CString,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    },
+    path::Path,
+    time::Duration,
+};
+
+/// Watches a directory for new entries.
+///
+/// Entries are reported when created or renamed into the directory.
+#[derive(Debug)]
+pub(crate) struct DirectoryWatch {
+    fd: OwnedFd,
+}
+
+impl DirectoryWatch {
+    /// Start watching a directory.
+    pub fn new(dir: &Path) -> std::io::Result<Self> {
+        let path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        if unsafe {
+            libc::inotify_add_watch(
+                fd.as_raw_fd(),
+                path.as_ptr(),
+                libc::IN_CREATE | libc::IN_MOVED_TO,
+            )
+        } < 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Self { fd })
+    }
+
+    /// Wait for entries to appear, up to a timeout.
+    ///
+    /// Returns whether any did. Pending events are consumed, so callers should
+    /// check for the entries they are interested in afterwards.
+    pub fn wait(&self, timeout: Duration) -> std::io::Result<bool> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // Round up so short timeouts don't become busy loops.
+        let timeout_ms = ((timeout.as_nanos() + 999_999) / 1_000_000).min(libc::c_int::MAX as u128)
+            as libc::c_int;
+
+        match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
+            0 => Ok(false),
+            n if n < 0 => {
+                let e = std::io::Error::last_os_error();
+
+                if e.kind() == std::io::ErrorKind::Interrupted {
+                    Ok(false)
+                } else {
+                    Err(e)
+                }
+            }
+            _ => {
+                self.drain()?;
+
+                Ok(true)
+            }
+        }
+    }
+
+    /// Consume pending events.
+    pub fn drain(&self) -> std::io::Result<()> {
+        let mut buf = [0u8; 4096];
+
+        loop {
+            let count =
+                unsafe { libc::read(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+
+            if count == 0 {
+                return Ok(());
+            } else if count < 0 {
+                let e = std::io::Error::last_os_error();
+
+                return match e.kind() {
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => Ok(()),
+                    _ => Err(e),
+                };
+            }
+        }
+    }
+}
+
+impl AsRawFd for DirectoryWatch {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, std::time::Instant};
+
+    #[test]
+    fn wait() -> std::io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let watch = DirectoryWatch::new(dir.path())?;
+
+        // Nothing happened yet.
+        assert!(!watch.wait(Duration::from_millis(10))?);
+
+        let path = dir.path().join(".java_pid42");
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            std::fs::write(path, "")
+        });
+
+        let start = Instant::now();
+        assert!(watch.wait(Duration::from_secs(30))?);
+        assert!(start.elapsed() < Duration::from_secs(30));
+        writer.join().unwrap()?;
+
+        // Events were consumed.
+        assert!(!watch.wait(Duration::from_millis(10))?);
+
+        // Renames into the directory count as well.
+        let other = tempfile::tempdir()?;
+        std::fs::write(other.path().join("moved"), "")?;
+        std::fs::rename(other.path().join("moved"), dir.path().join("moved"))?;
+        assert!(watch.wait(Duration::from_secs(30))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_directory() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(DirectoryWatch::new(&dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,624 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Java Flight Recorder (JFR) control.
+//!
+//! This module provides [DiagnosticCommand]s for the `JFR.*` commands.
+//! Unlike most diagnostic commands, JFR commands report many failures as
+//! regular output. Their responses are validated and unexpected output is
+//! turned into [Error::CommandError].
+//!
+//! [record] starts a recording, waits, and stops it, returning the path to
+//! the written recording. Recordings are written by the JVM, so paths are
+//! relative to the JVM's mount namespace. [host_path] resolves them
+//! through `/proc/<pid>/root`, and [host_path_in] through another procfs.
+//! With the `jfr-reader` feature, [record_and_open] and [open_recording]
+//! additionally open the recording for analysis.
+
+use crate::{
+    command::{Arguments, DiagnosticCommand},
+    Error, Result,
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use crate::UnixSocketConnection;
+
+/// Format a duration as a time argument. e.g. `30s`.
+fn format_time(duration: Duration) -> String {
+    if duration.subsec_nanos() == 0 {
+        format!("{}s", duration.as_secs())
+    } else if duration.subsec_nanos() % 1_000_000 == 0 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{}ns", duration.as_nanos())
+    }
+}
+
+/// Find the path following a `written to:` line.
+fn written_path(output: &str) -> Option<PathBuf> {
+    let (_, path) = output.split_once("written to:")?;
+    let path = path.trim();
+
+    if path.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(path))
+    }
+}
+
+/// `JFR.start`.
+#[derive(Clone, Debug, Default)]
+pub struct JfrStart {
+    /// Name of the recording. The JVM uses the recording ID if not set.
+    pub name: Option<String>,
+    /// Settings files or their names. e.g. `default` or `profile`.
+    pub settings: Vec<String>,
+    /// Time to wait before starting.
+    pub delay: Option<Duration>,
+    /// Time to record. The recording is written to [Self::filename] when done.
+    pub duration: Option<Duration>,
+    /// Whether to write data to disk while recording.
+    pub disk: Option<bool>,
+    /// Path to write the recording to when it stops. Relative to the JVM's
+    /// working directory.
+    pub filename: Option<String>,
+    /// Maximum age of data to keep on disk.
+    pub maxage: Option<Duration>,
+    /// Maximum size in bytes of data to keep on disk.
+    pub maxsize: Option<u64>,
+    /// Whether to write the recording when the JVM exits.
+    pub dumponexit: Option<bool>,
+    /// Whether to record paths to GC roots at the end of the recording.
+    pub path_to_gc_roots: Option<bool>,
+}
+
+/// Response to [JfrStart].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StartedRecording {
+    /// The ID of the recording. Can be used as the name of the recording in
+    /// other commands.
+    pub id: u64,
+    /// Whether the start of the recording was delayed.
+    pub delayed: bool,
+    /// Where the recording will be written, if reported.
+    pub filename: Option<PathBuf>,
+    /// The full output. It may contain warnings about settings.
+    pub message: String,
+}
+
+impl DiagnosticCommand for JfrStart {
+    type Response = StartedRecording;
+
+    fn name(&self) -> &str {
+        "JFR.start"
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        args.option_opt("name", self.name.as_ref())?;
+        for settings in &self.settings {
+            args.option("settings", settings)?;
+        }
+        args.option_opt("delay", self.delay.map(format_time))?;
+        args.option_opt("duration", self.duration.map(format_time))?;
+        args.option_opt("disk", self.disk)?;
+        args.option_opt("filename", self.filename.as_ref())?;
+        args.option_opt("maxage", self.maxage.map(format_time))?;
+        args.option_opt("maxsize", self.maxsize)?;
+        args.option_opt("dumponexit", self.dumponexit)?;
+        args.option_opt("path-to-gc-roots", self.path_to_gc_roots)?;
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        let started = output.lines().find_map(|line| {
+            if let Some(rest) = line.strip_prefix("Started recording ") {
+                Some((rest.split(['.', ' ']).next()?.parse().ok()?, false))
+            } else if let Some(rest) = line.strip_prefix("Recording ") {
+                let (id, rest) = rest.split_once(' ')?;
+                rest.starts_with("scheduled to start")
+                    .then(|| Some((id.parse().ok()?, true)))
+                    .flatten()
+            } else {
+                None
+            }
+        });
+
+        let (id, delayed) = started.ok_or_else(|| Error::CommandError(output.clone()))?;
+
+        Ok(StartedRecording {
+            id,
+            delayed,
+            filename: written_path(&output),
+            message: output,
+        })
+    }
+}
+
+/// `JFR.stop`.
+///
+/// The response is the path the recording was written to, if it was written.
+#[derive(Clone, Debug, Default)]
+pub struct JfrStop {
+    /// Name or ID of the recording.
+    pub name: String,
+    /// Path to write the recording to. Relative to the JVM's working directory.
+    pub filename: Option<String>,
+}
+
+impl DiagnosticCommand for JfrStop {
+    type Response = Option<PathBuf>;
+
+    fn name(&self) -> &str {
+        "JFR.stop"
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        args.option("name", &self.name)?;
+        args.option_opt("filename", self.filename.as_ref())?;
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        if output.starts_with("Stopped recording") {
+            Ok(written_path(&output))
+        } else {
+            Err(Error::CommandError(output))
+        }
+    }
+}
+
+/// `JFR.dump`.
+///
+/// The response is the path the recording was written to.
+#[derive(Clone, Debug, Default)]
+pub struct JfrDump {
+    /// Name or ID of the recording. Data from all recordings is dumped if not set.
+    pub name: Option<String>,
+    /// Path to write the recording to. Relative to the JVM's working directory.
+    pub filename: Option<String>,
+    /// Maximum age of data to dump.
+    pub maxage: Option<Duration>,
+    /// Maximum size in bytes of data to dump.
+    pub maxsize: Option<u64>,
+    /// Only dump data after this time. ISO 8601, local time (e.g. `13:20:15`)
+    /// or relative to now (e.g. `-5m`).
+    pub begin: Option<String>,
+    /// Only dump data before this time. Same format as [Self::begin].
+    pub end: Option<String>,
+    /// Whether to record paths to GC roots.
+    pub path_to_gc_roots: Option<bool>,
+}
+
+impl DiagnosticCommand for JfrDump {
+    type Response = PathBuf;
+
+    fn name(&self) -> &str {
+        "JFR.dump"
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        args.option_opt("name", self.name.as_ref())?;
+        args.option_opt("filename", self.filename.as_ref())?;
+        args.option_opt("maxage", self.maxage.map(format_time))?;
+        args.option_opt("maxsize", self.maxsize)?;
+        args.option_opt("begin", self.begin.as_ref())?;
+        args.option_opt("end", self.end.as_ref())?;
+        args.option_opt("path-to-gc-roots", self.path_to_gc_roots)?;
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        match written_path(&output) {
+            Some(path) if output.starts_with("Dumped recording") => Ok(path),
+            _ => Err(Error::CommandError(output)),
+        }
+    }
+}
+
+/// `JFR.check`.
+#[derive(Clone, Debug, Default)]
+pub struct JfrCheck {
+    /// Name or ID of the recording. All recordings are described if not set.
+    pub name: Option<String>,
+    /// Whether to describe the event settings of recordings.
+    pub verbose: bool,
+}
+
+/// State of a recording.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RecordingState {
+    New,
+    Delayed,
+    Starting,
+    Running,
+    Stopped,
+    Closed,
+    Unknown(String),
+}
+
+impl From<&str> for RecordingState {
+    fn from(s: &str) -> Self {
+        match s {
+            "new" => Self::New,
+            "delayed" => Self::Delayed,
+            "starting" => Self::Starting,
+            "running" => Self::Running,
+            "stopped" => Self::Stopped,
+            "closed" => Self::Closed,
+            s => Self::Unknown(s.to_string()),
+        }
+    }
+}
+
+/// Settings of an event type in a recording.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EventSettings {
+    /// The event's label. e.g. `Flight Recording`.
+    pub label: String,
+    /// The event's name. e.g. `jdk.ActiveRecording`.
+    pub name: String,
+    pub settings: BTreeMap<String, String>,
+}
+
+/// A recording described by [JfrCheck].
+///
+/// Durations and sizes are formatted for humans. e.g. `1h` or `95.4MB`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordingDescriptor {
+    pub id: u64,
+    pub name: String,
+    pub duration: Option<String>,
+    pub maxsize: Option<String>,
+    pub maxage: Option<String>,
+    pub state: RecordingState,
+    /// Event settings. Only present with [JfrCheck::verbose].
+    pub events: Vec<EventSettings>,
+}
+
+/// Parse a `Recording <id>: name=<name> [key=value]... (<state>)` line.
+fn parse_recording_line(line: &str) -> Result<RecordingDescriptor> {
+    let (id, rest) = line
+        .strip_prefix("Recording ")
+        .and_then(|s| s.split_once(": name="))
+        .ok_or(Error::ResponseParse("invalid recording line"))?;
+    let id = id
+        .parse()
+        .map_err(|_| Error::ResponseParse("invalid recording ID"))?;
+
+    let (rest, state) = rest
+        .strip_suffix(')')
+        .and_then(|s| s.rsplit_once(" ("))
+        .ok_or(Error::ResponseParse("recording state not found"))?;
+
+    // Names may contain spaces. Fields are always printed in this order
+    // after the name, so peel them off the end.
+    let mut rest = rest;
+    let mut fields = [None, None, None];
+    for (i, key) in [" maxage=", " maxsize=", " duration="].iter().enumerate() {
+        if let Some((head, value)) = rest.rsplit_once(key) {
+            fields[i] = Some(value.to_string());
+            rest = head;
+        }
+    }
+    let [maxage, maxsize, duration] = fields;
+
+    Ok(RecordingDescriptor {
+        id,
+        name: rest.to_string(),
+        duration,
+        maxsize,
+        maxage,
+        state: RecordingState::from(state),
+        events: vec![],
+    })
+}
+
+impl DiagnosticCommand for JfrCheck {
+    type Response = Vec<RecordingDescriptor>;
+
+    fn name(&self) -> &str {
+        "JFR.check"
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        args.option_opt("name", self.name.as_ref())?;
+        if self.verbose {
+            args.option("verbose", true)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        if output.starts_with("No available recordings") {
+            return Ok(vec![]);
+        } else if !output.starts_with("Recording ") {
+            return Err(Error::CommandError(output));
+        }
+
+        let mut res: Vec<RecordingDescriptor> = vec![];
+
+        for line in output.lines() {
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() {
+                continue;
+            } else if line.starts_with("Recording ") {
+                res.push(parse_recording_line(line)?);
+            } else if let Some(settings) =
+                trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']'))
+            {
+                let event = res
+                    .last_mut()
+                    .and_then(|r| r.events.last_mut())
+                    .ok_or(Error::ResponseParse("event settings without event"))?;
+
+                event.settings = settings
+                    .split(',')
+                    .filter_map(|kv| kv.split_once('='))
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect();
+            } else {
+                // <label> (<name>)
+                let (label, name) = trimmed
+                    .strip_suffix(')')
+                    .and_then(|s| s.rsplit_once(" ("))
+                    .ok_or(Error::ResponseParse("invalid event line"))?;
+
+                res.last_mut()
+                    .ok_or(Error::ResponseParse("event without recording"))?
+                    .events
+                    .push(EventSettings {
+                        label: label.to_string(),
+                        name: name.to_string(),
+                        settings: BTreeMap::new(),
+                    });
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+/// `JFR.configure`.
+///
+/// Options that aren't set are left unchanged. The response is the
+/// reported configuration, keyed by label. e.g. `Stack depth`. Only
+/// changed values are reported unless no options are set.
+#[derive(Clone, Debug, Default)]
+pub struct JfrConfigure {
+    /// Directory where recordings are stored until written.
+    pub repository_path: Option<String>,
+    /// Directory where recordings are written when the JVM crashes.
+    pub dump_path: Option<String>,
+    /// Maximum number of frames in stack traces.
+    pub stack_depth: Option<u32>,
+    pub global_buffer_count: Option<u64>,
+    /// Size in bytes of global buffers.
+    pub global_buffer_size: Option<u64>,
+    /// Size in bytes of thread local buffers.
+    pub thread_buffer_size: Option<u64>,
+    /// Overall memory size in bytes.
+    pub memory_size: Option<u64>,
+    /// Maximum size in bytes of a chunk.
+    pub max_chunk_size: Option<u64>,
+    /// Whether to sample threads.
+    pub sample_threads: Option<bool>,
+}
+
+impl DiagnosticCommand for JfrConfigure {
+    type Response = BTreeMap<String, String>;
+
+    fn name(&self) -> &str {
+        "JFR.configure"
+    }
+
+    fn arguments(&self, args: &mut Arguments) -> Result<()> {
+        args.option_opt("repositorypath", self.repository_path.as_ref())?;
+        args.option_opt("dumppath", self.dump_path.as_ref())?;
+        args.option_opt("stackdepth", self.stack_depth)?;
+        args.option_opt("globalbuffercount", self.global_buffer_count)?;
+        args.option_opt("globalbuffersize", self.global_buffer_size)?;
+        args.option_opt("thread_buffer_size", self.thread_buffer_size)?;
+        args.option_opt("memorysize", self.memory_size)?;
+        args.option_opt("maxchunksize", self.max_chunk_size)?;
+        args.option_opt("samplethreads", self.sample_threads)?;
+
+        Ok(())
+    }
+
+    fn parse_response(&self, output: String) -> Result<Self::Response> {
+        Ok(output
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .collect())
+    }
+}
+
+/// Resolve a path in a JVM's mount namespace to a path accessible from ours.
+///
+/// Absolute paths are resolved through `/proc/<pid>/root`. Relative paths
+/// are relative to the JVM's working directory, `/proc/<pid>/cwd`.
+#[cfg(unix)]
+pub fn host_path(pid: i32, path: &Path) -> PathBuf {
+    host_path_in(Path::new("/proc"), pid, path)
+}
+
+/// Resolve a path in a JVM's mount namespace using the given procfs.
+///
+/// See [host_path].
+#[cfg(unix)]
+pub fn host_path_in(procfs: &Path, pid: i32, path: &Path) -> PathBuf {
+    let proc_dir = procfs.join(pid.to_string());
+
+    match path.strip_prefix("/") {
+        Ok(relative) => proc_dir.join("root").join(relative),
+        Err(_) => proc_dir.join("cwd").join(path),
+    }
+}
+
+/// Record for a period of time.
+///
+/// `start` describes the recording. Its `duration` is ignored. After
+/// `duration` elapses, the recording is stopped and written to the
+/// `filename` of `start`, or to a file in the JVM's `/tmp` if not set.
+///
+/// Returns the path to the written recording, resolved with [host_path_in]
+/// using the connection's procfs.
+#[cfg(unix)]
+pub fn record(
+    connection: &UnixSocketConnection,
+    mut start: JfrStart,
+    duration: Duration,
+) -> Result<PathBuf> {
+    start.duration = None;
+    let filename = start.filename.take();
+
+    let started = connection.execute(&start)?;
+
+    std::thread::sleep(duration);
+
+    let stop = JfrStop {
+        name: started.id.to_string(),
+        filename: Some(filename.unwrap_or_else(|| {
+            format!("/tmp/jvm-attach-{}-{}.jfr", std::process::id(), started.id)
+        })),
+    };
+
+    let path = connection
+        .execute(&stop)?
+        .ok_or_else(|| Error::CommandError("recording was not written".into()))?;
+
+    Ok(host_path_in(
+        &connection.options().procfs,
+        connection.pid(),
+        &path,
+    ))
+}
+
+/// Open a recording written by a JVM.
+///
+/// `path` is in the JVM's mount namespace, as reported by [JfrStop] or [JfrDump].
+#[cfg(all(unix, feature = "jfr-reader"))]
+pub fn open_recording(pid: i32, path: &Path) -> Result<jfr_reader::recording::Recording> {
+    open_recording_in(Path::new("/proc"), pid, path)
+}
+
+/// Open a recording written by a JVM using the given procfs.
+///
+/// See [open_recording].
+#[cfg(all(unix, feature = "jfr-reader"))]
+pub fn open_recording_in(
+    procfs: &Path,
+    pid: i32,
+    path: &Path,
+) -> Result<jfr_reader::recording::Recording> {
+    Ok(jfr_reader::recording::Recording::from_path(host_path_in(
+        procfs, pid, path,
+    ))?)
+}
+
+/// Record for a period of time and open the recording.
+///
+/// See [record].
+#[cfg(all(unix, feature = "jfr-reader"))]
+pub fn record_and_open(
+    connection: &UnixSocketConnection,
+    start: JfrStart,
+    duration: Duration,
+) -> Result<jfr_reader::recording::Recording> {
+    Ok(jfr_reader::recording::Recording::from_path(record(
+        connection, start, duration,
+    )?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_line() -> Result<()> {
+        let start = JfrStart {
+            name: Some("my recording".into()),
+            settings: vec!["default".into(), "/tmp/my settings.jfc".into()],
+            duration: Some(Duration::from_secs(30)),
+            maxage: Some(Duration::from_millis(1500)),
+            maxsize: Some(1000000),
+            ..Default::default()
+        };
+        assert_eq!(
+            start.command_line()?,
+            "JFR.start name=\"my recording\" settings=default settings=\"/tmp/my settings.jfc\" \
+             duration=30s maxage=1500ms maxsize=1000000"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_responses() -> Result<()> {
+        let started = JfrStart::default().parse_response(
+            "Started recording 2. The result will be written to:\n\n/tmp/ab.jfr\n".into(),
+        )?;
+        assert_eq!(started.id, 2);
+        assert_eq!(started.filename, Some(PathBuf::from("/tmp/ab.jfr")));
+
+        let started = JfrStart::default().parse_response(
+            "Recording 3 scheduled to start in 1 m. No limit specified, using maxsize=250MB as default.\n".into(),
+        )?;
+        assert_eq!((started.id, started.delayed), (3, true));
+
+        let recordings = JfrCheck::default().parse_response(
+            "Recording 2: name=my rec duration=90s maxsize=95.4MB maxage=1h (running)\n\n\
+             Recording 3: name=del maxsize=250.0MB (delayed)\n\n \
+             Flight Recording (jdk.ActiveRecording)\n   [enabled=true]\n \
+             Biased Lock Revocation (jdk.BiasedLockRevocation)\n   [threshold=0 ms,stackTrace=true,enabled=true]\n"
+                .into(),
+        )?;
+        assert_eq!(recordings[0].name, "my rec");
+        assert_eq!(recordings[0].duration.as_deref(), Some("90s"));
+        assert_eq!(recordings[0].maxage.as_deref(), Some("1h"));
+        assert_eq!(recordings[1].state, RecordingState::Delayed);
+        assert_eq!(recordings[1].events[1].name, "jdk.BiasedLockRevocation");
+        assert_eq!(recordings[1].events[1].settings["threshold"], "0 ms");
+
+        assert!(JfrDump::default()
+            .parse_response("Could not find zzz.\n".into())
+            .is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn host_paths() {
+        let procfs = Path::new("/host/proc");
+
+        assert_eq!(
+            host_path_in(procfs, 42, Path::new("/tmp/a.jfr")),
+            PathBuf::from("/host/proc/42/root/tmp/a.jfr")
+        );
+        assert_eq!(
+            host_path_in(procfs, 42, Path::new("a.jfr")),
+            PathBuf::from("/host/proc/42/cwd/a.jfr")
+        );
+        assert_eq!(
+            host_path(42, Path::new("/tmp/a.jfr")),
+            PathBuf::from("/proc/42/root/tmp/a.jfr")
+        );
+    }
+}
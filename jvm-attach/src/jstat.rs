@@ -0,0 +1,607 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! `jstat` equivalent statistics.
+//!
+//! [Sampler] periodically reads a JVM's [PerfData]. Each sample can be
+//! converted to one of the views offered by `jstat`:
+//!
+//! * [GcStats] (`-gc`)
+//! * [GcUtil] (`-gcutil`)
+//! * [ClassStats] (`-class`)
+//! * [CompilerStats] (`-compiler`)
+//!
+//! Values are derived from the same counters and expressions as `jstat`.
+//! Fields are [None] when `jstat` would print `-`: the counters aren't
+//! present or the expression is undefined (e.g. a percentage of a 0 sized
+//! space). Unlike `jstat`, sizes are in bytes, not KB.
+//!
+//! [Statistics::format_header] and [Statistics::format_row] render text
+//! with the same layout as `jstat`.
+
+use crate::{
+    perfdata::{perf_data_path, PerfData},
+    Error, Result,
+};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// A column of `jstat` output.
+#[derive(Clone, Copy, Debug)]
+pub struct Column {
+    pub header: &'static str,
+    /// Minimum width. Columns are widened to fit their header.
+    pub width: usize,
+    /// Whether values are left aligned instead of right aligned.
+    pub left: bool,
+}
+
+impl Column {
+    const fn new(header: &'static str, width: usize) -> Self {
+        Self {
+            header,
+            width,
+            left: false,
+        }
+    }
+
+    fn effective_width(&self) -> usize {
+        self.width.max(self.header.len())
+    }
+}
+
+/// A view of [PerfData] corresponding to a `jstat` option.
+pub trait Statistics: Sized {
+    /// The `jstat` option name. e.g. `gcutil`.
+    const OPTION: &'static str;
+
+    /// The columns of the view.
+    const COLUMNS: &'static [Column];
+
+    /// Derive statistics from counters.
+    fn from_perf_data(perf: &PerfData) -> Self;
+
+    /// Values of each column, formatted like `jstat`.
+    fn values(&self) -> Vec<String>;
+
+    /// The header line, like `jstat`.
+    fn format_header() -> String {
+        Self::COLUMNS
+            .iter()
+            .map(|c| {
+                // Headers are centered. The extra space goes on the right.
+                let pad = c.effective_width() - c.header.len();
+                let left = pad / 2;
+                format!("{}{}{}", " ".repeat(left), c.header, " ".repeat(pad - left))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// A line of values, like `jstat`.
+    fn format_row(&self) -> String {
+        Self::COLUMNS
+            .iter()
+            .zip(self.values())
+            .map(|(c, v)| {
+                let width = c.effective_width();
+                if c.left {
+                    format!("{:<width$}", v)
+                } else {
+                    format!("{:>width$}", v)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Format an optional value with a fixed number of decimals.
+fn format_decimal(v: Option<f64>, precision: usize) -> String {
+    match v {
+        Some(v) if v.is_finite() => format!("{:.precision$}", v),
+        _ => "-".to_string(),
+    }
+}
+
+/// Format an optional size in bytes as KB with 1 decimal.
+fn format_kb(v: Option<i64>) -> String {
+    format_decimal(v.map(|v| v as f64 / 1024.0), 1)
+}
+
+fn format_integer(v: Option<i64>) -> String {
+    v.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+/// Sum the present counters, or [None] if none are present.
+///
+/// Like `jstat`, missing terms of sums are ignored.
+fn sum(perf: &PerfData, names: &[&str]) -> Option<i64> {
+    names
+        .iter()
+        .filter_map(|name| perf.long(name))
+        .fold(None, |acc, v| Some(acc.unwrap_or(0) + v))
+}
+
+/// Sum of tick counters, in seconds.
+fn seconds(perf: &PerfData, names: &[&str]) -> Option<f64> {
+    perf.ticks_to_seconds(sum(perf, names)?)
+}
+
+/// Percentage of a space that is used.
+fn utilization(capacity: Option<i64>, used: Option<i64>) -> Option<f64> {
+    match (capacity?, used?) {
+        (0, _) => None,
+        (capacity, used) => Some(used as f64 / capacity as f64 * 100.0),
+    }
+}
+
+/// Garbage collection counts and times, shared by [GcStats] and [GcUtil].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GcCollections {
+    /// Young generation collections (`YGC`).
+    pub young_count: Option<i64>,
+    /// Young generation collection time in seconds (`YGCT`).
+    pub young_time: Option<f64>,
+    /// Full collections (`FGC`).
+    pub full_count: Option<i64>,
+    /// Full collection time in seconds (`FGCT`).
+    pub full_time: Option<f64>,
+    /// Stop the world phases of concurrent collections (`CGC`).
+    pub concurrent_count: Option<i64>,
+    /// Time of stop the world phases of concurrent collections in seconds (`CGCT`).
+    pub concurrent_time: Option<f64>,
+    /// Total collection time in seconds (`GCT`).
+    pub total_time: Option<f64>,
+}
+
+impl GcCollections {
+    const COLUMNS: [Column; 7] = [
+        Column::new("YGC", 6),
+        Column::new("YGCT", 9),
+        Column::new("FGC", 5),
+        Column::new("FGCT", 9),
+        Column::new("CGC", 5),
+        Column::new("CGCT", 9),
+        Column::new("GCT", 9),
+    ];
+
+    fn from_perf_data(perf: &PerfData) -> Self {
+        Self {
+            young_count: perf.long("sun.gc.collector.0.invocations"),
+            young_time: seconds(perf, &["sun.gc.collector.0.time"]),
+            full_count: perf.long("sun.gc.collector.1.invocations"),
+            full_time: seconds(perf, &["sun.gc.collector.1.time"]),
+            concurrent_count: perf.long("sun.gc.collector.2.invocations"),
+            concurrent_time: seconds(perf, &["sun.gc.collector.2.time"]),
+            total_time: seconds(
+                perf,
+                &[
+                    "sun.gc.collector.0.time",
+                    "sun.gc.collector.1.time",
+                    "sun.gc.collector.2.time",
+                ],
+            ),
+        }
+    }
+
+    fn values(&self) -> [String; 7] {
+        [
+            format_integer(self.young_count),
+            format_decimal(self.young_time, 3),
+            format_integer(self.full_count),
+            format_decimal(self.full_time, 3),
+            format_integer(self.concurrent_count),
+            format_decimal(self.concurrent_time, 3),
+            format_decimal(self.total_time, 3),
+        ]
+    }
+}
+
+/// Heap capacity and usage. Equivalent to `jstat -gc`.
+///
+/// Sizes are in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GcStats {
+    /// `S0C`
+    pub survivor0_capacity: Option<i64>,
+    /// `S1C`
+    pub survivor1_capacity: Option<i64>,
+    /// `S0U`
+    pub survivor0_used: Option<i64>,
+    /// `S1U`
+    pub survivor1_used: Option<i64>,
+    /// `EC`
+    pub eden_capacity: Option<i64>,
+    /// `EU`
+    pub eden_used: Option<i64>,
+    /// `OC`
+    pub old_capacity: Option<i64>,
+    /// `OU`
+    pub old_used: Option<i64>,
+    /// `MC`
+    pub metaspace_capacity: Option<i64>,
+    /// `MU`
+    pub metaspace_used: Option<i64>,
+    /// `CCSC`
+    pub compressed_class_capacity: Option<i64>,
+    /// `CCSU`
+    pub compressed_class_used: Option<i64>,
+    pub collections: GcCollections,
+}
+
+impl Statistics for GcStats {
+    const OPTION: &'static str = "gc";
+
+    const COLUMNS: &'static [Column] = &[
+        Column::new("S0C", 11),
+        Column::new("S1C", 11),
+        Column::new("S0U", 11),
+        Column::new("S1U", 11),
+        Column::new("EC", 12),
+        Column::new("EU", 12),
+        Column::new("OC", 12),
+        Column::new("OU", 12),
+        Column::new("MC", 10),
+        Column::new("MU", 10),
+        Column::new("CCSC", 9),
+        Column::new("CCSU", 9),
+        GcCollections::COLUMNS[0],
+        GcCollections::COLUMNS[1],
+        GcCollections::COLUMNS[2],
+        GcCollections::COLUMNS[3],
+        GcCollections::COLUMNS[4],
+        GcCollections::COLUMNS[5],
+        GcCollections::COLUMNS[6],
+    ];
+
+    fn from_perf_data(perf: &PerfData) -> Self {
+        Self {
+            survivor0_capacity: perf.long("sun.gc.generation.0.space.1.capacity"),
+            survivor1_capacity: perf.long("sun.gc.generation.0.space.2.capacity"),
+            survivor0_used: perf.long("sun.gc.generation.0.space.1.used"),
+            survivor1_used: perf.long("sun.gc.generation.0.space.2.used"),
+            eden_capacity: perf.long("sun.gc.generation.0.space.0.capacity"),
+            eden_used: perf.long("sun.gc.generation.0.space.0.used"),
+            old_capacity: perf.long("sun.gc.generation.1.space.0.capacity"),
+            old_used: perf.long("sun.gc.generation.1.space.0.used"),
+            metaspace_capacity: perf.long("sun.gc.metaspace.capacity"),
+            metaspace_used: perf.long("sun.gc.metaspace.used"),
+            compressed_class_capacity: perf.long("sun.gc.compressedclassspace.capacity"),
+            compressed_class_used: perf.long("sun.gc.compressedclassspace.used"),
+            collections: GcCollections::from_perf_data(perf),
+        }
+    }
+
+    fn values(&self) -> Vec<String> {
+        [
+            self.survivor0_capacity,
+            self.survivor1_capacity,
+            self.survivor0_used,
+            self.survivor1_used,
+            self.eden_capacity,
+            self.eden_used,
+            self.old_capacity,
+            self.old_used,
+            self.metaspace_capacity,
+            self.metaspace_used,
+            self.compressed_class_capacity,
+            self.compressed_class_used,
+        ]
+        .into_iter()
+        .map(format_kb)
+        .chain(self.collections.values())
+        .collect()
+    }
+}
+
+/// Heap utilization. Equivalent to `jstat -gcutil`.
+///
+/// Utilizations are percentages.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GcUtil {
+    /// `S0`
+    pub survivor0: Option<f64>,
+    /// `S1`
+    pub survivor1: Option<f64>,
+    /// `E`
+    pub eden: Option<f64>,
+    /// `O`
+    pub old: Option<f64>,
+    /// `M`
+    pub metaspace: Option<f64>,
+    /// `CCS`
+    pub compressed_class: Option<f64>,
+    pub collections: GcCollections,
+}
+
+impl From<&GcStats> for GcUtil {
+    fn from(stats: &GcStats) -> Self {
+        Self {
+            survivor0: utilization(stats.survivor0_capacity, stats.survivor0_used),
+            survivor1: utilization(stats.survivor1_capacity, stats.survivor1_used),
+            eden: utilization(stats.eden_capacity, stats.eden_used),
+            old: utilization(stats.old_capacity, stats.old_used),
+            metaspace: utilization(stats.metaspace_capacity, stats.metaspace_used),
+            compressed_class: utilization(
+                stats.compressed_class_capacity,
+                stats.compressed_class_used,
+            ),
+            collections: stats.collections,
+        }
+    }
+}
+
+impl Statistics for GcUtil {
+    const OPTION: &'static str = "gcutil";
+
+    const COLUMNS: &'static [Column] = &[
+        Column::new("S0", 6),
+        Column::new("S1", 6),
+        Column::new("E", 6),
+        Column::new("O", 6),
+        Column::new("M", 6),
+        Column::new("CCS", 6),
+        GcCollections::COLUMNS[0],
+        GcCollections::COLUMNS[1],
+        GcCollections::COLUMNS[2],
+        GcCollections::COLUMNS[3],
+        GcCollections::COLUMNS[4],
+        GcCollections::COLUMNS[5],
+        GcCollections::COLUMNS[6],
+    ];
+
+    fn from_perf_data(perf: &PerfData) -> Self {
+        Self::from(&GcStats::from_perf_data(perf))
+    }
+
+    fn values(&self) -> Vec<String> {
+        [
+            self.survivor0,
+            self.survivor1,
+            self.eden,
+            self.old,
+            self.metaspace,
+            self.compressed_class,
+        ]
+        .into_iter()
+        .map(|v| format_decimal(v, 2))
+        .chain(self.collections.values())
+        .collect()
+    }
+}
+
+/// Class loader statistics. Equivalent to `jstat -class`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClassStats {
+    /// Number of classes loaded (`Loaded`).
+    pub loaded: Option<i64>,
+    /// Size in bytes of classes loaded (`Bytes`).
+    pub loaded_bytes: Option<i64>,
+    /// Number of classes unloaded (`Unloaded`).
+    pub unloaded: Option<i64>,
+    /// Size in bytes of classes unloaded (`Bytes`).
+    pub unloaded_bytes: Option<i64>,
+    /// Time spent loading classes in seconds (`Time`).
+    pub time: Option<f64>,
+}
+
+impl Statistics for ClassStats {
+    const OPTION: &'static str = "class";
+
+    const COLUMNS: &'static [Column] = &[
+        Column::new("Loaded", 5),
+        Column::new("Bytes", 7),
+        Column::new("Unloaded", 5),
+        Column::new("Bytes", 7),
+        Column::new("Time", 10),
+    ];
+
+    fn from_perf_data(perf: &PerfData) -> Self {
+        Self {
+            loaded: sum(
+                perf,
+                &["java.cls.loadedClasses", "java.cls.sharedLoadedClasses"],
+            ),
+            loaded_bytes: sum(perf, &["sun.cls.loadedBytes", "sun.cls.sharedLoadedBytes"]),
+            unloaded: sum(
+                perf,
+                &["java.cls.unloadedClasses", "java.cls.sharedUnloadedClasses"],
+            ),
+            unloaded_bytes: sum(
+                perf,
+                &["sun.cls.unloadedBytes", "sun.cls.sharedUnloadedBytes"],
+            ),
+            time: seconds(perf, &["sun.cls.time"]),
+        }
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            format_integer(self.loaded),
+            format_kb(self.loaded_bytes),
+            format_integer(self.unloaded),
+            format_kb(self.unloaded_bytes),
+            format_decimal(self.time, 2),
+        ]
+    }
+}
+
+/// JIT compiler statistics. Equivalent to `jstat -compiler`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompilerStats {
+    /// Compilation tasks performed (`Compiled`).
+    pub compiled: Option<i64>,
+    /// Compilation tasks that failed (`Failed`).
+    pub failed: Option<i64>,
+    /// Compilation tasks that were invalidated (`Invalid`).
+    pub invalidated: Option<i64>,
+    /// Time spent compiling in seconds (`Time`).
+    pub time: Option<f64>,
+    /// Compile type of the last failed compilation (`FailedType`).
+    pub last_failed_type: Option<i64>,
+    /// Class and method of the last failed compilation (`FailedMethod`).
+    pub last_failed_method: Option<String>,
+}
+
+impl Statistics for CompilerStats {
+    const OPTION: &'static str = "compiler";
+
+    const COLUMNS: &'static [Column] = &[
+        Column::new("Compiled", 6),
+        Column::new("Failed", 6),
+        Column::new("Invalid", 6),
+        Column::new("Time", 8),
+        Column::new("FailedType", 4),
+        Column {
+            header: "FailedMethod",
+            width: 1,
+            left: true,
+        },
+    ];
+
+    fn from_perf_data(perf: &PerfData) -> Self {
+        Self {
+            compiled: perf.long("sun.ci.totalCompiles"),
+            failed: perf.long("sun.ci.totalBailouts"),
+            invalidated: perf.long("sun.ci.totalInvalidates"),
+            time: seconds(perf, &["java.ci.totalTime"]),
+            last_failed_type: perf.long("sun.ci.lastFailedType"),
+            last_failed_method: perf.string("sun.ci.lastFailedMethod").map(String::from),
+        }
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            format_integer(self.compiled),
+            format_integer(self.failed),
+            format_integer(self.invalidated),
+            format_decimal(self.time, 2),
+            format_integer(self.last_failed_type),
+            self.last_failed_method
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+        ]
+    }
+}
+
+/// Periodically reads [PerfData] of a JVM.
+///
+/// The first sample is taken immediately. Subsequent samples are taken
+/// at a fixed rate. The iterator ends after the configured number of
+/// samples or when the PerfData file can no longer be read after a
+/// successful sample, which normally means the JVM exited.
+#[derive(Clone, Debug)]
+pub struct Sampler {
+    path: PathBuf,
+    interval: Duration,
+    remaining: Option<usize>,
+    next: Option<Instant>,
+    sampled: bool,
+}
+
+impl Sampler {
+    /// Construct an instance sampling a JVM identified by a PID in our namespace.
+    pub fn new(pid: i32, interval: Duration) -> Result<Self> {
+        let path = perf_data_path(pid)?.ok_or(Error::NoPerfData)?;
+
+        Ok(Self::from_path(path, interval))
+    }
+
+    /// Construct an instance sampling a PerfData file.
+    pub fn from_path(path: impl AsRef<Path>, interval: Duration) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            interval,
+            remaining: None,
+            next: None,
+            sampled: false,
+        }
+    }
+
+    /// Set the number of samples to take. [None] is unlimited.
+    pub fn set_count(&mut self, count: Option<usize>) {
+        self.remaining = count;
+    }
+
+    /// The path of the PerfData file being sampled.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Iterator for Sampler {
+    type Item = Result<PerfData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+
+        let now = Instant::now();
+        let next = self.next.unwrap_or(now);
+        if next > now {
+            std::thread::sleep(next - now);
+        }
+        self.next = Some(next + self.interval);
+
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+
+        match PerfData::from_path(&self.path) {
+            Ok(perf) => {
+                self.sampled = true;
+                Some(Ok(perf))
+            }
+            Err(Error::Io(e)) if self.sampled && e.kind() == std::io::ErrorKind::NotFound => {
+                self.remaining = Some(0);
+                None
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        assert_eq!(
+            ClassStats::format_header(),
+            "Loaded  Bytes  Unloaded  Bytes     Time   "
+        );
+
+        let stats = ClassStats {
+            loaded: Some(426),
+            loaded_bytes: Some(1100186),
+            unloaded: Some(0),
+            unloaded_bytes: Some(0),
+            time: Some(0.0812),
+        };
+        assert_eq!(
+            stats.format_row(),
+            "   426  1074.4        0     0.0       0.08"
+        );
+
+        let util = GcUtil::from(&GcStats {
+            survivor0_capacity: Some(0),
+            survivor0_used: Some(0),
+            eden_capacity: Some(1000),
+            eden_used: Some(250),
+            ..Default::default()
+        });
+        assert_eq!(util.survivor0, None);
+        assert_eq!(util.eden, Some(25.0));
+        assert_eq!(&util.values()[0..3], &["-", "-", "25.00"]);
+    }
+}
@@ -7,17 +7,48 @@
 // except according to those terms.
 
 //! JVM attachment.
+//!
+//! See [discovery] for finding the JVMs to attach to. [perfdata] reads a
+//! JVM's performance counters without attaching to it. [jstat] derives
+//! `jstat` statistics from them. [command] provides typed diagnostic
+//! commands and [jfr] controls Java Flight Recorder. [threaddump] parses
+//! thread dumps. [agent] loads agents into running JVMs.
+//!
+//! With the `tokio` feature, the `tokio` module provides an async attach
+//! client.
 
+#[cfg(unix)]
+pub mod agent;
+pub mod command;
+#[cfg(unix)]
+pub mod discovery;
+#[cfg(target_os = "linux")]
+mod inotify;
+pub mod jfr;
+#[cfg(unix)]
+pub mod jstat;
+#[cfg(unix)]
+pub mod perfdata;
+pub mod threaddump;
+#[cfg(all(unix, feature = "tokio"))]
+pub mod tokio;
+
+#[cfg(unix)]
+use crate::command::DiagnosticCommand;
+use crate::command::ARGUMENT_LENGTH_MAX;
 use std::{
     io::{Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
+    sync::atomic::{AtomicU32, Ordering},
     time::Duration,
 };
 use thiserror::Error;
 
+#[cfg(target_os = "linux")]
+use crate::inotify::DirectoryWatch;
 #[cfg(unix)]
-use std::os::unix::net::UnixStream;
+use std::os::unix::{fs::MetadataExt, io::AsRawFd, net::UnixStream};
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -33,11 +64,72 @@ pub enum Error {
     #[error("no socket to connect to")]
     NoSocket,
 
+    #[error("JVM didn't respond to SIGQUIT within {0:?}")]
+    NoSignalResponse(Duration),
+
+    #[error("permission denied: {0}")]
+    PermissionDenied(&'static str),
+
+    #[error("JVM closed the connection without responding")]
+    ConnectionRejected,
+
+    #[error("JVM doesn't recognize command {0}")]
+    UnknownCommand(String),
+
+    #[error("JVM doesn't support attach protocol version {0}")]
+    UnsupportedProtocolVersion(u32),
+
+    #[error("parsing perfdata: {0}")]
+    PerfDataParse(&'static str),
+
+    #[error("no perfdata file found")]
+    NoPerfData,
+
     #[error("command error: {0}")]
     CommandError(String),
 
+    #[error("invalid command argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("parsing command response: {0}")]
+    ResponseParse(&'static str),
+
+    #[error("process is not attachable: {0}")]
+    NotAttachable(&'static str),
+
+    #[error("request exceeds the limits of attach protocol version {0}")]
+    ProtocolLimit(u32),
+
+    #[cfg(unix)]
+    #[error("agent error: {0}")]
+    Agent(#[from] agent::AgentError),
+
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[cfg(feature = "jfr-reader")]
+    #[error("JFR error: {0}")]
+    Jfr(#[from] jfr_reader::error::Error),
+}
+
+impl Error {
+    /// Whether the failed operation may succeed if retried.
+    ///
+    /// Retryable failures are transient, such as a JVM too busy to answer
+    /// `SIGQUIT` in time or a socket that went away and can be recreated by
+    /// attaching again. Other failures will recur until something changes.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::NoSocket | Self::NoSignalResponse(_) => true,
+            Self::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::WouldBlock
+            ),
+            _ => false,
+        }
+    }
 }
 
 /// This crate's result type.
@@ -59,45 +151,337 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 /// different mount namespace from the caller. And the PID namespace could
 /// be different. This means the client and JVM could disagree on both the
 /// PID and the well-known filesystem path.
+///
+/// The JVM only accepts clients with its effective uid and gid (or root, on
+/// recent JVMs). When running as root, the `.attach_pid<jvm_pid>` file is
+/// given to the JVM's user and connections are made with the JVM's
+/// credentials. See [AttachOptions].
+///
+/// Other clients may attach to the same JVM concurrently. The
+/// `.attach_pid<jvm_pid>` file is created exclusively and only removed by
+/// whoever created it. Sockets left behind by exited JVMs with the same PID
+/// are detected and replaced.
 #[cfg(unix)]
 #[derive(Debug)]
 pub struct UnixSocketRequest {
     pid: i32,
     ns_pid: i32,
-    attach_pid_path: Option<PathBuf>,
+    attach_pid_path: PathBuf,
+    /// Device and inode of the `.attach_pid` file, if we created it.
+    attach_pid_file: Option<(u64, u64)>,
     socket_path: PathBuf,
+    connector: SocketConnector,
+    /// Whether [discovery::check_attachable] passed.
+    checked: bool,
+}
+
+/// Options controlling how connections to a JVM are made.
+///
+/// Credential and namespace options only have an effect when running as root.
+/// They are only supported on Linux. Elsewhere, connecting fails when they
+/// apply.
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+pub struct AttachOptions {
+    /// Connect with the JVM's effective uid and gid.
+    ///
+    /// Required to attach to JVMs in other user namespaces, where root is
+    /// seen as an unprivileged user. Defaults to true.
+    pub switch_credentials: bool,
+
+    /// Enter the JVM's mount namespace to connect.
+    ///
+    /// The socket is otherwise reached through `/proc/<pid>/root`. Once in
+    /// the namespace, the socket is connected to in `/tmp`, where the JVM
+    /// creates it. [Self::tmp] is ignored for connecting.
+    pub enter_mount_namespace: bool,
+
+    /// Enter the JVM's network namespace to connect.
+    pub enter_net_namespace: bool,
+
+    /// The procfs to inspect processes with. Defaults to `/proc`.
+    pub procfs: PathBuf,
+
+    /// The directory holding the JVM's attach files, as seen from our mount
+    /// namespace.
+    ///
+    /// Defaults to the `tmp` directory of the process's root filesystem,
+    /// `<procfs>/<pid>/root/tmp`. The `.attach_pid` file is created and the
+    /// socket looked for there. The socket is connected to there as well
+    /// unless [Self::enter_mount_namespace] is set.
+    pub tmp: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+impl Default for AttachOptions {
+    fn default() -> Self {
+        Self {
+            switch_credentials: true,
+            enter_mount_namespace: false,
+            enter_net_namespace: false,
+            procfs: PathBuf::from("/proc"),
+            tmp: None,
+        }
+    }
+}
+
+/// Connects to a JVM's socket, switching credentials and namespaces as needed.
+#[cfg(unix)]
+#[derive(Clone, Debug)]
+struct SocketConnector {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pid: i32,
+    /// Path to the socket in our mount namespace.
+    socket_path: PathBuf,
+    /// Path to the socket in the JVM's mount namespace.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    namespace_socket_path: PathBuf,
+    /// Effective uid and gid to connect with.
+    credentials: Option<(u32, u32)>,
+    options: AttachOptions,
+}
+
+/// Classify a failure to connect to an attach socket.
+#[cfg(unix)]
+fn connect_error(e: std::io::Error) -> Error {
+    match e.kind() {
+        // A socket file without a listener is left behind by JVMs that exited.
+        std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused => Error::NoSocket,
+        std::io::ErrorKind::PermissionDenied => {
+            Error::PermissionDenied("can't connect to attach socket")
+        }
+        _ => Error::Io(e),
+    }
+}
+
+#[cfg(unix)]
+impl SocketConnector {
+    fn connect(&self) -> Result<UnixStream> {
+        if self.credentials.is_none()
+            && !self.options.enter_mount_namespace
+            && !self.options.enter_net_namespace
+        {
+            return UnixStream::connect(&self.socket_path).map_err(connect_error);
+        }
+
+        // Credentials and namespaces are per-thread at the system call level.
+        // Switch them on a separate thread to leave the caller's alone.
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| self.connect_in_jvm_context())
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn connect_in_jvm_context(&self) -> Result<UnixStream> {
+        let ns_dir = self.options.procfs.join(self.pid.to_string()).join("ns");
+
+        // Open both namespaces first, as entering the mount namespace changes
+        // what /proc refers to.
+        let net = if self.options.enter_net_namespace {
+            Some(std::fs::File::open(ns_dir.join("net"))?)
+        } else {
+            None
+        };
+        let mnt = if self.options.enter_mount_namespace {
+            Some(std::fs::File::open(ns_dir.join("mnt"))?)
+        } else {
+            None
+        };
+
+        if let Some(net) = net {
+            if unsafe { libc::setns(net.as_raw_fd(), libc::CLONE_NEWNET) } != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+
+        let socket_path = if let Some(mnt) = mnt {
+            // Threads share filesystem attributes with the rest of the process,
+            // which prevents entering a mount namespace.
+            if unsafe { libc::unshare(libc::CLONE_FS) } != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            if unsafe { libc::setns(mnt.as_raw_fd(), libc::CLONE_NEWNS) } != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            &self.namespace_socket_path
+        } else {
+            &self.socket_path
+        };
+
+        // The libc wrappers change the credentials of every thread in the
+        // process. The raw system calls only change this thread's. The gid must
+        // be changed first, while we are still privileged.
+        if let Some((uid, gid)) = self.credentials {
+            if unsafe { libc::syscall(libc::SYS_setresgid, -1, gid, -1) } != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            if unsafe { libc::syscall(libc::SYS_setresuid, -1, uid, -1) } != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+
+        UnixStream::connect(socket_path).map_err(connect_error)
+    }
+
+    /// Namespaces and per-thread credentials are specific to Linux.
+    #[cfg(not(target_os = "linux"))]
+    fn connect_in_jvm_context(&self) -> Result<UnixStream> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "switching credentials or namespaces is only supported on Linux",
+        )
+        .into())
+    }
 }
 
 #[cfg(unix)]
 impl UnixSocketRequest {
     /// Request attachment to a PID.
+    ///
+    /// Unless the JVM is already listening for attach requests, the process is
+    /// verified with [discovery::check_attachable] first. A process that isn't
+    /// attachable results in [Error::NotAttachable] rather than being sent
+    /// `SIGQUIT`.
     pub fn new(pid: i32) -> Result<Self, Error> {
-        let procfs = "/proc";
+        Self::with_options(pid, AttachOptions::default())
+    }
+
+    /// Request attachment to a PID with custom options.
+    pub fn with_options(pid: i32, options: AttachOptions) -> Result<Self, Error> {
+        let procfs = options.procfs.as_path();
 
         let ns_pid = namespace_pid(pid, procfs)?.get_or_default(pid);
 
-        let pid_fs_root = PathBuf::from(procfs).join(pid.to_string()).join("root");
-        let protocol_path = pid_fs_root.join("tmp");
+        let protocol_path = options
+            .tmp
+            .clone()
+            .unwrap_or_else(|| procfs.join(pid.to_string()).join("root").join("tmp"));
 
         let attach_pid_path = protocol_path.join(format!(".attach_pid{}", ns_pid));
         let socket_path = protocol_path.join(format!(".java_pid{}", ns_pid));
 
-        // TODO consider looking at file owner and removing if different.
-        let attach_pid_path = if !attach_pid_path.exists() {
-            // No special content. Just a placeholder file.
-            std::fs::File::create(&attach_pid_path)?;
+        // The socket may be stale, in which case we check before signalling.
+        let checked = if !socket_path.exists() {
+            discovery::check_attachable(procfs, pid)?;
+            true
+        } else {
+            false
+        };
 
-            Some(attach_pid_path)
+        let credentials = if options.switch_credentials && unsafe { libc::geteuid() } == 0 {
+            match process_credentials(pid, procfs)? {
+                (0, 0) => None,
+                credentials => Some(credentials),
+            }
         } else {
             None
         };
 
-        Ok(Self {
+        let connector = SocketConnector {
+            pid,
+            socket_path: socket_path.clone(),
+            // The JVM always uses /tmp, whatever it maps to in our namespace.
+            namespace_socket_path: Path::new("/tmp").join(format!(".java_pid{}", ns_pid)),
+            credentials,
+            options,
+        };
+
+        let mut request = Self {
             pid,
             ns_pid,
             attach_pid_path,
+            attach_pid_file: None,
             socket_path,
-        })
+            connector,
+            checked,
+        };
+
+        request.create_attach_pid_file()?;
+
+        Ok(request)
+    }
+
+    /// Whether the `.attach_pid` file is the one we created.
+    fn owns_attach_pid_file(&self) -> bool {
+        match (
+            self.attach_pid_file,
+            std::fs::metadata(&self.attach_pid_path),
+        ) {
+            (Some(ours), Ok(metadata)) => ours == (metadata.dev(), metadata.ino()),
+            _ => false,
+        }
+    }
+
+    /// Create the `.attach_pid` file unless it exists.
+    ///
+    /// An existing file was created by another client attaching concurrently.
+    /// It triggers the JVM just the same, so it is left alone. This holds even
+    /// if it is owned by another user: the client that created it removes it
+    /// when done, and we only ever remove files we created.
+    fn create_attach_pid_file(&mut self) -> Result<()> {
+        if self.owns_attach_pid_file() {
+            return Ok(());
+        }
+
+        // No special content. Just a placeholder file.
+        let file = match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.attach_pid_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                self.attach_pid_file = None;
+                return Ok(());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                return Err(Error::PermissionDenied("can't create attach trigger file"));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let metadata = file.metadata()?;
+        self.attach_pid_file = Some((metadata.dev(), metadata.ino()));
+
+        if let Some((uid, gid)) = self.connector.credentials {
+            if unsafe { libc::fchown(file.as_raw_fd(), uid, gid) } != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the `.attach_pid` file if we created it.
+    fn remove_attach_pid_file(&mut self) {
+        if self.owns_attach_pid_file() {
+            let _ = std::fs::remove_file(&self.attach_pid_path);
+        }
+
+        self.attach_pid_file = None;
+    }
+
+    /// Remove a socket nobody listens on, unless it has been replaced since.
+    fn remove_stale_socket(&self, stale: &std::fs::Metadata) {
+        if let Ok(metadata) = std::fs::metadata(&self.socket_path) {
+            if (metadata.dev(), metadata.ino()) == (stale.dev(), stale.ino()) {
+                let _ = std::fs::remove_file(&self.socket_path);
+            }
+        }
+    }
+
+    /// The PID of the JVM process, as seen from our PID namespace.
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// The PID of the JVM process, as seen from the JVM's PID namespace.
+    pub fn namespace_pid(&self) -> i32 {
+        self.ns_pid
     }
 
     /// Attempt to connect to the JVM command and control socket.
@@ -105,111 +489,432 @@ impl UnixSocketRequest {
     /// Consumes the instance. Returns a new type which can issue commands to the
     /// socket if the socket materializes.
     pub fn try_connect(mut self, max_wait_time: Duration) -> Result<UnixSocketConnection> {
-        let now = std::time::Instant::now();
-        let max_time = now + max_wait_time;
-        const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
-        let last_signal_time = now - SIGNAL_INTERVAL;
+        let start = std::time::Instant::now();
+        let max_time = start + max_wait_time;
+
+        // Watch before checking for the socket so its creation isn't missed.
+        #[cfg(target_os = "linux")]
+        let watch = self
+            .socket_path
+            .parent()
+            .and_then(|dir| DirectoryWatch::new(dir).ok());
+
+        let mut schedule = SignalSchedule::new(start);
 
         loop {
             let now = std::time::Instant::now();
+            // Why connecting to an existing socket failed.
+            let mut connect_error = None;
 
-            if self.socket_path.exists() {
-                // Check permissions first?
+            if let Ok(metadata) = std::fs::metadata(&self.socket_path) {
+                // Make sure we can connect, and to the right JVM. With PID reuse,
+                // the socket may belong to a JVM that exited or to one in
+                // another PID namespace sharing the same tmp directory.
+                match self.connector.connect() {
+                    Ok(sock) if peer_pid(&sock)?.map_or(true, |pid| pid == self.pid) => {
+                        self.remove_attach_pid_file();
 
-                // Make sure we can connect.
-                UnixStream::connect(&self.socket_path)?;
-
-                if let Some(p) = self.attach_pid_path.take() {
-                    let _ = std::fs::remove_file(p);
+                        return Ok(UnixSocketConnection {
+                            pid: self.pid,
+                            connector: self.connector.clone(),
+                            protocol_version: AtomicU32::new(0),
+                            attach_duration: start.elapsed(),
+                        });
+                    }
+                    // Our JVM replaces the socket when signalled.
+                    Ok(_) => {}
+                    Err(Error::NoSocket) => {
+                        self.remove_stale_socket(&metadata);
+                        connect_error = Some(Error::NoSocket);
+                    }
+                    Err(e) => return Err(e),
                 }
-
-                return Ok(UnixSocketConnection {
-                    socket_path: self.socket_path.clone(),
-                });
             }
 
             if now >= max_time {
-                return Err(Error::NoSocket);
+                return Err(connect_error.unwrap_or(Error::NoSignalResponse(max_wait_time)));
             }
 
-            // Send SIGQUIT if it has been long enough since the last one.
-            if now - last_signal_time >= SIGNAL_INTERVAL {
-                // TODO handle error?
-                unsafe {
-                    libc::kill(self.pid, libc::SIGQUIT);
-                }
+            if schedule.due(now) {
+                self.signal()?;
             }
 
-            // If the socket doesn't exist, sleep and try later.
-            std::thread::sleep(Duration::from_millis(10));
+            // Wait for the socket to appear until it is time to signal again.
+            let timeout = schedule
+                .next()
+                .min(max_time)
+                .saturating_duration_since(std::time::Instant::now());
+
+            #[cfg(target_os = "linux")]
+            if let Some(watch) = &watch {
+                watch.wait(timeout)?;
+                continue;
+            }
+
+            std::thread::sleep(timeout.min(SignalSchedule::POLL_INTERVAL));
+        }
+    }
+
+    /// Send `SIGQUIT` to the JVM to make it listen for attach requests.
+    fn signal(&mut self) -> Result<()> {
+        if !self.checked {
+            discovery::check_attachable(&self.connector.options.procfs, self.pid)?;
+            self.checked = true;
+        }
+
+        // Another client may have removed its file when giving up.
+        self.create_attach_pid_file()?;
+
+        if unsafe { libc::kill(self.pid, libc::SIGQUIT) } != 0 {
+            let e = std::io::Error::last_os_error();
+
+            return Err(match e.raw_os_error() {
+                Some(libc::ESRCH) => Error::NotAttachable("no such process"),
+                Some(libc::EPERM) => Error::PermissionDenied("can't signal process"),
+                _ => Error::Io(e),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// When to send `SIGQUIT` while waiting for a JVM to listen.
+///
+/// Slow JVMs may take a while to answer. Back off rather than flood them.
+#[cfg(unix)]
+struct SignalSchedule {
+    interval: Duration,
+    next: std::time::Instant,
+}
+
+#[cfg(unix)]
+impl SignalSchedule {
+    const INTERVAL_INITIAL: Duration = Duration::from_millis(100);
+    const INTERVAL_MAX: Duration = Duration::from_secs(1);
+
+    /// How often to check for the socket if the tmp directory can't be
+    /// watched, e.g. when the inotify watch limit is reached.
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    fn new(start: std::time::Instant) -> Self {
+        Self {
+            interval: Self::INTERVAL_INITIAL,
+            next: start,
         }
     }
+
+    /// Whether a signal is due, scheduling the next one if so.
+    fn due(&mut self, now: std::time::Instant) -> bool {
+        if now < self.next {
+            return false;
+        }
+
+        self.next = now + self.interval;
+        self.interval = (self.interval * 2).min(Self::INTERVAL_MAX);
+
+        true
+    }
+
+    /// When the next signal is due.
+    fn next(&self) -> std::time::Instant {
+        self.next
+    }
 }
 
 #[cfg(unix)]
 impl Drop for UnixSocketRequest {
     fn drop(&mut self) {
-        if let Some(path) = &self.attach_pid_path {
-            let _ = std::fs::remove_file(path);
+        self.remove_attach_pid_file();
+    }
+}
+
+/// The PID of the process that listens on the other end of a socket.
+///
+/// The PID is in our PID namespace, or 0 if the process isn't in it. [None]
+/// if the platform can't tell.
+#[cfg(target_os = "linux")]
+fn peer_pid(sock: &UnixStream) -> Result<Option<i32>> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    if unsafe {
+        libc::getsockopt(
+            sock.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    } != 0
+    {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(Some(cred.pid))
+}
+
+#[cfg(target_vendor = "apple")]
+fn peer_pid(sock: &UnixStream) -> Result<Option<i32>> {
+    let mut pid: libc::pid_t = 0;
+    let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+
+    if unsafe {
+        libc::getsockopt(
+            sock.as_raw_fd(),
+            libc::SOL_LOCAL,
+            libc::LOCAL_PEERPID,
+            (&mut pid as *mut libc::pid_t).cast(),
+            &mut len,
+        )
+    } != 0
+    {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(Some(pid))
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_vendor = "apple"))))]
+fn peer_pid(_sock: &UnixStream) -> Result<Option<i32>> {
+    Ok(None)
+}
+
+/// Attach protocol version supported by all JVMs.
+///
+/// Requests have exactly 3 arguments of less than [ARGUMENT_LENGTH_MAX] bytes.
+pub const PROTOCOL_VERSION_1: u32 = 1;
+
+/// Attach protocol version supporting any number of arguments of any length.
+///
+/// Supported since JDK 24.
+pub const PROTOCOL_VERSION_2: u32 = 2;
+
+/// Maximum number of arguments with [PROTOCOL_VERSION_1].
+#[cfg(unix)]
+const PROTOCOL_VERSION_1_ARGUMENTS_MAX: usize = 3;
+
+/// Whether a request can be sent with [PROTOCOL_VERSION_1].
+#[cfg(unix)]
+fn fits_protocol_version_1(args: &[&str]) -> bool {
+    args.len() <= PROTOCOL_VERSION_1_ARGUMENTS_MAX
+        && args.iter().all(|arg| arg.len() < ARGUMENT_LENGTH_MAX)
+}
+
+/// Encode a request in the given attach protocol version.
+///
+/// Version 1 requests are `<version> <command> <arg> <arg> <arg>`, with
+/// missing arguments sent as empty strings. Version 2 requests are
+/// `<version> <size> <command> <arg>...`, where size is the number of bytes
+/// following it. Each element is NULL terminated.
+#[cfg(unix)]
+fn encode_request(version: u32, command: &str, args: &[&str]) -> Result<Vec<u8>> {
+    if std::iter::once(&command)
+        .chain(args)
+        .any(|s| s.contains('\0'))
+    {
+        return Err(Error::InvalidArgument(
+            "attach requests can't contain NULL bytes".into(),
+        ));
+    }
+
+    let terminated = |s: &str| s.bytes().chain(std::iter::once(0)).collect::<Vec<_>>();
+
+    let mut payload = terminated(command);
+
+    if version == PROTOCOL_VERSION_1 {
+        if !fits_protocol_version_1(args) {
+            return Err(Error::ProtocolLimit(version));
+        }
+
+        for i in 0..PROTOCOL_VERSION_1_ARGUMENTS_MAX {
+            payload.extend(terminated(args.get(i).copied().unwrap_or("")));
+        }
+
+        Ok([terminated("1"), payload].concat())
+    } else {
+        for arg in args {
+            payload.extend(terminated(arg));
         }
+
+        Ok([
+            terminated("2"),
+            terminated(&payload.len().to_string()),
+            payload,
+        ]
+        .concat())
     }
 }
 
+/// Status of a failed attach operation. The reason is in the response.
+#[cfg(unix)]
+const ATTACH_STATUS_ERROR: i32 = -1;
+
+/// Status of a request in an unsupported protocol version.
+#[cfg(unix)]
+const ATTACH_STATUS_BAD_VERSION: i32 = 101;
+
 /// Response to a command invocation.
-pub enum CommandResponse {
+pub enum CommandResponse<S = UnixStream> {
     /// Command execution failed. The error string is captured.
+    ///
+    /// Failures with a dedicated [Error] variant are returned as such instead.
     Error(String),
     /// Command execution success. The raw socket is available to read the response.
-    Success(UnixStream),
+    Success(S),
+}
+
+/// Interpret the status line of a response. Empty when the JVM closed the connection.
+#[cfg(unix)]
+fn parse_status(line: &[u8]) -> Result<i32> {
+    if line.is_empty() {
+        return Err(Error::ConnectionRejected);
+    }
+
+    let s = std::str::from_utf8(line).map_err(|_| Error::IntegerReadBadString)?;
+
+    i32::from_str(s).map_err(|_| Error::IntegerReadNotInteger)
+}
+
+/// Interpret a response with a non-zero status and its message.
+#[cfg(unix)]
+fn failure_response<S>(
+    version: u32,
+    command: &str,
+    status: i32,
+    message: String,
+) -> Result<CommandResponse<S>> {
+    match status {
+        ATTACH_STATUS_BAD_VERSION => Err(Error::UnsupportedProtocolVersion(version)),
+        ATTACH_STATUS_ERROR
+            if message.trim() == format!("Operation {} not recognized!", command) =>
+        {
+            Err(Error::UnknownCommand(command.to_string()))
+        }
+        _ => Ok(CommandResponse::Error(message)),
+    }
+}
+
+/// Parse the output of a successful `getversion` command.
+///
+/// Newer JVMs follow the version with other text, e.g. `2 options: ...`.
+#[cfg(unix)]
+fn parse_protocol_version(output: &str) -> Result<u32> {
+    output
+        .split_whitespace()
+        .next()
+        .and_then(|version| u32::from_str(version).ok())
+        .filter(|version| *version >= PROTOCOL_VERSION_1)
+        .ok_or(Error::ResponseParse("invalid getversion response"))
 }
 
 #[cfg(unix)]
 #[derive(Debug)]
 pub struct UnixSocketConnection {
-    socket_path: PathBuf,
+    pid: i32,
+    connector: SocketConnector,
+    /// The negotiated protocol version. 0 if not yet negotiated.
+    protocol_version: AtomicU32,
+    attach_duration: Duration,
 }
 
 #[cfg(unix)]
 impl UnixSocketConnection {
+    /// The PID of the JVM process, as seen from our PID namespace.
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// The options the connection was made with.
+    pub fn options(&self) -> &AttachOptions {
+        &self.connector.options
+    }
+
+    /// How long attaching took.
+    ///
+    /// This is the time [UnixSocketRequest::try_connect] spent waiting for the
+    /// JVM to start listening and connecting to it.
+    pub fn attach_duration(&self) -> Duration {
+        self.attach_duration
+    }
+
+    /// The attach protocol version supported by the JVM.
+    ///
+    /// Negotiated with a `getversion` command on first use. JVMs that don't
+    /// recognize it only support [PROTOCOL_VERSION_1].
+    pub fn protocol_version(&self) -> Result<u32> {
+        let version = self.protocol_version.load(Ordering::Relaxed);
+        if version != 0 {
+            return Ok(version);
+        }
+
+        let version = match self.send_request(PROTOCOL_VERSION_1, "getversion", &[]) {
+            Ok(CommandResponse::Success(mut sock)) => {
+                let mut res = String::new();
+                sock.read_to_string(&mut res)?;
+
+                parse_protocol_version(&res)?
+            }
+            Ok(CommandResponse::Error(_)) | Err(Error::UnknownCommand(_)) => PROTOCOL_VERSION_1,
+            Err(e) => return Err(e),
+        };
+
+        self.protocol_version.store(version, Ordering::Relaxed);
+
+        Ok(version)
+    }
+
     /// Sends a command to the socket.
     ///
     /// Result reflects whether the socket I/O worked correctly.
     ///
     /// In the case of a successful command, the returned value holds a reference to the
     /// socket, which can be read from as appropriate.
-    pub fn send_command(&self, command: &str, mut args: Vec<&str>) -> Result<CommandResponse> {
-        // Command requests are <version> <command> <args>. Each is NULL terminated.
-        // Over the wire we send up to 3 arguments. If provided fewer, we send empty
-        // strings because that's the protocol.
-        if args.len() < 3 {
-            args.extend(std::iter::repeat("").take(3 - args.len()))
-        }
+    ///
+    /// Commands are sent with [PROTOCOL_VERSION_1] when they fit its limits. Otherwise
+    /// [PROTOCOL_VERSION_2] is used if the JVM supports it. [Error::ProtocolLimit] is
+    /// returned if it doesn't.
+    pub fn send_command(&self, command: &str, args: Vec<&str>) -> Result<CommandResponse> {
+        let version = if fits_protocol_version_1(&args) {
+            PROTOCOL_VERSION_1
+        } else {
+            match self.protocol_version()? {
+                PROTOCOL_VERSION_1 => return Err(Error::ProtocolLimit(PROTOCOL_VERSION_1)),
+                _ => PROTOCOL_VERSION_2,
+            }
+        };
 
-        let request = std::iter::once("1")
-            .chain([command].into_iter())
-            .chain(args.into_iter().take(3))
-            .flat_map(|s| {
-                let mut bytes = s.as_bytes().to_vec();
-                bytes.push(0);
+        self.send_request(version, command, &args)
+    }
 
-                bytes.into_iter()
-            })
-            .collect::<Vec<_>>();
+    /// Send a request and decode the status of the response.
+    fn send_request(&self, version: u32, command: &str, args: &[&str]) -> Result<CommandResponse> {
+        let request = encode_request(version, command, args)?;
 
-        let mut sock = UnixStream::connect(&self.socket_path)?;
+        let mut sock = self.connector.connect()?;
 
         sock.write_all(&request)?;
 
-        let status = Self::read_int(&mut sock)?;
+        // The JVM closes connections from clients it doesn't accept.
+        let status = match Self::read_int(&mut sock) {
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+                return Err(Error::ConnectionRejected);
+            }
+            res => res?,
+        };
 
         if status == 0 {
-            Ok(CommandResponse::Success(sock))
-        } else {
-            let mut res = String::new();
-            sock.read_to_string(&mut res)?;
-
-            Ok(CommandResponse::Error(res))
+            return Ok(CommandResponse::Success(sock));
         }
+
+        let mut res = String::new();
+        sock.read_to_string(&mut res)?;
+
+        failure_response(version, command, status, res)
     }
 
     /// Send a command and read its output as a Vec<u8>.
@@ -238,6 +943,14 @@ impl UnixSocketConnection {
         }
     }
 
+    /// Execute a typed diagnostic command and parse its output.
+    pub fn execute<C: DiagnosticCommand>(&self, command: &C) -> Result<C::Response> {
+        let line = command.command_line()?;
+        let output = self.send_command_string("jcmd", vec![&line])?;
+
+        command.parse_response(output)
+    }
+
     fn read_int(sock: &mut UnixStream) -> Result<i32> {
         let mut result = vec![];
         let mut buf = vec![0u8; 1];
@@ -255,16 +968,13 @@ impl UnixSocketConnection {
             result.push(buf[0]);
         }
 
-        let s = std::str::from_utf8(&result).map_err(|_| Error::IntegerReadBadString)?;
-        let v = i32::from_str(s).map_err(|_| Error::IntegerReadNotInteger)?;
-
-        Ok(v)
+        parse_status(&result)
     }
 }
 
 /// Represents the results of looking up a namespace pid.
-#[derive(Clone, Copy, Debug)]
-enum NamespacePid {
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NamespacePid {
     /// No /proc/pid/status file.
     NoStatus,
     /// Found a pid.
@@ -284,16 +994,47 @@ impl NamespacePid {
     }
 }
 
+/// Parse the effective ID from a `Uid:` or `Gid:` line of a `/proc/<pid>/status` file.
+#[cfg(unix)]
+fn parse_effective_id(status: &str, key: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// Resolve the effective uid and gid of a process.
+#[cfg(unix)]
+fn process_credentials(pid: i32, procfs: &Path) -> Result<(u32, u32)> {
+    let status = std::fs::read_to_string(procfs.join(pid.to_string()).join("status"))?;
+
+    let uid = parse_effective_id(&status, "Uid:")
+        .ok_or(Error::StatusParse("invalid syntax for Uid: entry"))?;
+    let gid = parse_effective_id(&status, "Gid:")
+        .ok_or(Error::StatusParse("invalid syntax for Gid: entry"))?;
+
+    Ok((uid, gid))
+}
+
 /// Attempt to resolve the PID of a process using that process's namespace PID view.
-fn namespace_pid(pid: i32, procfs: &str) -> Result<NamespacePid> {
-    let status_file = PathBuf::from(format!("{}/{}/status", procfs, pid));
+///
+/// With nested PID namespaces, this is the PID in the innermost namespace,
+/// which is the one the process sees itself as.
+pub fn namespace_pid(pid: i32, procfs: &Path) -> Result<NamespacePid> {
+    let status_file = procfs.join(pid.to_string()).join("status");
 
     if !status_file.exists() {
         return Ok(NamespacePid::NoStatus);
     }
 
-    let status = std::fs::read_to_string(&status_file)?;
+    parse_namespace_pid(&std::fs::read_to_string(&status_file)?)
+}
 
+/// Resolve the namespace PID from the content of a `/proc/<pid>/status` file.
+fn parse_namespace_pid(status: &str) -> Result<NamespacePid> {
     for line in status.lines() {
         if !line.starts_with("NSpid:") {
             continue;
@@ -303,7 +1044,12 @@ fn namespace_pid(pid: i32, procfs: &str) -> Result<NamespacePid> {
             .split(':')
             .nth(1)
             .ok_or(Error::StatusParse("invalid syntax for NSpid: entry"))?;
-        let value = value.trim();
+
+        // There is a PID for each nested namespace, outermost first.
+        let value = value
+            .split_whitespace()
+            .last()
+            .ok_or(Error::StatusParse("empty NSpid: entry"))?;
 
         let ns_pid =
             i32::from_str(value).map_err(|_| Error::StatusParse("NSpid value not an integer"))?;
@@ -313,3 +1059,135 @@ fn namespace_pid(pid: i32, procfs: &str) -> Result<NamespacePid> {
 
     Ok(NamespacePid::NoPid)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn requests() -> Result<()> {
+        assert_eq!(
+            encode_request(PROTOCOL_VERSION_1, "jcmd", &["VM.version"])?,
+            b"1\0jcmd\0VM.version\0\0\0"
+        );
+        assert_eq!(
+            encode_request(PROTOCOL_VERSION_2, "jcmd", &["a", "b", "c", "d"])?,
+            b"2\x0013\0jcmd\0a\0b\0c\0d\0"
+        );
+        assert!(matches!(
+            encode_request(PROTOCOL_VERSION_1, "jcmd", &["a", "b", "c", "d"]),
+            Err(Error::ProtocolLimit(1))
+        ));
+        assert!(matches!(
+            encode_request(
+                PROTOCOL_VERSION_1,
+                "jcmd",
+                &[&"x".repeat(ARGUMENT_LENGTH_MAX)]
+            ),
+            Err(Error::ProtocolLimit(1))
+        ));
+        assert!(encode_request(PROTOCOL_VERSION_2, "jcmd", &["a\0b"]).is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signal_schedule() {
+        let start = std::time::Instant::now();
+        let mut schedule = SignalSchedule::new(start);
+
+        let mut signals = vec![];
+        for ms in (0..4000).step_by(10) {
+            if schedule.due(start + Duration::from_millis(ms)) {
+                signals.push(ms);
+            }
+        }
+
+        assert_eq!(signals, vec![0, 100, 300, 700, 1500, 2500, 3500]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_socket() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket_path = dir.path().join(".java_pid42");
+
+        // A socket nobody listens on.
+        drop(std::os::unix::net::UnixListener::bind(&socket_path)?);
+
+        let request = UnixSocketRequest {
+            pid: 42,
+            ns_pid: 42,
+            attach_pid_path: dir.path().join(".attach_pid42"),
+            attach_pid_file: None,
+            socket_path: socket_path.clone(),
+            connector: SocketConnector {
+                pid: 42,
+                socket_path: socket_path.clone(),
+                namespace_socket_path: socket_path.clone(),
+                credentials: None,
+                options: AttachOptions::default(),
+            },
+            checked: true,
+        };
+
+        // Connecting failed rather than the JVM not answering.
+        assert!(matches!(
+            request.try_connect(Duration::ZERO),
+            Err(Error::NoSocket)
+        ));
+        assert!(!socket_path.exists());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn protocol_versions() {
+        assert_eq!(parse_protocol_version("2\n").unwrap(), 2);
+        assert_eq!(
+            parse_protocol_version("2 options: streaming=1\n").unwrap(),
+            2
+        );
+        assert!(parse_protocol_version("options: 2\n").is_err());
+        assert!(parse_protocol_version("").is_err());
+        assert!(parse_protocol_version("0").is_err());
+    }
+
+    #[test]
+    fn retryable() {
+        assert!(Error::NoSignalResponse(Duration::from_secs(1)).is_retryable());
+        assert!(Error::NoSocket.is_retryable());
+        assert!(!Error::UnknownCommand("bogus".into()).is_retryable());
+        assert!(!Error::PermissionDenied("process is owned by another user").is_retryable());
+        assert!(!Error::Io(std::io::ErrorKind::NotFound.into()).is_retryable());
+    }
+
+    #[test]
+    fn namespace_pids() -> Result<()> {
+        assert_eq!(
+            parse_namespace_pid("Name:\tjava\nNSpid:\t1234\n")?,
+            NamespacePid::Namespace(1234)
+        );
+        assert_eq!(
+            parse_namespace_pid("NSpid:\t1234\t56\t1\n")?,
+            NamespacePid::Namespace(1)
+        );
+        assert_eq!(parse_namespace_pid("Name:\tjava\n")?, NamespacePid::NoPid);
+        assert!(parse_namespace_pid("NSpid:\t\n").is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn effective_ids() {
+        let status = "Name:\tjava\nUid:\t1000\t1001\t1002\t1003\nGid:\t100\t101\t102\t103\n";
+
+        assert_eq!(parse_effective_id(status, "Uid:"), Some(1001));
+        assert_eq!(parse_effective_id(status, "Gid:"), Some(101));
+        assert_eq!(parse_effective_id(status, "Groups:"), None);
+    }
+}
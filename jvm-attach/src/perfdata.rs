@@ -0,0 +1,411 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! HotSpot performance counters (PerfData).
+//!
+//! Unless started with `-XX:-UsePerfData`, HotSpot JVMs expose hundreds of
+//! counters in a memory mapped file at `<tmp>/hsperfdata_<user>/<pid>`.
+//! This is the data source of `jstat`. Reading the file doesn't require
+//! attaching to or signaling the JVM.
+//!
+//! The file begins with a 32 byte prologue:
+//!
+//! | Offset | Type | Field |
+//! | ------ | ---- | ----- |
+//! | 0 | u32 | Magic (`0xcafec0c0`, always big-endian) |
+//! | 4 | u8 | Byte order (0 = big-endian, 1 = little-endian) |
+//! | 5 | u8 | Major version |
+//! | 6 | u8 | Minor version |
+//! | 7 | u8 | Accessible flag |
+//! | 8 | i32 | Bytes used |
+//! | 12 | i32 | Overflow |
+//! | 16 | i64 | Modification time stamp (ticks) |
+//! | 24 | i32 | Offset of first entry |
+//! | 28 | i32 | Number of entries |
+//!
+//! Each entry has a 20 byte header:
+//!
+//! | Offset | Type | Field |
+//! | ------ | ---- | ----- |
+//! | 0 | i32 | Entry length |
+//! | 4 | i32 | Name offset, relative to entry start |
+//! | 8 | i32 | Vector length (0 for scalars) |
+//! | 12 | u8 | Data type (`J` for i64, `B` for byte) |
+//! | 13 | u8 | Flags |
+//! | 14 | u8 | [Units] |
+//! | 15 | u8 | [Variability] |
+//! | 16 | i32 | Data offset, relative to entry start |
+//!
+//! Names are NULL terminated. Strings are NULL terminated byte vectors.
+
+use crate::{discovery::find_perf_data, namespace_pid, Error, Result};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Magic value at the start of PerfData files.
+const MAGIC: u32 = 0xcafec0c0;
+
+/// Size in bytes of the file prologue.
+const PROLOGUE_SIZE: usize = 32;
+
+/// Size in bytes of an entry header.
+const ENTRY_HEADER_SIZE: usize = 20;
+
+/// Units of a counter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Units {
+    None,
+    Bytes,
+    /// High resolution ticks. See [PerfData::frequency].
+    Ticks,
+    Events,
+    String,
+    Hertz,
+    Unknown(u8),
+}
+
+impl From<u8> for Units {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => Self::None,
+            2 => Self::Bytes,
+            3 => Self::Ticks,
+            4 => Self::Events,
+            5 => Self::String,
+            6 => Self::Hertz,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+/// How a counter's value changes over time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Variability {
+    /// The value never changes.
+    Constant,
+    /// The value only increases.
+    Monotonic,
+    /// The value can change arbitrarily.
+    Variable,
+    Unknown(u8),
+}
+
+impl From<u8> for Variability {
+    fn from(v: u8) -> Self {
+        match v {
+            1 => Self::Constant,
+            2 => Self::Monotonic,
+            3 => Self::Variable,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+/// The value of a counter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CounterValue {
+    Long(i64),
+    String(String),
+    /// A byte vector that isn't a string.
+    Bytes(Vec<u8>),
+    /// A vector of longs.
+    Longs(Vec<i64>),
+}
+
+/// A named performance counter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Counter {
+    pub name: String,
+    pub units: Units,
+    pub variability: Variability,
+    pub value: CounterValue,
+}
+
+/// Reads integers honoring the file's byte order.
+struct Reader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or(Error::PerfDataParse("data out of bounds"))
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    fn i32(&self, offset: usize) -> Result<i32> {
+        let b: [u8; 4] = self.bytes(offset, 4)?.try_into().unwrap();
+
+        Ok(if self.little_endian {
+            i32::from_le_bytes(b)
+        } else {
+            i32::from_be_bytes(b)
+        })
+    }
+
+    fn i64(&self, offset: usize) -> Result<i64> {
+        let b: [u8; 8] = self.bytes(offset, 8)?.try_into().unwrap();
+
+        Ok(if self.little_endian {
+            i64::from_le_bytes(b)
+        } else {
+            i64::from_be_bytes(b)
+        })
+    }
+
+    fn usize(&self, offset: usize) -> Result<usize> {
+        usize::try_from(self.i32(offset)?).map_err(|_| Error::PerfDataParse("negative offset"))
+    }
+}
+
+/// Parsed contents of a PerfData file.
+#[derive(Clone, Debug)]
+pub struct PerfData {
+    pub major_version: u8,
+    pub minor_version: u8,
+
+    /// Whether the JVM has finished initializing the file.
+    pub accessible: bool,
+
+    /// Time of the last structural modification, in ticks.
+    pub modification_time_stamp: i64,
+
+    counters: BTreeMap<String, Counter>,
+}
+
+impl PerfData {
+    /// Parse PerfData from bytes.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < PROLOGUE_SIZE {
+            return Err(Error::PerfDataParse("file too small"));
+        }
+
+        if u32::from_be_bytes(data[0..4].try_into().unwrap()) != MAGIC {
+            return Err(Error::PerfDataParse("bad magic"));
+        }
+
+        let r = Reader {
+            data,
+            little_endian: data[4] == 1,
+        };
+
+        let major_version = r.u8(5)?;
+        let minor_version = r.u8(6)?;
+
+        if major_version != 2 {
+            return Err(Error::PerfDataParse("unsupported version"));
+        }
+
+        let accessible = r.u8(7)? != 0;
+        let modification_time_stamp = r.i64(16)?;
+        let mut offset = r.usize(24)?;
+        let count = r.usize(28)?;
+
+        let mut counters = BTreeMap::new();
+
+        for _ in 0..count {
+            let entry_length = r.usize(offset)?;
+            let name_offset = r.usize(offset + 4)?;
+            let vector_length = r.usize(offset + 8)?;
+            let data_type = r.u8(offset + 12)?;
+            let units = Units::from(r.u8(offset + 14)?);
+            let variability = Variability::from(r.u8(offset + 15)?);
+            let data_offset = r.usize(offset + 16)?;
+
+            if entry_length < ENTRY_HEADER_SIZE {
+                return Err(Error::PerfDataParse("entry too small"));
+            }
+
+            let name = r.bytes(
+                offset + name_offset,
+                data_offset.saturating_sub(name_offset),
+            )?;
+            let name = name.split(|c| *c == 0).next().unwrap_or_default();
+            let name = String::from_utf8_lossy(name).to_string();
+
+            let data_start = offset + data_offset;
+
+            let value = match (data_type, vector_length) {
+                (b'J', 0) => CounterValue::Long(r.i64(data_start)?),
+                (b'J', len) => CounterValue::Longs(
+                    (0..len)
+                        .map(|i| r.i64(data_start + i * 8))
+                        .collect::<Result<Vec<_>>>()?,
+                ),
+                (b'B', len) => {
+                    let bytes = r.bytes(data_start, len)?;
+
+                    if units == Units::String {
+                        let s = bytes.split(|c| *c == 0).next().unwrap_or_default();
+                        CounterValue::String(String::from_utf8_lossy(s).to_string())
+                    } else {
+                        CounterValue::Bytes(bytes.to_vec())
+                    }
+                }
+                _ => return Err(Error::PerfDataParse("unsupported data type")),
+            };
+
+            counters.insert(
+                name.clone(),
+                Counter {
+                    name,
+                    units,
+                    variability,
+                    value,
+                },
+            );
+
+            offset += entry_length;
+        }
+
+        Ok(Self {
+            major_version,
+            minor_version,
+            accessible,
+            modification_time_stamp,
+            counters,
+        })
+    }
+
+    /// Read PerfData from a file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read(path.as_ref())?)
+    }
+
+    /// Read PerfData of a running JVM, as identified by a PID in our namespace.
+    pub fn from_pid(pid: i32) -> Result<Self> {
+        let path = perf_data_path(pid)?.ok_or(Error::NoPerfData)?;
+
+        Self::from_path(path)
+    }
+
+    /// Iterate over all counters, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Counter> {
+        self.counters.values()
+    }
+
+    /// Obtain a counter by name.
+    pub fn get(&self, name: &str) -> Option<&Counter> {
+        self.counters.get(name)
+    }
+
+    /// Obtain the value of an integer counter.
+    pub fn long(&self, name: &str) -> Option<i64> {
+        match self.get(name)?.value {
+            CounterValue::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Obtain the value of a string counter.
+    pub fn string(&self, name: &str) -> Option<&str> {
+        match &self.get(name)?.value {
+            CounterValue::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// The frequency of [Units::Ticks] counters, in ticks per second.
+    pub fn frequency(&self) -> Option<i64> {
+        self.long("sun.os.hrt.frequency").filter(|v| *v > 0)
+    }
+
+    /// Convert a ticks value to seconds.
+    pub fn ticks_to_seconds(&self, ticks: i64) -> Option<f64> {
+        Some(ticks as f64 / self.frequency()? as f64)
+    }
+}
+
+/// Locate the PerfData file of a process, as identified by a PID in our namespace.
+///
+/// The file is searched for in the `tmp` directory of the process's root
+/// filesystem, so JVMs in other mount and PID namespaces are found.
+pub fn perf_data_path(pid: i32) -> Result<Option<PathBuf>> {
+    perf_data_path_in(Path::new("/proc"), pid)
+}
+
+/// Locate the PerfData file of a process using the given procfs.
+pub fn perf_data_path_in(procfs: &Path, pid: i32) -> Result<Option<PathBuf>> {
+    let ns_pid = namespace_pid(pid, procfs)?.get_or_default(pid);
+
+    let tmp = procfs.join(pid.to_string()).join("root").join("tmp");
+
+    Ok(find_perf_data(&tmp, ns_pid))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(name: &str, data_type: u8, units: u8, data: &[u8], vector_length: i32) -> Vec<u8> {
+        let mut name = name.as_bytes().to_vec();
+        name.push(0);
+
+        let data_offset = ENTRY_HEADER_SIZE + name.len();
+        let len = (data_offset + data.len() + 7) & !7;
+
+        let mut res = vec![];
+        res.extend_from_slice(&(len as i32).to_le_bytes());
+        res.extend_from_slice(&(ENTRY_HEADER_SIZE as i32).to_le_bytes());
+        res.extend_from_slice(&vector_length.to_le_bytes());
+        res.extend_from_slice(&[data_type, 1, units, 1]);
+        res.extend_from_slice(&(data_offset as i32).to_le_bytes());
+        res.extend_from_slice(&name);
+        res.extend_from_slice(data);
+        res.resize(len, 0);
+
+        res
+    }
+
+    #[test]
+    fn parse() -> Result<()> {
+        let entries = [
+            entry(
+                "sun.os.hrt.frequency",
+                b'J',
+                6,
+                &1_000_000_000i64.to_le_bytes(),
+                0,
+            ),
+            entry("java.property.java.vm.name", b'B', 5, b"OpenJDK\0\0\0", 10),
+        ]
+        .concat();
+
+        let mut data = vec![];
+        data.extend_from_slice(&MAGIC.to_be_bytes());
+        data.extend_from_slice(&[1, 2, 0, 1]);
+        data.extend_from_slice(&((PROLOGUE_SIZE + entries.len()) as i32).to_le_bytes());
+        data.extend_from_slice(&0i32.to_le_bytes());
+        data.extend_from_slice(&42i64.to_le_bytes());
+        data.extend_from_slice(&(PROLOGUE_SIZE as i32).to_le_bytes());
+        data.extend_from_slice(&2i32.to_le_bytes());
+        data.extend_from_slice(&entries);
+
+        let perf = PerfData::parse(&data)?;
+        assert!(perf.accessible);
+        assert_eq!(perf.modification_time_stamp, 42);
+        assert_eq!(perf.frequency(), Some(1_000_000_000));
+        assert_eq!(perf.string("java.property.java.vm.name"), Some("OpenJDK"));
+        assert_eq!(
+            perf.get("sun.os.hrt.frequency").map(|c| c.units),
+            Some(Units::Hertz)
+        );
+
+        assert!(PerfData::parse(&data[0..PROLOGUE_SIZE + 10]).is_err());
+
+        Ok(())
+    }
+}
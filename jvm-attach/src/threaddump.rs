@@ -0,0 +1,579 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Thread dump parsing.
+//!
+//! [ThreadDump::parse] parses the output of `Thread.print` (also produced
+//! by the `threaddump` attach command and `jstack`).
+//!
+//! Each thread begins with a header line, followed by its state, stack
+//! frames, and monitors:
+//!
+//! ```text
+//! "mon-1" #12 prio=5 os_prio=0 cpu=0.32ms elapsed=3.77s tid=0x00007f2f740ad3b0 nid=0x75e6 waiting for monitor entry  [0x00007f2effdfd000]
+//!    java.lang.Thread.State: BLOCKED (on object monitor)
+//!     at Dead.lambda$main$0(Dead.java:7)
+//!     - waiting to lock <0x00000000a2390e20> (a java.lang.Object)
+//!     - locked <0x00000000a2390e10> (a java.lang.Object)
+//!
+//!    Locked ownable synchronizers:
+//!     - None
+//! ```
+//!
+//! Ownable synchronizers (`java.util.concurrent` locks) are only printed
+//! with `Thread.print -l`. Without them, deadlocks involving these locks
+//! can't be detected.
+
+use crate::{Error, Result};
+use std::collections::HashMap;
+
+/// The state of a Java thread. Mirrors `java.lang.Thread.State`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ThreadState {
+    New,
+    Runnable,
+    Blocked,
+    Waiting,
+    TimedWaiting,
+    Terminated,
+    Unknown(String),
+}
+
+impl From<&str> for ThreadState {
+    fn from(s: &str) -> Self {
+        match s {
+            "NEW" => Self::New,
+            "RUNNABLE" => Self::Runnable,
+            "BLOCKED" => Self::Blocked,
+            "WAITING" => Self::Waiting,
+            "TIMED_WAITING" => Self::TimedWaiting,
+            "TERMINATED" => Self::Terminated,
+            s => Self::Unknown(s.to_string()),
+        }
+    }
+}
+
+/// An object used as a lock.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct LockRef {
+    /// Address of the object.
+    pub address: u64,
+    /// Class of the object. e.g. `java.lang.Object`.
+    pub class_name: String,
+}
+
+/// How a frame interacts with a lock.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LockAction {
+    /// The monitor is held (`locked`).
+    Locked,
+    /// Blocked entering a monitor (`waiting to lock`).
+    WaitingToLock,
+    /// Blocked re-entering a monitor after `Object.wait()` (`waiting to re-lock in wait()`).
+    WaitingToRelock,
+    /// In `Object.wait()` on the monitor (`waiting on`). The monitor is released.
+    WaitingOn,
+    /// Parked waiting for a `java.util.concurrent` lock (`parking to wait for`).
+    ParkingToWaitFor,
+    /// A lock elided by the JIT compiler (`eliminated`).
+    Eliminated,
+}
+
+/// A lock annotation of a stack frame.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrameLock {
+    pub action: LockAction,
+    pub lock: LockRef,
+}
+
+/// A frame of a thread's stack.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StackFrame {
+    /// Class and method name. e.g. `java.lang.Thread.sleep`.
+    pub method: String,
+    /// The module and version. e.g. `java.base@17.0.8`.
+    pub module: Option<String>,
+    /// Source location. e.g. `Thread.java:840`, `Native Method` or `Unknown Source`.
+    pub location: String,
+    /// Locks acquired or waited on in this frame.
+    pub locks: Vec<FrameLock>,
+}
+
+/// A thread in a [ThreadDump].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct JavaThread {
+    pub name: String,
+    /// The Java thread ID (`#N`). Absent for internal VM threads.
+    pub id: Option<u64>,
+    pub daemon: bool,
+    /// The Java priority. Absent for internal VM threads.
+    pub priority: Option<i32>,
+    pub os_priority: Option<i32>,
+    /// Address of the thread's native structure (`tid`).
+    pub tid: Option<u64>,
+    /// The OS thread ID (`nid`).
+    pub nid: Option<u64>,
+    /// Consumed CPU time. e.g. `0.32ms`.
+    pub cpu: Option<String>,
+    /// Time since the thread started. e.g. `3.77s`.
+    pub elapsed: Option<String>,
+    /// What the thread is doing according to the VM. e.g. `waiting for monitor entry`.
+    pub description: String,
+    /// The Java thread state. Absent for internal VM threads.
+    pub state: Option<ThreadState>,
+    /// Detail of the state. e.g. `on object monitor` or `sleeping`.
+    pub state_detail: Option<String>,
+    /// Stack frames, innermost first.
+    pub frames: Vec<StackFrame>,
+    /// Held `java.util.concurrent` locks. Only present with `Thread.print -l`.
+    pub ownable_synchronizers: Vec<LockRef>,
+}
+
+impl JavaThread {
+    /// Monitors held by the thread.
+    ///
+    /// Monitors released by `Object.wait()` are excluded.
+    pub fn held_monitors(&self) -> impl Iterator<Item = &LockRef> {
+        let waiting_on = self.frame_locks(LockAction::WaitingOn).collect::<Vec<_>>();
+
+        self.frame_locks(LockAction::Locked)
+            .filter(move |lock| !waiting_on.contains(lock))
+    }
+
+    /// Monitors and ownable synchronizers held by the thread.
+    pub fn held_locks(&self) -> impl Iterator<Item = &LockRef> {
+        self.held_monitors()
+            .chain(self.ownable_synchronizers.iter())
+    }
+
+    /// The lock the thread is blocked acquiring, if any.
+    ///
+    /// Threads in `Object.wait()` aren't blocked on a lock: they are waiting
+    /// for a notification.
+    pub fn blocked_on(&self) -> Option<&FrameLock> {
+        self.frames.first()?.locks.iter().find(|l| {
+            matches!(
+                l.action,
+                LockAction::WaitingToLock
+                    | LockAction::WaitingToRelock
+                    | LockAction::ParkingToWaitFor
+            )
+        })
+    }
+
+    fn frame_locks(&self, action: LockAction) -> impl Iterator<Item = &LockRef> {
+        self.frames
+            .iter()
+            .flat_map(|f| f.locks.iter())
+            .filter(move |l| l.action == action)
+            .map(|l| &l.lock)
+    }
+}
+
+/// Threads sharing a state and stack. See [ThreadDump::group_by_stack].
+#[derive(Clone, Debug)]
+pub struct StackGroup<'a> {
+    pub state: Option<&'a ThreadState>,
+    /// The shared frames. Lock annotations are those of the first thread.
+    pub frames: &'a [StackFrame],
+    pub threads: Vec<&'a JavaThread>,
+}
+
+/// A parsed thread dump.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ThreadDump {
+    /// When the dump was taken, as printed by the JVM. e.g. `2023-08-30 12:00:00`.
+    pub timestamp: Option<String>,
+    /// The VM description. e.g. `OpenJDK 64-Bit Server VM (17.0.8+7 mixed mode, sharing)`.
+    pub vm: Option<String>,
+    pub threads: Vec<JavaThread>,
+}
+
+/// Parse a `0x` prefixed hex or a decimal integer.
+fn parse_id(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Parse a thread header line.
+fn parse_thread_header(line: &str) -> Result<JavaThread> {
+    // The name can contain anything, including quotes. Nothing after it can.
+    let end = line
+        .rfind('"')
+        .filter(|end| *end > 0)
+        .ok_or(Error::ResponseParse("unterminated thread name"))?;
+
+    let mut thread = JavaThread {
+        name: line[1..end].to_string(),
+        ..Default::default()
+    };
+
+    let mut description = vec![];
+
+    for token in line[end + 1..].split_whitespace() {
+        if token.starts_with("[0x") {
+            // Last known Java stack pointer.
+            break;
+        } else if token.starts_with('[') {
+            // OS thread ID printed by JDK 19+. The same as `nid`.
+            continue;
+        } else if let Some(id) = token.strip_prefix('#') {
+            thread.id = id.parse().ok();
+        } else if token == "daemon" {
+            thread.daemon = true;
+        } else if let Some((key, value)) = token.split_once('=') {
+            match key {
+                "prio" => thread.priority = value.parse().ok(),
+                "os_prio" => thread.os_priority = value.parse().ok(),
+                "cpu" => thread.cpu = Some(value.to_string()),
+                "elapsed" => thread.elapsed = Some(value.to_string()),
+                "tid" => thread.tid = parse_id(value),
+                "nid" => thread.nid = parse_id(value),
+                _ => {}
+            }
+        } else {
+            description.push(token);
+        }
+    }
+
+    thread.description = description.join(" ");
+
+    Ok(thread)
+}
+
+/// Parse a `<0x...> (a class)` lock reference.
+fn parse_lock_ref(s: &str) -> Option<LockRef> {
+    let (address, rest) = s.trim().strip_prefix('<')?.split_once('>')?;
+    let class_name = rest.trim().strip_prefix("(a ")?.strip_suffix(')')?;
+
+    Some(LockRef {
+        address: parse_id(address)?,
+        class_name: class_name.to_string(),
+    })
+}
+
+/// Parse a `- <action> <0x...> (a class)` frame lock line.
+fn parse_frame_lock(line: &str) -> Option<FrameLock> {
+    const ACTIONS: &[(&str, LockAction)] = &[
+        ("locked ", LockAction::Locked),
+        ("waiting to lock ", LockAction::WaitingToLock),
+        ("waiting to re-lock in wait() ", LockAction::WaitingToRelock),
+        ("waiting on ", LockAction::WaitingOn),
+        ("parking to wait for ", LockAction::ParkingToWaitFor),
+        ("eliminated ", LockAction::Eliminated),
+    ];
+
+    ACTIONS.iter().find_map(|(prefix, action)| {
+        Some(FrameLock {
+            action: *action,
+            lock: parse_lock_ref(line.strip_prefix(prefix)?)?,
+        })
+    })
+}
+
+/// Parse an `at <method>(<location>)` frame line.
+fn parse_frame(line: &str) -> Result<StackFrame> {
+    let (method, location) = line
+        .strip_suffix(')')
+        .and_then(|s| s.split_once('('))
+        .ok_or(Error::ResponseParse("invalid stack frame"))?;
+
+    let (module, location) = match location.rsplit_once('/') {
+        Some((module, location)) => (Some(module.to_string()), location),
+        None => (None, location),
+    };
+
+    Ok(StackFrame {
+        method: method.to_string(),
+        module,
+        location: location.to_string(),
+        locks: vec![],
+    })
+}
+
+impl ThreadDump {
+    /// Parse a thread dump.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut res = Self::default();
+        let mut in_synchronizers = false;
+
+        for line in s.lines() {
+            let trimmed = line.trim();
+
+            if let Some(vm) = line
+                .strip_prefix("Full thread dump ")
+                .and_then(|s| s.strip_suffix(':'))
+            {
+                res.vm = Some(vm.to_string());
+            } else if res.vm.is_none() && res.timestamp.is_none() && is_timestamp(trimmed) {
+                res.timestamp = Some(trimmed.to_string());
+            } else if line.starts_with('"') {
+                res.threads.push(parse_thread_header(line)?);
+                in_synchronizers = false;
+            } else if line.starts_with("Found ") {
+                // The deadlock report repeats thread names and stacks.
+                break;
+            } else if let Some(thread) = res.threads.last_mut() {
+                if let Some(state) = trimmed.strip_prefix("java.lang.Thread.State: ") {
+                    let (state, detail) = match state.split_once(" (") {
+                        Some((state, detail)) => {
+                            (state, Some(detail.trim_end_matches(')').to_string()))
+                        }
+                        None => (state, None),
+                    };
+                    thread.state = Some(ThreadState::from(state));
+                    thread.state_detail = detail;
+                } else if let Some(frame) = trimmed.strip_prefix("at ") {
+                    thread.frames.push(parse_frame(frame)?);
+                } else if trimmed == "Locked ownable synchronizers:" {
+                    in_synchronizers = true;
+                } else if let Some(lock) = trimmed.strip_prefix("- ") {
+                    if in_synchronizers {
+                        thread.ownable_synchronizers.extend(parse_lock_ref(lock));
+                    } else if let Some(frame) = thread.frames.last_mut() {
+                        frame.locks.extend(parse_frame_lock(lock));
+                    }
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Find the thread holding a lock.
+    pub fn owner_of(&self, address: u64) -> Option<&JavaThread> {
+        self.threads
+            .iter()
+            .find(|t| t.held_locks().any(|l| l.address == address))
+    }
+
+    /// Find deadlocked threads.
+    ///
+    /// Each returned cycle contains threads blocked on a lock held by the
+    /// next thread, with the last thread blocked on a lock held by the first.
+    pub fn deadlocks(&self) -> Vec<Vec<&JavaThread>> {
+        let owners = self
+            .threads
+            .iter()
+            .enumerate()
+            .flat_map(|(i, t)| t.held_locks().map(move |l| (l.address, i)))
+            .collect::<HashMap<_, _>>();
+
+        // Every thread is blocked on at most 1 lock, so each thread has at
+        // most 1 outgoing edge and each thread belongs to at most 1 cycle.
+        let next = self
+            .threads
+            .iter()
+            .map(|t| {
+                t.blocked_on()
+                    .and_then(|l| owners.get(&l.lock.address).copied())
+            })
+            .collect::<Vec<_>>();
+
+        let mut visited = vec![false; self.threads.len()];
+        let mut res = vec![];
+
+        for start in 0..self.threads.len() {
+            let mut path = vec![];
+            let mut current = Some(start);
+
+            while let Some(i) = current {
+                if visited[i] {
+                    // A cycle exists if we returned to a thread on this path.
+                    if let Some(pos) = path.iter().position(|p| *p == i) {
+                        res.push(path[pos..].iter().map(|i| &self.threads[*i]).collect());
+                    }
+                    break;
+                }
+
+                visited[i] = true;
+                path.push(i);
+                current = next[i];
+            }
+        }
+
+        res
+    }
+
+    /// Group threads having the same state and stack.
+    ///
+    /// Frames are compared ignoring their locks. Groups are sorted by
+    /// descending number of threads.
+    pub fn group_by_stack(&self) -> Vec<StackGroup<'_>> {
+        type Key<'a> = (
+            Option<&'a ThreadState>,
+            Vec<(&'a str, Option<&'a str>, &'a str)>,
+        );
+
+        let mut groups: Vec<StackGroup> = vec![];
+        let mut index = HashMap::<Key, usize>::new();
+
+        for thread in &self.threads {
+            let key = (
+                thread.state.as_ref(),
+                thread
+                    .frames
+                    .iter()
+                    .map(|f| (f.method.as_str(), f.module.as_deref(), f.location.as_str()))
+                    .collect(),
+            );
+
+            match index.get(&key) {
+                Some(i) => groups[*i].threads.push(thread),
+                None => {
+                    index.insert(key, groups.len());
+                    groups.push(StackGroup {
+                        state: thread.state.as_ref(),
+                        frames: &thread.frames,
+                        threads: vec![thread],
+                    });
+                }
+            }
+        }
+
+        groups.sort_by_key(|g| std::cmp::Reverse(g.threads.len()));
+
+        groups
+    }
+}
+
+/// Whether a line is a `YYYY-MM-DD HH:MM:SS` timestamp.
+fn is_timestamp(s: &str) -> bool {
+    s.len() == 19
+        && s.char_indices().all(|(i, c)| match i {
+            4 | 7 => c == '-',
+            10 => c == ' ',
+            13 | 16 => c == ':',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DUMP: &str = "2026-10-17 01:22:45
+Full thread dump OpenJDK 64-Bit Server VM (17.0.15+6-Debian-1deb12u1 mixed mode, sharing):
+
+\"mon-1\" #12 prio=5 os_prio=0 cpu=0.32ms elapsed=3.77s tid=0x00007f2f740ad3b0 nid=0x75e6 waiting for monitor entry  [0x00007f2effdfd000]
+   java.lang.Thread.State: BLOCKED (on object monitor)
+\tat Dead.lambda$main$0(Dead.java:7)
+\t- waiting to lock <0x00000000a2390e20> (a java.lang.Object)
+\t- locked <0x00000000a2390e10> (a java.lang.Object)
+\tat java.lang.Thread.run(java.base@17.0.15/Thread.java:840)
+
+   Locked ownable synchronizers:
+\t- None
+
+\"mon-2\" #13 prio=5 os_prio=0 cpu=0.38ms elapsed=3.76s tid=0x00007f2f740ae8a0 nid=0x75e7 waiting for monitor entry  [0x00007f2effcfd000]
+   java.lang.Thread.State: BLOCKED (on object monitor)
+\tat Dead.lambda$main$1(Dead.java:8)
+\t- waiting to lock <0x00000000a2390e10> (a java.lang.Object)
+\t- locked <0x00000000a2390e20> (a java.lang.Object)
+\tat java.lang.Thread.run(java.base@17.0.15/Thread.java:840)
+
+   Locked ownable synchronizers:
+\t- None
+
+\"waiter\" #16 daemon prio=5 os_prio=0 cpu=0.15ms elapsed=3.75s tid=0x00007f2f740b2340 nid=0x75ea in Object.wait()  [0x00007f2eff9fd000]
+   java.lang.Thread.State: WAITING (on object monitor)
+\tat java.lang.Object.wait(java.base@17.0.15/Native Method)
+\t- waiting on <0x00000000a2390e30> (a java.lang.Object)
+\tat Dead.lambda$main$4(Dead.java:11)
+\t- locked <0x00000000a2390e30> (a java.lang.Object)
+
+   Locked ownable synchronizers:
+\t- <0x00000000a2391228> (a java.util.concurrent.locks.ReentrantLock$NonfairSync)
+
+\"VM Thread\" os_prio=0 cpu=1.07ms elapsed=3.91s tid=0x00007f2f7404d310 nid=0x75da runnable
+
+JNI global refs: 4, weak refs: 0
+
+Found one Java-level deadlock:
+=============================
+\"mon-1\":
+  waiting to lock monitor 0x00007f2f08001d00 (object 0x00000000a2390e20, a java.lang.Object),
+  which is held by \"mon-2\"
+";
+
+    #[test]
+    fn parse() -> Result<()> {
+        let dump = ThreadDump::parse(DUMP)?;
+
+        assert_eq!(dump.timestamp.as_deref(), Some("2026-10-17 01:22:45"));
+        assert_eq!(dump.threads.len(), 4);
+
+        let thread = &dump.threads[0];
+        assert_eq!(thread.name, "mon-1");
+        assert_eq!(thread.id, Some(12));
+        assert_eq!(thread.nid, Some(0x75e6));
+        assert_eq!(thread.description, "waiting for monitor entry");
+        assert_eq!(thread.state, Some(ThreadState::Blocked));
+        assert_eq!(
+            thread.frames[1].module.as_deref(),
+            Some("java.base@17.0.15")
+        );
+        assert_eq!(thread.blocked_on().unwrap().lock.address, 0xa2390e20);
+
+        let waiter = &dump.threads[2];
+        assert!(waiter.daemon);
+        assert_eq!(waiter.held_monitors().count(), 0);
+        assert_eq!(waiter.ownable_synchronizers[0].address, 0xa2391228);
+        assert!(waiter.blocked_on().is_none());
+
+        let vm = &dump.threads[3];
+        assert_eq!((vm.id, vm.priority, vm.state.as_ref()), (None, None, None));
+
+        let deadlocks = dump.deadlocks();
+        assert_eq!(deadlocks.len(), 1);
+        assert_eq!(
+            deadlocks[0]
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["mon-1", "mon-2"]
+        );
+        assert_eq!(dump.owner_of(0xa2390e10).unwrap().name, "mon-1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_jdk21() -> Result<()> {
+        let dump = ThreadDump::parse(
+            "2026-10-17 01:30:12
+Full thread dump OpenJDK 64-Bit Server VM (21.0.8+9-Debian-1 mixed mode, sharing):
+
+\"Reference Handler\" #9 [8940] daemon prio=10 os_prio=0 cpu=0.21ms elapsed=2.41s tid=0x00007f8c9c0e7e80 nid=8940 waiting on condition  [0x00007f8c7c1fe000]
+   java.lang.Thread.State: RUNNABLE
+\tat java.lang.ref.Reference.waitForReferencePendingList(java.base@21.0.8/Native Method)
+
+\"VM Thread\" os_prio=0 cpu=2.10ms elapsed=2.42s tid=0x00007f8c9c0d5c60 nid=8938 runnable
+",
+        )?;
+
+        let thread = &dump.threads[0];
+        assert_eq!(thread.name, "Reference Handler");
+        assert_eq!(thread.id, Some(9));
+        assert!(thread.daemon);
+        assert_eq!(thread.priority, Some(10));
+        assert_eq!(thread.os_priority, Some(0));
+        assert_eq!(thread.cpu.as_deref(), Some("0.21ms"));
+        assert_eq!(thread.elapsed.as_deref(), Some("2.41s"));
+        assert_eq!(thread.tid, Some(0x7f8c9c0e7e80));
+        assert_eq!(thread.nid, Some(8940));
+        assert_eq!(thread.description, "waiting on condition");
+        assert_eq!(thread.state, Some(ThreadState::Runnable));
+
+        assert_eq!(dump.threads[1].nid, Some(8938));
+
+        Ok(())
+    }
+}
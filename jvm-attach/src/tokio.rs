@@ -0,0 +1,596 @@
+// Copyright 2023 Gregory Szorc.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Async attach client built on tokio.
+//!
+//! These types mirror [crate::UnixSocketRequest] and
+//! [crate::UnixSocketConnection]. Waiting for a JVM to listen and reading
+//! command output don't block threads, so many JVMs can be attached to
+//! concurrently.
+//!
+//! Successful commands yield a [UnixStream] from which output can be read
+//! as it is produced:
+//!
+//! ```no_run
+//! # async fn example() -> jvm_attach::Result<()> {
+//! use jvm_attach::tokio::UnixSocketRequest;
+//! use std::time::Duration;
+//!
+//! let connection = UnixSocketRequest::new(1234)
+//!     .await?
+//!     .try_connect(Duration::from_secs(5))
+//!     .await?;
+//!
+//! let mut lines = connection
+//!     .send_command_lines("jcmd", vec!["Thread.print"])
+//!     .await?;
+//!
+//! while let Some(line) = lines.next_line().await? {
+//!     println!("{}", line);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Setting up a request, signalling the JVM, checking for its socket and
+//! connecting with switched credentials or namespaces involve blocking system
+//! calls. These run on tokio's blocking thread pool.
+
+use crate::{
+    command::DiagnosticCommand, encode_request, failure_response, fits_protocol_version_1,
+    parse_protocol_version, parse_status, AttachOptions, Error, Result, SignalSchedule,
+    SocketConnector, PROTOCOL_VERSION_1, PROTOCOL_VERSION_2,
+};
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Lines},
+    net::UnixStream,
+};
+
+#[cfg(target_os = "linux")]
+use {crate::inotify::DirectoryWatch, tokio::io::unix::AsyncFd};
+
+/// Response to a command invocation.
+pub type CommandResponse = crate::CommandResponse<UnixStream>;
+
+/// Run a blocking operation on tokio's blocking thread pool.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?
+}
+
+/// Connect to a JVM's socket.
+async fn connect(connector: &SocketConnector) -> Result<UnixStream> {
+    if connector.credentials.is_none()
+        && !connector.options.enter_mount_namespace
+        && !connector.options.enter_net_namespace
+    {
+        return UnixStream::connect(&connector.socket_path)
+            .await
+            .map_err(crate::connect_error);
+    }
+
+    let connector = connector.clone();
+    let sock = blocking(move || connector.connect()).await?;
+    sock.set_nonblocking(true)?;
+
+    Ok(UnixStream::from_std(sock)?)
+}
+
+/// Async counterpart of [crate::UnixSocketRequest].
+#[derive(Debug)]
+pub struct UnixSocketRequest {
+    /// Only `None` while a blocking operation on it is in progress.
+    inner: Option<crate::UnixSocketRequest>,
+}
+
+impl UnixSocketRequest {
+    /// Request attachment to a PID.
+    ///
+    /// See [crate::UnixSocketRequest::new].
+    pub async fn new(pid: i32) -> Result<Self> {
+        Self::with_options(pid, AttachOptions::default()).await
+    }
+
+    /// Request attachment to a PID with custom options.
+    pub async fn with_options(pid: i32, options: AttachOptions) -> Result<Self> {
+        let inner = blocking(move || crate::UnixSocketRequest::with_options(pid, options)).await?;
+
+        Ok(Self { inner: Some(inner) })
+    }
+
+    fn inner(&self) -> &crate::UnixSocketRequest {
+        self.inner
+            .as_ref()
+            .expect("no blocking operation in progress")
+    }
+
+    /// Run a blocking operation on the request on tokio's blocking thread pool.
+    async fn blocking_inner<T: Send + 'static>(
+        &mut self,
+        f: impl FnOnce(&mut crate::UnixSocketRequest) -> T + Send + 'static,
+    ) -> Result<T> {
+        let mut inner = self
+            .inner
+            .take()
+            .expect("no blocking operation in progress");
+
+        let (inner, res) = blocking(move || {
+            let res = f(&mut inner);
+            Ok((inner, res))
+        })
+        .await?;
+        self.inner = Some(inner);
+
+        Ok(res)
+    }
+
+    /// The PID of the JVM process, as seen from our PID namespace.
+    pub fn pid(&self) -> i32 {
+        self.inner().pid()
+    }
+
+    /// The PID of the JVM process, as seen from the JVM's PID namespace.
+    pub fn namespace_pid(&self) -> i32 {
+        self.inner().namespace_pid()
+    }
+
+    /// Attempt to connect to the JVM command and control socket.
+    ///
+    /// See [crate::UnixSocketRequest::try_connect].
+    pub async fn try_connect(mut self, max_wait_time: Duration) -> Result<UnixSocketConnection> {
+        let start = Instant::now();
+        let max_time = start + max_wait_time;
+
+        // Watch before checking for the socket so its creation isn't missed.
+        #[cfg(target_os = "linux")]
+        let watch = self
+            .blocking_inner(|inner| {
+                inner
+                    .socket_path
+                    .parent()
+                    .and_then(|dir| DirectoryWatch::new(dir).ok())
+            })
+            .await?
+            .and_then(|watch| AsyncFd::new(watch).ok());
+
+        let mut schedule = SignalSchedule::new(start);
+
+        loop {
+            let now = Instant::now();
+            // Why connecting to an existing socket failed.
+            let mut connect_error = None;
+
+            let metadata = self
+                .blocking_inner(|inner| std::fs::metadata(&inner.socket_path).ok())
+                .await?;
+
+            if let Some(metadata) = metadata {
+                // Make sure we can connect, and to the right JVM.
+                let pid = self.inner().pid;
+
+                match connect(&self.inner().connector).await {
+                    Ok(sock) if sock.peer_cred()?.pid() == Some(pid) => {
+                        self.blocking_inner(|inner| inner.remove_attach_pid_file())
+                            .await?;
+
+                        return Ok(UnixSocketConnection {
+                            pid,
+                            connector: self.inner().connector.clone(),
+                            protocol_version: AtomicU32::new(0),
+                            attach_duration: start.elapsed(),
+                        });
+                    }
+                    // Our JVM replaces the socket when signalled.
+                    Ok(_) => {}
+                    Err(Error::NoSocket) => {
+                        self.blocking_inner(move |inner| inner.remove_stale_socket(&metadata))
+                            .await?;
+                        connect_error = Some(Error::NoSocket);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if now >= max_time {
+                return Err(connect_error.unwrap_or(Error::NoSignalResponse(max_wait_time)));
+            }
+
+            if schedule.due(now) {
+                self.blocking_inner(|inner| inner.signal()).await??;
+            }
+
+            // Wait for the socket to appear until it is time to signal again.
+            let timeout = schedule
+                .next()
+                .min(max_time)
+                .saturating_duration_since(Instant::now());
+
+            #[cfg(target_os = "linux")]
+            if let Some(watch) = &watch {
+                if let Ok(guard) = tokio::time::timeout(timeout, watch.readable()).await {
+                    let mut guard = guard?;
+                    guard.get_inner().drain()?;
+                    guard.clear_ready();
+                }
+                continue;
+            }
+
+            tokio::time::sleep(timeout.min(SignalSchedule::POLL_INTERVAL)).await;
+        }
+    }
+}
+
+impl Drop for UnixSocketRequest {
+    fn drop(&mut self) {
+        // Dropping the request removes the `.attach_pid` file we created.
+        if let Some(inner) = self.inner.take() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn_blocking(move || drop(inner));
+                }
+                Err(_) => drop(inner),
+            }
+        }
+    }
+}
+
+/// Async counterpart of [crate::UnixSocketConnection].
+#[derive(Debug)]
+pub struct UnixSocketConnection {
+    pid: i32,
+    connector: SocketConnector,
+    /// The negotiated protocol version. 0 if not yet negotiated.
+    protocol_version: AtomicU32,
+    attach_duration: Duration,
+}
+
+impl UnixSocketConnection {
+    /// The PID of the JVM process, as seen from our PID namespace.
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// How long attaching took.
+    pub fn attach_duration(&self) -> Duration {
+        self.attach_duration
+    }
+
+    /// The attach protocol version supported by the JVM.
+    ///
+    /// See [crate::UnixSocketConnection::protocol_version].
+    pub async fn protocol_version(&self) -> Result<u32> {
+        let version = self.protocol_version.load(Ordering::Relaxed);
+        if version != 0 {
+            return Ok(version);
+        }
+
+        let version = match self
+            .send_request(PROTOCOL_VERSION_1, "getversion", &[])
+            .await
+        {
+            Ok(CommandResponse::Success(mut sock)) => {
+                let mut res = String::new();
+                sock.read_to_string(&mut res).await?;
+
+                parse_protocol_version(&res)?
+            }
+            Ok(CommandResponse::Error(_)) | Err(Error::UnknownCommand(_)) => PROTOCOL_VERSION_1,
+            Err(e) => return Err(e),
+        };
+
+        self.protocol_version.store(version, Ordering::Relaxed);
+
+        Ok(version)
+    }
+
+    /// Sends a command to the socket.
+    ///
+    /// See [crate::UnixSocketConnection::send_command].
+    pub async fn send_command(&self, command: &str, args: Vec<&str>) -> Result<CommandResponse> {
+        let version = if fits_protocol_version_1(&args) {
+            PROTOCOL_VERSION_1
+        } else {
+            match self.protocol_version().await? {
+                PROTOCOL_VERSION_1 => return Err(Error::ProtocolLimit(PROTOCOL_VERSION_1)),
+                _ => PROTOCOL_VERSION_2,
+            }
+        };
+
+        self.send_request(version, command, &args).await
+    }
+
+    /// Send a request and decode the status of the response.
+    async fn send_request(
+        &self,
+        version: u32,
+        command: &str,
+        args: &[&str],
+    ) -> Result<CommandResponse> {
+        let request = encode_request(version, command, args)?;
+
+        let mut sock = connect(&self.connector).await?;
+
+        sock.write_all(&request).await?;
+
+        // The JVM closes connections from clients it doesn't accept.
+        let status = match Self::read_int(&mut sock).await {
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+                return Err(Error::ConnectionRejected);
+            }
+            res => res?,
+        };
+
+        if status == 0 {
+            return Ok(CommandResponse::Success(sock));
+        }
+
+        let mut res = String::new();
+        sock.read_to_string(&mut res).await?;
+
+        failure_response(version, command, status, res)
+    }
+
+    /// Send a command and read its output as a Vec<u8>.
+    pub async fn send_command_bytes(&self, command: &str, args: Vec<&str>) -> Result<Vec<u8>> {
+        match self.send_command(command, args).await? {
+            CommandResponse::Success(mut sock) => {
+                let mut res = vec![];
+                sock.read_to_end(&mut res).await?;
+
+                Ok(res)
+            }
+            CommandResponse::Error(reason) => Err(Error::CommandError(reason)),
+        }
+    }
+
+    /// Send a command and read its output as a string.
+    pub async fn send_command_string(&self, command: &str, args: Vec<&str>) -> Result<String> {
+        match self.send_command(command, args).await? {
+            CommandResponse::Success(mut sock) => {
+                let mut res = String::new();
+                sock.read_to_string(&mut res).await?;
+
+                Ok(res)
+            }
+            CommandResponse::Error(reason) => Err(Error::CommandError(reason)),
+        }
+    }
+
+    /// Send a command and stream its output line by line.
+    pub async fn send_command_lines(
+        &self,
+        command: &str,
+        args: Vec<&str>,
+    ) -> Result<Lines<BufReader<UnixStream>>> {
+        match self.send_command(command, args).await? {
+            CommandResponse::Success(sock) => Ok(BufReader::new(sock).lines()),
+            CommandResponse::Error(reason) => Err(Error::CommandError(reason)),
+        }
+    }
+
+    /// Execute a typed diagnostic command and parse its output.
+    pub async fn execute<C: DiagnosticCommand>(&self, command: &C) -> Result<C::Response> {
+        let line = command.command_line()?;
+        let output = self.send_command_string("jcmd", vec![&line]).await?;
+
+        command.parse_response(output)
+    }
+
+    async fn read_int(sock: &mut UnixStream) -> Result<i32> {
+        let mut result = vec![];
+
+        loop {
+            match sock.read_u8().await {
+                Ok(b'\n') => break,
+                Ok(c) => result.push(c),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        parse_status(&result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        std::{future::Future, path::Path},
+        tokio::net::UnixListener,
+    };
+
+    fn run<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    fn connection(socket_path: &Path) -> UnixSocketConnection {
+        UnixSocketConnection {
+            pid: 42,
+            connector: SocketConnector {
+                pid: 42,
+                socket_path: socket_path.to_path_buf(),
+                namespace_socket_path: socket_path.to_path_buf(),
+                credentials: None,
+                options: AttachOptions::default(),
+            },
+            protocol_version: AtomicU32::new(0),
+            attach_duration: Duration::ZERO,
+        }
+    }
+
+    /// Answer a single request with a canned response.
+    ///
+    /// Returns the request that was received.
+    async fn respond(listener: UnixListener, response: &'static [u8]) -> Vec<u8> {
+        let (mut sock, _) = listener.accept().await.unwrap();
+
+        let mut request = vec![];
+        while !request.ends_with(b"\0\0\0") {
+            request.push(sock.read_u8().await.unwrap());
+        }
+
+        sock.write_all(response).await.unwrap();
+
+        request
+    }
+
+    /// Send a `jcmd` request to a listener answering with `response`.
+    ///
+    /// The output of successful commands is read into a string.
+    fn send(response: &'static [u8]) -> Result<crate::CommandResponse<String>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".java_pid42");
+
+        run(async {
+            let listener = UnixListener::bind(&path)?;
+            let server = tokio::spawn(respond(listener, response));
+
+            let res = connection(&path)
+                .send_request(PROTOCOL_VERSION_1, "jcmd", &["VM.version"])
+                .await;
+
+            assert_eq!(server.await.unwrap(), b"1\0jcmd\0VM.version\0\0\0");
+
+            match res? {
+                CommandResponse::Success(mut sock) => {
+                    let mut output = String::new();
+                    sock.read_to_string(&mut output).await?;
+
+                    Ok(crate::CommandResponse::Success(output))
+                }
+                CommandResponse::Error(e) => Ok(crate::CommandResponse::Error(e)),
+            }
+        })
+    }
+
+    #[test]
+    fn read_int() -> Result<()> {
+        run(async {
+            let (mut ours, mut theirs) = UnixStream::pair()?;
+
+            theirs.write_all(b"42\n-1\nabc\n\xff\n13").await?;
+            drop(theirs);
+
+            assert_eq!(UnixSocketConnection::read_int(&mut ours).await?, 42);
+            assert_eq!(UnixSocketConnection::read_int(&mut ours).await?, -1);
+            assert!(matches!(
+                UnixSocketConnection::read_int(&mut ours).await,
+                Err(Error::IntegerReadNotInteger)
+            ));
+            assert!(matches!(
+                UnixSocketConnection::read_int(&mut ours).await,
+                Err(Error::IntegerReadBadString)
+            ));
+            // The end of the stream terminates the value as well.
+            assert_eq!(UnixSocketConnection::read_int(&mut ours).await?, 13);
+            assert!(matches!(
+                UnixSocketConnection::read_int(&mut ours).await,
+                Err(Error::ConnectionRejected)
+            ));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn status() -> Result<()> {
+        assert!(matches!(
+            send(b"0\nOpenJDK 64-Bit Server VM\n")?,
+            crate::CommandResponse::Success(output) if output == "OpenJDK 64-Bit Server VM\n"
+        ));
+        assert!(matches!(
+            send(b"1\nSomething went wrong\n")?,
+            crate::CommandResponse::Error(e) if e == "Something went wrong\n"
+        ));
+
+        assert!(matches!(
+            send(b"-1\nOperation jcmd not recognized!\n"),
+            Err(Error::UnknownCommand(command)) if command == "jcmd"
+        ));
+        assert!(matches!(
+            send(b"101\n"),
+            Err(Error::UnsupportedProtocolVersion(PROTOCOL_VERSION_1))
+        ));
+        assert!(matches!(send(b""), Err(Error::ConnectionRejected)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejected() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(".java_pid42");
+
+        run(async {
+            let listener = UnixListener::bind(&path)?;
+
+            // Closing with the request unread resets the connection.
+            let server = tokio::spawn(async move {
+                let (sock, _) = listener.accept().await.unwrap();
+                sock.readable().await.unwrap();
+            });
+
+            let res = connection(&path)
+                .send_request(PROTOCOL_VERSION_1, "jcmd", &["VM.version"])
+                .await;
+            server.await.unwrap();
+
+            assert!(matches!(res, Err(Error::ConnectionRejected)));
+
+            // Nobody listens once the listener is gone.
+            assert!(matches!(
+                connection(&path)
+                    .send_request(PROTOCOL_VERSION_1, "jcmd", &["VM.version"])
+                    .await,
+                Err(Error::NoSocket)
+            ));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn stale_socket() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket_path = dir.path().join(".java_pid42");
+
+        // A socket nobody listens on.
+        drop(std::os::unix::net::UnixListener::bind(&socket_path)?);
+
+        let request = UnixSocketRequest {
+            inner: Some(crate::UnixSocketRequest {
+                pid: 42,
+                ns_pid: 42,
+                attach_pid_path: dir.path().join(".attach_pid42"),
+                attach_pid_file: None,
+                socket_path: socket_path.clone(),
+                connector: connection(&socket_path).connector,
+                checked: true,
+            }),
+        };
+
+        // Connecting failed rather than the JVM not answering.
+        assert!(matches!(
+            run(request.try_connect(Duration::ZERO)),
+            Err(Error::NoSocket)
+        ));
+        assert!(!socket_path.exists());
+
+        Ok(())
+    }
+}